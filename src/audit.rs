@@ -0,0 +1,159 @@
+use crate::{cache, git, json};
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(clap::Args)]
+pub struct AuditArgs {
+    /// Append a JSON line to this file for every ref or branch upstream changed, with its old and
+    /// new value; a change is refused when the line cannot be written [config: audit_log]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "GIT_DEFAULT_BRANCH_AUDIT_LOG",
+        global = true
+    )]
+    pub audit_log: Option<PathBuf>,
+}
+
+/// Where changes are recorded, set once at startup.
+static PATH: OnceLock<Option<PathBuf>> = OnceLock::new();
+
+/// Records changes in the file at `path` from now on, if one is given.
+pub fn init(path: Option<PathBuf>) {
+    let _ = PATH.set(path);
+}
+
+/// A change to a ref or a setting, as recorded.
+pub struct Change<'a> {
+    /// What was done, like `set-remote-head`, `rename-branch` or `set-upstream`.
+    pub action: &'a str,
+    /// The ref changed, or the config key of a setting.
+    pub name: &'a str,
+    /// The value before, `None` when the ref did not exist.
+    pub old: Option<&'a str>,
+    /// The value after, `None` when the ref was deleted.
+    pub new: Option<&'a str>,
+}
+
+/// Makes `change` to `repo` by running `apply`, recording it in the audit log if there is one.
+/// The entry is written before the change is made, so a change that cannot be recorded is not
+/// made; a change that then fails is recorded again with the error.
+pub fn record(
+    repo: &gix::Repository,
+    change: &Change,
+    apply: impl FnOnce() -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    record_to(PATH.get().and_then(Option::as_deref), repo, change, apply)
+}
+
+fn record_to(
+    path: Option<&Path>,
+    repo: &gix::Repository,
+    change: &Change,
+    apply: impl FnOnce() -> Result<(), Box<dyn std::error::Error>>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(path) = path else {
+        return apply();
+    };
+    let mut file =
+        open(path).map_err(|e| format!("Cannot open the audit log {}: {}", path.display(), e))?;
+    let mut write = |error: Option<&str>| {
+        writeln!(file, "{}", line(repo, change, error))
+            .and_then(|()| file.sync_all())
+            .map_err(|e| format!("Cannot write to the audit log {}: {}", path.display(), e))
+    };
+    write(None)?;
+    apply().inspect_err(|e| {
+        let _ = write(Some(&e.to_string()));
+    })
+}
+
+#[cfg(unix)]
+fn open(path: &Path) -> std::io::Result<File> {
+    use std::os::unix::fs::OpenOptionsExt;
+    OpenOptions::new()
+        .append(true)
+        .create(true)
+        .mode(0o600)
+        .open(path)
+}
+
+#[cfg(not(unix))]
+fn open(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().append(true).create(true).open(path)
+}
+
+/// Describes `change` as `{"time", "user", "repository", "action", "ref", "old", "new", "error"}`,
+/// where `error` is why the change just recorded failed, or `null`.
+fn line(repo: &gix::Repository, change: &Change, error: Option<&str>) -> String {
+    let optional = |value: Option<&str>| value.map_or("null".to_string(), json::quote);
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .ok();
+    format!(
+        "{{\"time\":{},\"user\":{},\"repository\":{},\"action\":{},\"ref\":{},\"old\":{},\"new\":{},\"error\":{}}}",
+        cache::now(),
+        optional(user.as_deref()),
+        json::quote(&git::run_dir(repo).display().to_string()),
+        json::quote(change.action),
+        json::quote(change.name),
+        optional(change.old),
+        optional(change.new),
+        optional(error),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::init_repo;
+    use std::fs;
+
+    #[test]
+    fn test_record_to() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        let repo = git::discover(tmp.path()).unwrap();
+        let log = tmp.path().join("audit.jsonl");
+        let change = Change {
+            action: "rename-branch",
+            name: "refs/heads/master",
+            old: Some("refs/heads/master"),
+            new: Some("refs/heads/main"),
+        };
+
+        record_to(Some(&log), &repo, &change, || Ok(())).unwrap();
+        assert!(record_to(Some(&log), &repo, &change, || Err("failed".into())).is_err());
+        record_to(Some(&log), &repo, &change, || Ok(())).unwrap();
+        let content = fs::read_to_string(&log).unwrap();
+        let entries = content
+            .lines()
+            .map(|line| json::parse(line).unwrap())
+            .collect::<Vec<_>>();
+        // The failed change is recorded before it is attempted, then again with the error.
+        let errors = entries
+            .iter()
+            .map(|entry| entry.get("error").unwrap().as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(errors, vec![None, None, Some("failed"), None]);
+        let entry = &entries[0];
+        assert_eq!(entry.get("action").unwrap().as_str(), Some("rename-branch"));
+        assert_eq!(
+            entry.get("old").unwrap().as_str(),
+            Some("refs/heads/master")
+        );
+        assert_eq!(entry.get("new").unwrap().as_str(), Some("refs/heads/main"));
+
+        // A change that cannot be recorded is not made.
+        let mut applied = false;
+        let unwritable = tmp.path().join("missing/audit.jsonl");
+        let result = record_to(Some(&unwritable), &repo, &change, || {
+            applied = true;
+            Ok(())
+        });
+        assert!(result.is_err());
+        assert!(!applied);
+    }
+}
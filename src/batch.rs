@@ -0,0 +1,221 @@
+use crate::resolve;
+use crate::scan::{self, Format};
+use std::collections::HashMap;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+
+#[derive(clap::Args)]
+pub struct BatchArgs {
+    /// Number of repositories to resolve concurrently
+    #[arg(
+        short,
+        long,
+        default_value_t = 1,
+        env = "GIT_DEFAULT_BRANCH_JOBS",
+        global = true
+    )]
+    pub jobs: usize,
+
+    /// Only print how many repositories use each default branch
+    #[arg(long, global = true)]
+    pub summary_only: bool,
+
+    /// Print the remote each repository is resolved against with its branch, as
+    /// `label<TAB>remote<TAB>branch`, or `label<TAB>remote/branch` with `slash`
+    #[arg(
+        long,
+        value_enum,
+        value_name = "STYLE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "tab",
+        global = true
+    )]
+    pub with_remote: Option<RemoteStyle>,
+}
+
+/// How `--with-remote` combines a remote and its default branch.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum RemoteStyle {
+    /// `origin<TAB>main`
+    Tab,
+    /// `origin/main`
+    Slash,
+}
+
+impl RemoteStyle {
+    pub fn join(self, remote: &str, branch: &str) -> String {
+        match self {
+            RemoteStyle::Tab => format!("{}\t{}", remote, branch),
+            RemoteStyle::Slash => format!("{}/{}", remote, branch),
+        }
+    }
+}
+
+/// Prints `label<TAB>branch` for each repository, with the remote as `--with-remote` asks,
+/// reporting failures on stderr without stopping, followed by a summary on stderr. With `json`,
+/// prints one JSON array instead, with each repository's status, as `scan --format json` does.
+pub fn print(
+    repos: &[(String, PathBuf)],
+    remote: &str,
+    resolve: &resolve::Options,
+    options: &BatchArgs,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let results = map_parallel(repos, options.jobs, |(_, path)| {
+        resolve::run(path, remote, resolve).map_err(|e| e.to_string())
+    });
+    if !options.summary_only {
+        let remotes = repos
+            .iter()
+            .map(|(_, path)| {
+                options
+                    .with_remote
+                    .map(|_| resolve::primary_remote(path, remote, resolve))
+            })
+            .collect::<Vec<_>>();
+        if json {
+            let entries = repos
+                .iter()
+                .zip(&results)
+                .zip(remotes)
+                .map(|(((label, _), result), remote)| scan::Entry {
+                    name: label.clone(),
+                    result: result.clone(),
+                    remote,
+                })
+                .collect::<Vec<_>>();
+            scan::write_entries(&mut io::stdout().lock(), "path", &entries, Format::Json)?;
+        } else {
+            for (((label, _), result), remote) in repos.iter().zip(&results).zip(&remotes) {
+                match (result, options.with_remote, remote) {
+                    (Ok(branch), Some(style), Some(remote)) => {
+                        println!("{}\t{}", label, style.join(remote, branch))
+                    }
+                    (Ok(branch), _, _) => println!("{}\t{}", label, branch),
+                    (Err(e), _, _) => eprintln!("{}: {}", label, e),
+                }
+            }
+        }
+    }
+    print_summary(results.iter().map(|r| r.as_deref().ok()), options);
+
+    let failures = results.iter().filter(|r| r.is_err()).count();
+    if failures > 0 {
+        return Err(format!("Failed to resolve {} repositories", failures).into());
+    }
+    Ok(())
+}
+
+/// Prints the summary to stdout with `--summary-only`, or to stderr so it does not mix with
+/// machine-readable output otherwise.
+pub fn print_summary<'a>(branches: impl IntoIterator<Item = Option<&'a str>>, options: &BatchArgs) {
+    let summary = summary(branches);
+    if options.summary_only {
+        println!("{}", summary);
+    } else {
+        eprintln!("{}", summary);
+    }
+}
+
+/// Formats counts like `212 main, 63 master, 4 errors`, most common branch first. `None` is a failure.
+pub fn summary<'a>(branches: impl IntoIterator<Item = Option<&'a str>>) -> String {
+    let mut counts = HashMap::new();
+    let mut errors = 0;
+    for branch in branches {
+        match branch {
+            Some(branch) => *counts.entry(branch).or_insert(0) += 1,
+            None => errors += 1,
+        }
+    }
+    let mut counts = counts.into_iter().collect::<Vec<_>>();
+    counts.sort_by(|(a, m), (b, n)| n.cmp(m).then(a.cmp(b)));
+
+    let mut parts = counts
+        .into_iter()
+        .map(|(branch, count)| format!("{} {}", count, branch))
+        .collect::<Vec<_>>();
+    match errors {
+        0 => {}
+        1 => parts.push("1 error".to_string()),
+        n => parts.push(format!("{} errors", n)),
+    }
+    if parts.is_empty() {
+        return "No repositories".to_string();
+    }
+    parts.join(", ")
+}
+
+/// Applies `f` to every item on up to `jobs` threads, returning the results in input order.
+pub fn map_parallel<T: Sync, R: Send>(
+    items: &[T],
+    jobs: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let jobs = jobs.clamp(1, items.len().max(1));
+    if jobs == 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let mut results = thread::scope(|scope| {
+        let workers = (0..jobs)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        let Some(item) = items.get(i) else { break };
+                        done.push((i, f(item)));
+                    }
+                    done
+                })
+            })
+            .collect::<Vec<_>>();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("worker thread panicked"))
+            .collect::<Vec<_>>()
+    });
+    results.sort_by_key(|(i, _)| *i);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let branches = [
+            Some("master"),
+            Some("main"),
+            None,
+            Some("trunk"),
+            Some("main"),
+        ];
+        assert_eq!(summary(branches), "2 main, 1 master, 1 trunk, 1 error");
+        assert_eq!(summary([None, None]), "2 errors");
+        assert_eq!(summary([]), "No repositories");
+    }
+
+    #[test]
+    fn test_remote_style() {
+        assert_eq!(RemoteStyle::Tab.join("origin", "main"), "origin\tmain");
+        assert_eq!(
+            RemoteStyle::Slash.join("upstream", "trunk"),
+            "upstream/trunk"
+        );
+    }
+
+    #[test]
+    fn test_map_parallel_keeps_order() {
+        let items = (0..100).collect::<Vec<u64>>();
+        let expected = items.iter().map(|i| i * 2).collect::<Vec<_>>();
+        for jobs in [0, 1, 4, 200] {
+            assert_eq!(map_parallel(&items, jobs, |i| i * 2), expected);
+        }
+    }
+}
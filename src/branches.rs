@@ -0,0 +1,133 @@
+use crate::git::{self, git, run_dir};
+use crate::resolve;
+use std::io::{self, Write};
+use std::path::Path;
+
+#[derive(clap::Args)]
+pub struct BranchesArgs {
+    /// Print `name<TAB>details` lines for fzf, e.g. `fzf --delimiter '\t' | cut -f1`
+    #[arg(long)]
+    fzf: bool,
+}
+
+/// A local branch compared with the default branch.
+#[derive(Debug, PartialEq)]
+pub struct Branch {
+    pub name: String,
+    /// Commits on the branch that the default branch lacks, and the reverse; `None` for the
+    /// default branch itself.
+    pub ahead_behind: Option<(usize, usize)>,
+}
+
+impl Branch {
+    fn details(&self) -> String {
+        match self.ahead_behind {
+            None => "default".to_string(),
+            Some((ahead, behind)) => format!("+{} -{}", ahead, behind),
+        }
+    }
+}
+
+pub fn run(
+    args: &BranchesArgs,
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let branches = list(Path::new(dir), remote, options)?;
+    let width = branches.iter().map(|b| b.name.len()).max().unwrap_or(0);
+    let mut stdout = io::stdout().lock();
+    for branch in &branches {
+        if args.fzf {
+            writeln!(stdout, "{}\t{}", branch.name, branch.details())?;
+        } else {
+            writeln!(stdout, "{:<width$}  {}", branch.name, branch.details())?;
+        }
+    }
+    Ok(())
+}
+
+/// Lists the local branches, the default branch first, each counted against the default branch
+/// or its remote-tracking branch when there is no local one.
+pub fn list(
+    dir: &Path,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<Vec<Branch>, Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    let default = resolve::resolve(&repo, remote, options)?;
+    let local = format!("refs/heads/{}", default);
+    let base = if repo.find_reference(local.as_str()).is_ok() {
+        local
+    } else {
+        resolve::tracking_ref(&repo, remote, &default)
+    };
+    let dir = run_dir(&repo);
+    let mut branches = vec![Branch {
+        name: default.clone(),
+        ahead_behind: None,
+    }];
+    let names = git(
+        dir,
+        &["for-each-ref", "--format=%(refname:short)", "refs/heads"],
+    )?;
+    for name in names.lines() {
+        if name == default {
+            continue;
+        }
+        let counts = git(
+            dir,
+            &[
+                "rev-list",
+                "--left-right",
+                "--count",
+                &format!("{}...refs/heads/{}", base, name),
+            ],
+        )?;
+        let (behind, ahead) = counts
+            .split_once('\t')
+            .ok_or_else(|| format!("Unexpected rev-list output: {}", counts))?;
+        branches.push(Branch {
+            name: name.to_string(),
+            ahead_behind: Some((ahead.parse()?, behind.parse()?)),
+        });
+    }
+    Ok(branches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+
+    #[test]
+    fn test_list() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        git(tmp.path(), &["checkout", "-q", "-b", "feature"]).unwrap();
+        commit(tmp.path(), "feature 1");
+        commit(tmp.path(), "feature 2");
+        git(tmp.path(), &["checkout", "-q", "main"]).unwrap();
+        commit(tmp.path(), "main 1");
+        git(tmp.path(), &["branch", "another"]).unwrap();
+
+        assert_eq!(
+            list(tmp.path(), "origin", &options()).unwrap(),
+            vec![
+                Branch {
+                    name: "main".to_string(),
+                    ahead_behind: None,
+                },
+                Branch {
+                    name: "another".to_string(),
+                    ahead_behind: Some((0, 0)),
+                },
+                Branch {
+                    name: "feature".to_string(),
+                    ahead_behind: Some((2, 1)),
+                },
+            ]
+        );
+    }
+}
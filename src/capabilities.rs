@@ -0,0 +1,113 @@
+use crate::json;
+use crate::resolve::Strategy;
+
+/// Cargo features this binary was built with.
+const FEATURES: &[(&str, bool)] = &[
+    ("forge", cfg!(feature = "forge")),
+    ("network", cfg!(feature = "network")),
+    ("subprocess", cfg!(feature = "subprocess")),
+    ("libgit2", cfg!(feature = "libgit2")),
+    ("scripting", cfg!(feature = "scripting")),
+    ("tui", cfg!(feature = "tui")),
+];
+
+/// Backends a flag or subcommand may rely on, and whether this binary has each.
+const BACKENDS: &[(&str, bool)] = &[
+    // Asking the remote with `git ls-remote`, for the `set-head` strategy.
+    ("ls-remote", cfg!(feature = "subprocess")),
+    ("plugins", cfg!(feature = "subprocess")),
+    ("github-api", cfg!(feature = "forge")),
+    ("gitlab-api", cfg!(feature = "forge")),
+    ("serve", cfg!(feature = "network")),
+    ("daemon", cfg!(feature = "network")),
+    ("libgit2", cfg!(feature = "libgit2")),
+    // Tokens come from the environment or gh, never from the system keyring.
+    ("keyring", false),
+];
+
+/// Prints what this binary can do, as `name<TAB>value` lines or one JSON object.
+pub fn run(json: bool) -> Result<(), Box<dyn std::error::Error>> {
+    match json {
+        true => println!("{}", to_json()),
+        false => print!("{}", to_text()),
+    }
+    Ok(())
+}
+
+fn features() -> Vec<&'static str> {
+    FEATURES
+        .iter()
+        .filter(|(_, enabled)| *enabled)
+        .map(|(name, _)| *name)
+        .collect()
+}
+
+fn strategies() -> Vec<&'static str> {
+    Strategy::all().iter().map(|s| s.name()).collect()
+}
+
+fn to_text() -> String {
+    let mut text = format!(
+        "version\t{}\nfeatures\t{}\nstrategies\t{}\n",
+        env!("CARGO_PKG_VERSION"),
+        features().join(","),
+        strategies().join(",")
+    );
+    for (name, available) in BACKENDS {
+        text.push_str(&format!(
+            "{}\t{}\n",
+            name,
+            if *available { "yes" } else { "no" }
+        ));
+    }
+    text
+}
+
+fn to_json() -> String {
+    let list = |names: Vec<&str>| {
+        names
+            .into_iter()
+            .map(json::quote)
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    format!(
+        "{{\"version\":{},\"features\":[{}],\"strategies\":[{}],\"backends\":{{{}}}}}",
+        json::quote(env!("CARGO_PKG_VERSION")),
+        list(features()),
+        list(strategies()),
+        BACKENDS
+            .iter()
+            .map(|(name, available)| format!("{}:{}", json::quote(name), available))
+            .collect::<Vec<_>>()
+            .join(",")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_json() {
+        let value = json::parse(&to_json()).unwrap();
+        assert_eq!(
+            value.get("version").unwrap().as_str(),
+            Some(env!("CARGO_PKG_VERSION"))
+        );
+        let strategies = value.get("strategies").unwrap().as_array().unwrap();
+        assert!(strategies.iter().any(|s| s.as_str() == Some("remote-head")));
+        let backends = value.get("backends").unwrap();
+        assert_eq!(
+            backends.get("ls-remote").unwrap().as_bool(),
+            Some(cfg!(feature = "subprocess"))
+        );
+        assert_eq!(backends.get("keyring").unwrap().as_bool(), Some(false));
+        assert_eq!(
+            to_text().lines().count(),
+            3 + BACKENDS.len(),
+            "{}",
+            to_text()
+        );
+    }
+}
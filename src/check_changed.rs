@@ -0,0 +1,70 @@
+use crate::cache::{self, Cache};
+use crate::resolve::{self, Source, Strategy};
+
+/// Asks the remote for its default branch and compares it with the cached one, failing with
+/// `old -> new` on stdout when it changed. Only an answer from the remote or a plugin counts,
+/// never the local remote HEAD or a guess, so the cache only ever holds what the remote said. The
+/// fresh answer is cached for the next run unless `--dry-run` is given, so a cron job reports
+/// each change once.
+pub fn run(
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if options.offline {
+        return Err("check-changed asks the remote, which --offline forbids".into());
+    }
+    let repo = resolve::open(dir)?;
+    let mut fresh = resolve::Options {
+        no_cache: true,
+        fallback: None,
+        strategies: vec![Strategy::SetHead, Strategy::Plugins],
+        ..options.clone()
+    };
+    fresh.set_source("strategies", Source::CommandLine);
+    let branch = resolve::resolve(&repo, remote, &fresh)?;
+    let remote = resolve::primary_remote(dir, remote, options);
+    let url = cache::remote_url(&repo, &remote)
+        .ok_or_else(|| format!("{} has no URL to look up in the cache", remote))?;
+    let mut cache = Cache::open_default_locked()?;
+    let previous = check(&mut cache, &url, &branch);
+    if !options.dry_run {
+        cache.save()?;
+    }
+    match previous {
+        Some(previous) => {
+            println!("{} -> {}", previous, branch);
+            Err(format!("The default branch of {} changed", url).into())
+        }
+        None => Ok(()),
+    }
+}
+
+/// Records `branch` for `url` in `cache`, returning the branch it replaced if that differs. A
+/// remote not cached before has nothing to compare with.
+fn check(cache: &mut Cache, url: &str, branch: &str) -> Option<String> {
+    let previous = cache
+        .get(url)
+        .map(|entry| entry.branch.clone())
+        .filter(|previous| previous != branch);
+    cache.insert(url, branch);
+    previous
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check() {
+        let tmp = tempfile::tempdir().unwrap();
+        let mut cache = Cache::load(tmp.path().join("remotes.tsv")).unwrap();
+        let url = "github.com/owner/repo";
+
+        assert_eq!(check(&mut cache, url, "master"), None);
+        assert_eq!(check(&mut cache, url, "master"), None);
+        assert_eq!(check(&mut cache, url, "main").as_deref(), Some("master"));
+        assert_eq!(check(&mut cache, url, "main"), None);
+        assert_eq!(cache.get(url).unwrap().branch, "main");
+    }
+}
@@ -0,0 +1,544 @@
+use crate::policy::{self, Network};
+use crate::resolve::{self, Source, Strategy};
+use crate::ssh::HostKeyChecking;
+use crate::toml::{self, Value};
+use crate::{git, paths};
+use clap::builder::BoolishValueParser;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct ConfigArgs {
+    /// Read defaults from this file instead of
+    /// `$XDG_CONFIG_HOME/git-default-branch/config.toml`
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "GIT_DEFAULT_BRANCH_CONFIG",
+        global = true
+    )]
+    pub config: Option<PathBuf>,
+
+    /// Ignore the configuration file. Its `network` and `allowed_hosts` policy still applies, as
+    /// does that of `/etc/git-default-branch/config.toml`
+    #[arg(
+        long,
+        env = "GIT_DEFAULT_BRANCH_NO_CONFIG",
+        value_parser = BoolishValueParser::new(),
+        global = true,
+        conflicts_with = "config"
+    )]
+    pub no_config: bool,
+}
+
+/// Defaults from the configuration file. Command-line flags, environment variables and the
+/// repository's git config take precedence over all of them.
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// Remotes to try in order when `--remote` is not given.
+    pub remotes: Vec<String>,
+    /// Named remote lists, referred to as `@name`.
+    pub remote_groups: Vec<(String, Vec<String>)>,
+    /// Local branch names to fall back to.
+    pub candidates: Vec<String>,
+    /// Globs naming release or maintenance branches.
+    pub release_patterns: Vec<String>,
+    pub strategies: Vec<Strategy>,
+    pub cache_ttl: Option<u64>,
+    /// The Rhai script the `script` strategy runs, with `~` expanded.
+    #[cfg(feature = "scripting")]
+    pub script: Option<PathBuf>,
+    /// Per-host settings, from `[host."name"]` sections and the `api_hosts` shorthand.
+    pub hosts: Vec<HostProfile>,
+    /// Where to record the refs changed, unless `--audit-log` says otherwise, with `~` expanded.
+    pub audit_log: Option<PathBuf>,
+    /// The CA bundle to verify HTTPS servers with, unless `--ca-cert` says otherwise, with `~`
+    /// expanded.
+    pub ca_cert: Option<PathBuf>,
+    /// How to check SSH host keys, unless `--ssh-host-key-checking` says otherwise.
+    pub ssh_host_key_checking: Option<HostKeyChecking>,
+    /// Whether the network may be used; part of the [`policy`], which flags cannot override.
+    pub network: Option<Network>,
+    /// The only hosts the network may be used with, also part of the [`policy`].
+    pub allowed_hosts: Option<Vec<String>>,
+}
+
+/// Settings for repositories whose remote is on one host, so work and personal repositories can
+/// behave differently.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HostProfile {
+    pub host: String,
+    /// `github` or `gitlab`, for self-hosted instances.
+    #[cfg_attr(not(feature = "forge"), allow(dead_code))]
+    pub api: Option<String>,
+    /// Environment variable holding the API token, instead of the forge's usual one.
+    #[cfg_attr(not(feature = "forge"), allow(dead_code))]
+    pub token_env: Option<String>,
+    /// Proxy for API requests and for asking the remote.
+    pub proxy: Option<String>,
+    /// Strategies to use instead of the general `strategies`.
+    pub strategies: Vec<Strategy>,
+}
+
+impl HostProfile {
+    pub fn find<'a>(hosts: &'a [HostProfile], host: &str) -> Option<&'a HostProfile> {
+        hosts
+            .iter()
+            .find(|profile| profile.host.eq_ignore_ascii_case(host))
+    }
+
+    fn parse(host: &str, value: &Value) -> Result<Self, String> {
+        let mut profile = HostProfile {
+            host: host.to_string(),
+            ..HostProfile::default()
+        };
+        let string = |key: &str, value: &Value| {
+            value
+                .as_str()
+                .map(str::to_string)
+                .ok_or_else(|| format!("host.{}.{} must be a string", host, key))
+        };
+        for (key, value) in value
+            .entries()
+            .ok_or_else(|| format!("host.{} must be a table", host))?
+        {
+            match key.as_str() {
+                "api" => profile.api = Some(api(&format!("host.{}.api", host), value)?),
+                "token_env" => profile.token_env = Some(string(key, value)?),
+                "proxy" => profile.proxy = Some(string(key, value)?),
+                "strategies" => profile.strategies = strategies(key, value)?,
+                _ => return Err(format!("Unknown configuration key host.{}.{}", host, key)),
+            }
+        }
+        Ok(profile)
+    }
+}
+
+impl Config {
+    /// Loads the file named by `args`, or the default one if it exists.
+    pub fn load(args: &ConfigArgs) -> Result<Self, Box<dyn std::error::Error>> {
+        if args.no_config {
+            return Ok(Config::default());
+        }
+        let (path, required) = match &args.config {
+            Some(path) => (path.clone(), true),
+            None => match default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Config::default()),
+            },
+        };
+        match fs::read_to_string(&path) {
+            Ok(content) => {
+                Config::parse(&content).map_err(|e| format!("{}: {}", path.display(), e).into())
+            }
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(Config::default())
+            }
+            Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, String> {
+        let doc = toml::parse(content)?;
+        let mut config = Config::default();
+        for (key, value) in doc.entries().unwrap_or_default() {
+            match key.as_str() {
+                "remotes" => config.remotes = strings(key, value)?,
+                "remote_groups" => {
+                    for (name, members) in value.entries().ok_or("remote_groups must be a table")? {
+                        let members = strings(&format!("remote_groups.{}", name), members)?;
+                        if members.is_empty() {
+                            return Err(format!("remote_groups.{} is empty", name));
+                        }
+                        config.remote_groups.push((name.clone(), members));
+                    }
+                }
+                "candidates" => config.candidates = strings(key, value)?,
+                "release_patterns" => config.release_patterns = strings(key, value)?,
+                "strategies" => config.strategies = strategies(key, value)?,
+                "cache_ttl" => {
+                    config.cache_ttl = Some(
+                        value
+                            .as_integer()
+                            .and_then(|n| u64::try_from(n).ok())
+                            .ok_or("cache_ttl must be a number of seconds")?,
+                    )
+                }
+                #[cfg(feature = "scripting")]
+                "script" => {
+                    config.script = Some(crate::glob::expand_home(
+                        value.as_str().ok_or("script must be a string")?,
+                    ))
+                }
+                "audit_log" => {
+                    config.audit_log = Some(crate::glob::expand_home(
+                        value.as_str().ok_or("audit_log must be a string")?,
+                    ))
+                }
+                "ca_cert" => {
+                    config.ca_cert = Some(crate::glob::expand_home(
+                        value.as_str().ok_or("ca_cert must be a string")?,
+                    ))
+                }
+                "ssh_host_key_checking" => {
+                    config.ssh_host_key_checking =
+                        Some(value.as_str().and_then(HostKeyChecking::parse).ok_or(
+                            "ssh_host_key_checking must be \"strict\", \"accept-new\" or \"off\"",
+                        )?)
+                }
+                "network" => {
+                    config.network = Some(
+                        value
+                            .as_str()
+                            .and_then(Network::parse)
+                            .ok_or("network must be \"auto\" or \"never\"")?,
+                    )
+                }
+                "allowed_hosts" => config.allowed_hosts = Some(strings(key, value)?),
+                "host" => {
+                    for (host, value) in value.entries().ok_or("host must be a table")? {
+                        config.hosts.push(HostProfile::parse(host, value)?);
+                    }
+                }
+                "api_hosts" => {
+                    for (host, value) in value.entries().ok_or("api_hosts must be a table")? {
+                        let api = api(&format!("api_hosts.{}", host), value)?;
+                        match config.hosts.iter_mut().find(|p| p.host == *host) {
+                            Some(profile) => profile.api = profile.api.clone().or(Some(api)),
+                            None => config.hosts.push(HostProfile {
+                                host: host.clone(),
+                                api: Some(api),
+                                ..HostProfile::default()
+                            }),
+                        }
+                    }
+                }
+                _ => return Err(format!("Unknown configuration key {}", key)),
+            }
+        }
+        Ok(config)
+    }
+
+    /// Fills in the settings `options` did not get from the command line or the environment. The
+    /// remote priority only applies when no remote was given either way.
+    pub fn apply(&self, options: &mut resolve::Options) -> Result<(), String> {
+        options.remote_groups = self.remote_groups.clone();
+        if !self.remotes.is_empty() && options.set_source("remote", Source::ConfigFile) {
+            options.remotes = options.expand_groups(&self.remotes)?;
+        }
+        if !self.candidates.is_empty() && options.set_source("candidates", Source::ConfigFile) {
+            options.candidates = self.candidates.clone();
+        }
+        if !self.release_patterns.is_empty()
+            && options.set_source("release_patterns", Source::ConfigFile)
+        {
+            options.release_patterns = self.release_patterns.clone();
+        }
+        if !self.strategies.is_empty() && options.set_source("strategies", Source::ConfigFile) {
+            options.strategies = self.strategies.clone();
+        }
+        if let Some(ttl) = self.cache_ttl
+            && options.set_source("cache_ttl", Source::ConfigFile)
+        {
+            options.cache_ttl = Some(ttl);
+        }
+        #[cfg(feature = "scripting")]
+        if let Some(script) = &self.script
+            && options.set_source("script", Source::ConfigFile)
+        {
+            options.script = Some(script.clone());
+        }
+        options.hosts = self.hosts.clone();
+        Ok(())
+    }
+}
+
+/// Prints each setting's effective value for the repository at `dir`, if there is one, with
+/// where the value came from.
+pub fn show(
+    args: &ConfigArgs,
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = if args.no_config {
+        "disabled".to_string()
+    } else {
+        match args.config.clone().or_else(default_path) {
+            Some(path) if path.exists() => path.display().to_string(),
+            Some(path) => format!("{} (not found)", path.display()),
+            None => "none".to_string(),
+        }
+    };
+    let repo = git::discover(dir).ok();
+    let options = match &repo {
+        Some(repo) => options.for_repo(repo, remote),
+        None => options.clone(),
+    };
+    let profile = repo
+        .as_ref()
+        .and_then(|repo| options.host_profile(repo, remote));
+
+    let row = |name: &str, value: String, source: String| {
+        println!(
+            "{}",
+            format!("{:<12} {:<40} {}", name, value, source).trim_end()
+        );
+    };
+    row("config file", file, String::new());
+    row(
+        "repository",
+        repo.as_ref().map_or("none".to_string(), |repo| {
+            let dir = repo.workdir().unwrap_or(repo.git_dir());
+            paths::canonicalize(dir)
+                .unwrap_or_else(|_| dir.to_path_buf())
+                .display()
+                .to_string()
+        }),
+        String::new(),
+    );
+    row(
+        "host profile",
+        profile.map_or("none".to_string(), |p| p.host.clone()),
+        String::new(),
+    );
+    let source = |name| options.source(name).to_string();
+    row(
+        "remote",
+        options.remotes(remote).join(","),
+        source("remote"),
+    );
+    row(
+        "candidates",
+        options.candidates().join(","),
+        source("candidates"),
+    );
+    row(
+        "releases",
+        options.release_patterns().join(","),
+        source("release_patterns"),
+    );
+    row(
+        "strategies",
+        options
+            .strategies()
+            .iter()
+            .map(|s| s.name())
+            .collect::<Vec<_>>()
+            .join(","),
+        source("strategies"),
+    );
+    row("offline", options.offline.to_string(), source("offline"));
+    row(
+        "network",
+        policy::get().network.name().to_string(),
+        "policy".to_string(),
+    );
+    row(
+        "allowed hosts",
+        policy::get()
+            .allowed_hosts
+            .map_or("any".to_string(), |hosts| hosts.join(",")),
+        "policy".to_string(),
+    );
+    row(
+        "cache_ttl",
+        options
+            .cache_ttl()
+            .map_or("disabled".to_string(), |ttl| ttl.as_secs().to_string()),
+        source(if options.no_cache {
+            "no_cache"
+        } else {
+            "cache_ttl"
+        }),
+    );
+    #[cfg(feature = "scripting")]
+    row(
+        "script",
+        options
+            .script
+            .as_ref()
+            .map_or("none".to_string(), |script| script.display().to_string()),
+        source("script"),
+    );
+    row(
+        "proxy",
+        options.proxy.clone().unwrap_or_else(|| "none".to_string()),
+        source("proxy"),
+    );
+    Ok(())
+}
+
+fn api(key: &str, value: &Value) -> Result<String, String> {
+    match value.as_str() {
+        Some(api @ ("github" | "gitlab")) => Ok(api.to_string()),
+        _ => Err(format!("{} must be \"github\" or \"gitlab\"", key)),
+    }
+}
+
+fn strategies(key: &str, value: &Value) -> Result<Vec<Strategy>, String> {
+    strings(key, value)?
+        .iter()
+        .map(|name| Strategy::from_name(name).ok_or_else(|| format!("Unknown strategy {}", name)))
+        .collect()
+}
+
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    value
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect()
+        })
+        .ok_or_else(|| format!("{} must be an array of strings", key))
+}
+
+/// Returns `$XDG_CONFIG_HOME/git-default-branch/config.toml`, falling back to `~/.config`.
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("APPDATA")
+                .filter(|_| cfg!(windows))
+                .map(PathBuf::from)
+        })
+        .or_else(|| std::env::home_dir().map(|home| home.join(".config")))?;
+    Some(Path::new(&base).join("git-default-branch/config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let config = Config::parse(
+            r#"
+remotes = ["@canonical"]
+candidates = ["trunk", "main"]
+release_patterns = ["release/*", "stable-*"]
+strategies = ["remote-head", "local-names"]
+cache_ttl = 60
+audit_log = "/var/log/git-default-branch.jsonl"
+ca_cert = "/etc/ssl/corp-ca.pem"
+ssh_host_key_checking = "accept-new"
+network = "never"
+allowed_hosts = ["github.com", "git.example.com"]
+
+[remote_groups]
+canonical = ["upstream", "origin"]
+
+[api_hosts]
+"git.example.com" = "github"
+
+[host."git.corp.com"]
+api = "gitlab"
+token_env = "CORP_TOKEN"
+proxy = "http://proxy.corp.com:3128"
+strategies = ["remote-head"]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config,
+            Config {
+                remotes: vec!["@canonical".to_string()],
+                remote_groups: vec![(
+                    "canonical".to_string(),
+                    vec!["upstream".to_string(), "origin".to_string()]
+                )],
+                candidates: vec!["trunk".to_string(), "main".to_string()],
+                release_patterns: vec!["release/*".to_string(), "stable-*".to_string()],
+                strategies: vec![Strategy::RemoteHead, Strategy::LocalNames],
+                cache_ttl: Some(60),
+                #[cfg(feature = "scripting")]
+                script: None,
+                hosts: vec![
+                    HostProfile {
+                        host: "git.example.com".to_string(),
+                        api: Some("github".to_string()),
+                        ..HostProfile::default()
+                    },
+                    HostProfile {
+                        host: "git.corp.com".to_string(),
+                        api: Some("gitlab".to_string()),
+                        token_env: Some("CORP_TOKEN".to_string()),
+                        proxy: Some("http://proxy.corp.com:3128".to_string()),
+                        strategies: vec![Strategy::RemoteHead],
+                    },
+                ],
+                audit_log: Some(PathBuf::from("/var/log/git-default-branch.jsonl")),
+                ca_cert: Some(PathBuf::from("/etc/ssl/corp-ca.pem")),
+                ssh_host_key_checking: Some(HostKeyChecking::AcceptNew),
+                network: Some(Network::Never),
+                allowed_hosts: Some(vec![
+                    "github.com".to_string(),
+                    "git.example.com".to_string()
+                ]),
+            }
+        );
+
+        assert!(Config::parse("remote = \"origin\"").is_err());
+        assert!(Config::parse("[remote_groups]\nnone = []").is_err());
+        assert!(Config::parse("strategies = [\"guess\"]").is_err());
+        assert!(Config::parse("cache_ttl = -1").is_err());
+        assert!(Config::parse("audit_log = 1").is_err());
+        assert!(Config::parse("ca_cert = 1").is_err());
+        if let Some(home) = std::env::home_dir() {
+            let config =
+                Config::parse("audit_log = \"~/audit.jsonl\"\nca_cert = \"~/ca.pem\"").unwrap();
+            assert_eq!(config.audit_log, Some(home.join("audit.jsonl")));
+            assert_eq!(config.ca_cert, Some(home.join("ca.pem")));
+        }
+        assert!(Config::parse("network = \"always\"").is_err());
+        assert!(Config::parse("allowed_hosts = \"github.com\"").is_err());
+        assert!(Config::parse("[api_hosts]\n\"x\" = \"bitbucket\"").is_err());
+        assert!(Config::parse("[host.x]\nuser = \"me\"").is_err());
+    }
+
+    #[test]
+    fn test_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        let args = |config: Option<PathBuf>, no_config| ConfigArgs { config, no_config };
+
+        assert!(Config::load(&args(Some(path.clone()), false)).is_err());
+        fs::write(&path, "candidates = [\"trunk\"]\n").unwrap();
+        assert_eq!(
+            Config::load(&args(Some(path.clone()), false))
+                .unwrap()
+                .candidates,
+            vec!["trunk".to_string()]
+        );
+        assert_eq!(Config::load(&args(None, true)).unwrap(), Config::default());
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_script() {
+        let config = Config::parse("script = \"/etc/default-branch.rhai\"").unwrap();
+        assert_eq!(
+            config.script,
+            Some(PathBuf::from("/etc/default-branch.rhai"))
+        );
+        assert!(Config::parse("script = 1").is_err());
+        assert!(Config::parse("strategies = [\"script\", \"remote-head\"]").is_ok());
+    }
+
+    #[test]
+    fn test_apply_keeps_explicit_settings() {
+        let config = Config::parse("remotes = [\"upstream\"]\ncandidates = [\"trunk\"]").unwrap();
+        let mut options = resolve::Options {
+            candidates: vec!["main".to_string()],
+            ..resolve::Options::default()
+        };
+        options.set_source("candidates", Source::Environment);
+        config.apply(&mut options).unwrap();
+        assert_eq!(options.candidates, vec!["main".to_string()]);
+        assert_eq!(options.remotes, vec!["upstream".to_string()]);
+        assert_eq!(options.source("remote"), Source::ConfigFile);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{i18n, paths};
+use std::fs;
+use std::path::Path;
+
+/// Looks around `path`, where repository discovery just failed, for the likely reason and
+/// returns a suggestion for the user.
+pub fn not_a_repository(path: &Path) -> Option<String> {
+    let about = |key, path: &Path| Some(i18n::text(key, &[("path", &path.display())]));
+    if !path.exists() {
+        return about("diagnose-missing", path);
+    }
+    if path.is_file() {
+        return about("diagnose-file", path);
+    }
+    let path = paths::canonicalize(path).ok()?;
+    if looks_bare(&path) {
+        return about("diagnose-bare", &path);
+    }
+    if let Some(repo) = path.ancestors().find(|dir| dir.join(".git").exists()) {
+        return about("diagnose-ancestor", repo);
+    }
+    let nested = fs::read_dir(&path)
+        .ok()?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let dir = entry.path();
+            dir.join(".git").exists() || looks_bare(&dir)
+        })
+        .count();
+    match nested {
+        0 => None,
+        1 => about("diagnose-nested-one", &path),
+        _ => Some(i18n::text(
+            "diagnose-nested-other",
+            &[("path", &path.display()), ("count", &nested)],
+        )),
+    }
+}
+
+fn looks_bare(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::init_repo;
+
+    #[test]
+    fn test_not_a_repository() {
+        let tmp = tempfile::tempdir().unwrap();
+        let missing = tmp.path().join("missing");
+        assert!(
+            not_a_repository(&missing)
+                .unwrap()
+                .ends_with("does not exist")
+        );
+
+        let file = tmp.path().join("file");
+        fs::write(&file, "").unwrap();
+        assert!(not_a_repository(&file).unwrap().contains("is a file"));
+
+        let empty = tmp.path().join("empty");
+        fs::create_dir(&empty).unwrap();
+        assert_eq!(not_a_repository(&empty), None);
+
+        fs::create_dir(empty.join("repo")).unwrap();
+        init_repo(&empty.join("repo"), "main");
+        assert!(
+            not_a_repository(&empty)
+                .unwrap()
+                .contains("contains 1 repository")
+        );
+    }
+}
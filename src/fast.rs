@@ -0,0 +1,258 @@
+use crate::resolve::MAX_SYMREF_DEPTH;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Reads `refs/remotes/<remote>/HEAD` straight from disk, without discovering the repository
+/// through gix or loading any configuration.
+///
+/// Returns `None` whenever anything is unusual — no `.git` found, a detached or missing remote
+/// HEAD, a non-files ref backend — so the caller can fall back to the complete resolution.
+/// Symbolic refs are never packed, so `packed-refs` does not need to be consulted. A repository
+/// with `defaultBranch.*` settings or unusual fetch refspecs in its config is also left to the
+/// complete resolution.
+pub fn remote_head_branch(path: &Path, remote: &str) -> Option<String> {
+    let common_dir = common_dir(path)?;
+    let config = fs::read_to_string(common_dir.join("config")).unwrap_or_default();
+    if config.to_ascii_lowercase().contains("[defaultbranch") || has_custom_refspec(&config) {
+        return None;
+    }
+    let mut target = read_symref(&common_dir, &format!("refs/remotes/{}/HEAD", remote))?;
+    // Follow a chain of loose symbolic refs; a packed or missing target ends it.
+    for _ in 0..MAX_SYMREF_DEPTH {
+        let Some(next) = read_symref(&common_dir, &target) else {
+            let branch = target
+                .strip_prefix("refs/remotes/")?
+                .strip_prefix(remote)?
+                .strip_prefix('/')?;
+            return (!branch.is_empty()).then(|| branch.to_string());
+        };
+        target = next;
+    }
+    None
+}
+
+/// Whether any `fetch` line of `config` differs from the `+refs/heads/*:refs/remotes/<name>/*`
+/// that `git clone` and `git remote add` write, which is then needed to map refs back to branches.
+fn has_custom_refspec(config: &str) -> bool {
+    config.lines().any(|line| {
+        let Some((key, value)) = line.split_once('=') else {
+            return false;
+        };
+        key.trim().eq_ignore_ascii_case("fetch")
+            && !value
+                .trim()
+                .strip_prefix("+refs/heads/*:refs/remotes/")
+                .is_some_and(|rest| rest.ends_with("/*") && !rest[..rest.len() - 2].contains('*'))
+    })
+}
+
+/// Returns the target of the loose symbolic ref `name`, if it is one.
+fn read_symref(common_dir: &Path, name: &str) -> Option<String> {
+    let content = fs::read_to_string(common_dir.join(name)).ok()?;
+    Some(content.trim_end().strip_prefix("ref: ")?.to_string())
+}
+
+/// Returns the first of `candidates` that exists as a local branch, as a loose or packed ref, or
+/// failing that as a remote-tracking branch of one of `remotes`, as a fresh clone that has not
+/// checked out anything may only have those. Names must match exactly, even on a
+/// case-insensitive filesystem.
+pub fn local_branch(path: &Path, remotes: &[&str], candidates: &[&str]) -> Option<String> {
+    let common_dir = common_dir(path)?;
+    let packed_refs = fs::read_to_string(common_dir.join("packed-refs")).unwrap_or_default();
+    let find = |prefix: &str| {
+        candidates.iter().find(|name| {
+            let ref_name = format!("{}{}", prefix, name);
+            is_loose_ref(&common_dir, &ref_name)
+                || packed_refs
+                    .lines()
+                    .any(|line| line.split_once(' ').is_some_and(|(_, r)| r == ref_name))
+        })
+    };
+    find("refs/heads/")
+        .or_else(|| {
+            let config = fs::read_to_string(common_dir.join("config")).unwrap_or_default();
+            if has_custom_refspec(&config) {
+                return None;
+            }
+            remotes
+                .iter()
+                .find_map(|remote| find(&format!("refs/remotes/{}/", remote)))
+        })
+        .map(|name| name.to_string())
+}
+
+/// Whether `ref_name` is a file under `common_dir` under exactly that name: a case-insensitive
+/// filesystem would also open `refs/heads/main` for a branch stored as `Main`.
+fn is_loose_ref(common_dir: &Path, ref_name: &str) -> bool {
+    if !common_dir.join(ref_name).is_file() {
+        return false;
+    }
+    let mut dir = common_dir.to_path_buf();
+    ref_name.split('/').all(|component| {
+        let found = fs::read_dir(&dir).is_ok_and(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .any(|entry| entry.file_name() == component)
+        });
+        dir.push(component);
+        found
+    })
+}
+
+/// Finds the directory holding the shared refs for the repository containing `path`, or for
+/// `GIT_DIR` when it is set.
+fn common_dir(path: &Path) -> Option<PathBuf> {
+    if let Some(git_dir) = std::env::var_os("GIT_DIR") {
+        return Some(shared(PathBuf::from(git_dir)));
+    }
+    let path = fs::canonicalize(path).ok()?;
+    let ceilings = ceiling_dirs();
+    let mut dot_git = None;
+    for dir in path.ancestors() {
+        if dir != path && ceilings.iter().any(|ceiling| ceiling == dir) {
+            return None;
+        }
+        // A bare repository, or a directory inside one, is left to the complete discovery
+        // rather than mistaken for part of a checkout that contains it.
+        if is_git_dir(dir) {
+            return None;
+        }
+        if dir.join(".git").exists() {
+            dot_git = Some(dir.join(".git"));
+            break;
+        }
+    }
+    let dot_git = dot_git?;
+    if dot_git.is_dir() {
+        return Some(shared(dot_git));
+    }
+
+    // Linked worktrees and submodules use a `.git` file pointing at the real git directory.
+    let content = fs::read_to_string(&dot_git).ok()?;
+    let git_dir = dot_git
+        .parent()?
+        .join(content.trim_end().strip_prefix("gitdir: ")?);
+    Some(shared(git_dir))
+}
+
+/// Whether `dir` has the `HEAD`, `objects` and `refs` of a git directory.
+fn is_git_dir(dir: &Path) -> bool {
+    dir.join("HEAD").is_file() && dir.join("objects").is_dir() && dir.join("refs").is_dir()
+}
+
+/// The directories in `GIT_CEILING_DIRECTORIES`, above which discovery must not look.
+fn ceiling_dirs() -> Vec<PathBuf> {
+    let Some(dirs) = std::env::var_os("GIT_CEILING_DIRECTORIES") else {
+        return Vec::new();
+    };
+    std::env::split_paths(&dirs)
+        .filter(|dir| dir.is_absolute())
+        .map(|dir| fs::canonicalize(&dir).unwrap_or(dir))
+        .collect()
+}
+
+/// Follows `commondir` from a linked worktree's git directory to the main one.
+fn shared(git_dir: PathBuf) -> PathBuf {
+    match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(common) => git_dir.join(common.trim_end()),
+        Err(_) => git_dir,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::git;
+    use crate::testutil::{commit, init_repo};
+
+    #[test]
+    fn test_has_custom_refspec() {
+        let config = |fetch: &str| format!("[remote \"origin\"]\n\turl = x\n\tfetch = {}\n", fetch);
+        assert!(!has_custom_refspec(&config(
+            "+refs/heads/*:refs/remotes/origin/*"
+        )));
+        assert!(!has_custom_refspec(&config(
+            "+refs/heads/*:refs/remotes/team/up/*"
+        )));
+        assert!(has_custom_refspec(&config(
+            "+refs/heads/*:refs/remotes/mirror-*"
+        )));
+        assert!(has_custom_refspec(&config(
+            "+refs/heads/main:refs/remotes/origin/main"
+        )));
+        assert!(has_custom_refspec(&config(
+            "refs/heads/*:refs/remotes/origin/*"
+        )));
+    }
+
+    #[test]
+    fn test_remote_head_branch() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        let worktree_dir = tmp.path().join("worktree");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+        assert_eq!(remote_head_branch(&repo_dir, "origin"), None);
+        assert_eq!(
+            local_branch(&repo_dir, &["origin"], &["main", "default"]).as_deref(),
+            Some("default")
+        );
+        git(&repo_dir, &["pack-refs", "--all"]).unwrap();
+        assert!(!repo_dir.join(".git/refs/heads/default").exists());
+        assert_eq!(
+            local_branch(&repo_dir, &["origin"], &["main", "default"]).as_deref(),
+            Some("default")
+        );
+        assert_eq!(local_branch(&repo_dir, &["origin"], &["main"]), None);
+
+        git(
+            tmp.path(),
+            &[
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        fs::create_dir(clone_dir.join("sub")).unwrap();
+        assert_eq!(
+            remote_head_branch(&clone_dir.join("sub"), "origin").as_deref(),
+            Some("default")
+        );
+        assert_eq!(remote_head_branch(&clone_dir, "upstream"), None);
+
+        // A bare clone inside the checkout must not answer with the checkout's remote HEAD.
+        let bare_dir = clone_dir.join("sub/bare.git");
+        git(
+            tmp.path(),
+            &[
+                "clone",
+                "--bare",
+                repo_dir.to_str().unwrap(),
+                bare_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(remote_head_branch(&bare_dir, "origin"), None);
+        assert_eq!(remote_head_branch(&bare_dir.join("refs"), "origin"), None);
+
+        git(
+            &clone_dir,
+            &[
+                "worktree",
+                "add",
+                "-b",
+                "feature",
+                worktree_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            remote_head_branch(&worktree_dir, "origin").as_deref(),
+            Some("default")
+        );
+    }
+}
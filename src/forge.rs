@@ -0,0 +1,995 @@
+use crate::cache;
+use crate::config::HostProfile;
+use crate::git;
+use crate::json::{self, Value};
+use crate::policy;
+use crate::redact;
+use crate::resolve;
+use crate::scan::{self, Entry, Format};
+use crate::tls;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+const PER_PAGE: usize = 100;
+
+#[derive(clap::Args)]
+pub struct OrgScanArgs {
+    /// Organization to scan, as `github:<org>`
+    target: String,
+
+    /// Base URL of the GitHub API, for GitHub Enterprise Server
+    #[arg(long, default_value = "https://api.github.com")]
+    api_url: String,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+}
+
+#[derive(clap::Args)]
+pub struct ProtectedArgs {
+    /// List every protected branch of the remote repository, one per line, instead of reporting
+    /// on the default branch; GitLab may list wildcard patterns such as `release/*`
+    #[arg(long)]
+    list: bool,
+}
+
+pub fn org_scan(args: &OrgScanArgs, use_cache: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let org = match args.target.split_once(':') {
+        Some(("github", org)) if !org.is_empty() => org,
+        _ => {
+            return Err(
+                format!("Unsupported target {}; expected github:<org>", args.target).into(),
+            );
+        }
+    };
+
+    let client = Client::new(
+        github_token(api_host(&args.api_url)),
+        use_cache,
+        None,
+        tls::HttpConfig::load(None),
+    );
+    let mut entries = Vec::new();
+    for page in 1.. {
+        let url = format!(
+            "{}/orgs/{}/repos?per_page={}&page={}",
+            args.api_url.trim_end_matches('/'),
+            org,
+            PER_PAGE,
+            page
+        );
+        let repos = parse_repositories(&client.get_json(&url)?)?;
+        let count = repos.len();
+        entries.extend(repos);
+        if count < PER_PAGE {
+            break;
+        }
+    }
+
+    scan::write_entries(
+        &mut io::stdout().lock(),
+        "repository",
+        &entries,
+        args.format,
+    )?;
+    Ok(())
+}
+
+/// The token for `host` from `GH_TOKEN` or `GITHUB_TOKEN`, or else the one gh stored with
+/// `gh auth login`.
+pub fn github_token(host: &str) -> Option<String> {
+    ["GH_TOKEN", "GITHUB_TOKEN"]
+        .iter()
+        .find_map(|name| std::env::var(name).ok().filter(|token| !token.is_empty()))
+        .or_else(|| {
+            let output = Command::new("gh")
+                .args(["auth", "token", "--hostname", host])
+                .stderr(Stdio::null())
+                .output()
+                .ok()?;
+            let token = String::from_utf8(output.stdout).ok()?;
+            (output.status.success() && !token.trim().is_empty()).then(|| token.trim().to_string())
+        })
+}
+
+/// The host a forge API URL belongs to, as gh names it.
+fn api_host(api_url: &str) -> &str {
+    let rest = api_url.split_once("://").map_or(api_url, |(_, rest)| rest);
+    match rest.split('/').next().unwrap_or(rest) {
+        "api.github.com" => "github.com",
+        host => host,
+    }
+}
+
+/// Whether this binary runs as the `gh default-branch` extension, which gh installs as
+/// `gh-default-branch`.
+pub fn is_gh_extension() -> bool {
+    std::env::args_os()
+        .next()
+        .map(PathBuf::from)
+        .is_some_and(|path| {
+            path.file_stem()
+                .is_some_and(|stem| stem == "gh-default-branch")
+        })
+}
+
+/// The repository gh points extensions at with `GH_REPO`, when running as one.
+pub fn gh_repo() -> Option<Hosted> {
+    if !is_gh_extension() {
+        return None;
+    }
+    let repo = std::env::var("GH_REPO").ok()?;
+    let host = std::env::var("GH_HOST")
+        .ok()
+        .filter(|host| !host.is_empty());
+    parse_gh_repo(&repo, host.as_deref().unwrap_or("github.com"))
+}
+
+/// Parses gh's `[HOST/]OWNER/REPO` notation.
+fn parse_gh_repo(repo: &str, default_host: &str) -> Option<Hosted> {
+    let (host, path) = match repo.split('/').collect::<Vec<_>>().as_slice() {
+        [owner, name] if !owner.is_empty() && !name.is_empty() => (default_host, repo),
+        [host, owner, name] if !host.is_empty() && !owner.is_empty() && !name.is_empty() => {
+            repo.split_once('/')?
+        }
+        _ => return None,
+    };
+    Some(Hosted::GitHub {
+        api_url: if host.eq_ignore_ascii_case("github.com") {
+            "https://api.github.com".to_string()
+        } else {
+            format!("https://{}/api/v3", host)
+        },
+        path: path.to_string(),
+    })
+}
+
+/// Resolves the repository named by `GH_REPO` through the API, for the gh extension.
+pub fn gh_default_branch(
+    options: &resolve::Options,
+) -> Result<resolve::Resolved, Box<dyn std::error::Error>> {
+    options.or_fallback(ask_hosted(gh_repo(), options))
+}
+
+fn ask_hosted(
+    hosted: Option<Hosted>,
+    options: &resolve::Options,
+) -> Result<resolve::Resolved, Box<dyn std::error::Error>> {
+    let hosted = hosted.ok_or("GH_REPO is not set")?;
+    let profile = HostProfile::find(&options.hosts, hosted.host());
+    Ok(resolve::Resolved {
+        branch: hosted.default_branch(!options.no_cache, profile, None)?,
+        provenance: "api",
+    })
+}
+
+pub fn gitlab_token() -> Option<String> {
+    std::env::var("GITLAB_TOKEN")
+        .ok()
+        .filter(|token| !token.is_empty())
+}
+
+/// Issues forge API requests with curl, revalidating cached responses with their ETags so
+/// unchanged answers cost neither bandwidth nor rate limit.
+pub struct Client {
+    token: Option<String>,
+    proxy: Option<String>,
+    cache_dir: Option<PathBuf>,
+    /// git's TLS settings, for verifying servers as git would.
+    http: tls::HttpConfig,
+}
+
+impl Client {
+    pub fn new(
+        token: Option<String>,
+        use_cache: bool,
+        proxy: Option<String>,
+        http: tls::HttpConfig,
+    ) -> Self {
+        Client {
+            token,
+            proxy,
+            http,
+            cache_dir: use_cache
+                .then(cache::dir)
+                .flatten()
+                .map(|dir| dir.join("http")),
+        }
+    }
+
+    /// Fetches `url` and returns the HTTP status with the parsed body. A `304 Not Modified` is
+    /// answered from the cache and reported as `200`.
+    pub fn request(&self, url: &str) -> Result<(u16, Value), Box<dyn std::error::Error>> {
+        // Responses are cached per token, so one token never sees what another was answered.
+        let key = match &self.token {
+            Some(token) => format!("{}\n{:016x}", url, fnv1a(token.as_bytes())),
+            None => url.to_string(),
+        };
+        let cache_file = self
+            .cache_dir
+            .as_ref()
+            .map(|dir| dir.join(format!("{:016x}", fnv1a(key.as_bytes()))));
+        let cached = cache_file
+            .as_ref()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| {
+                let (etag, body) = content.split_once('\n')?;
+                Some((etag.to_string(), body.to_string()))
+            });
+
+        let (status, etag, body) = curl(
+            url,
+            self.token.as_deref(),
+            self.proxy.as_deref(),
+            &self.http,
+            cached.as_ref().map(|(etag, _)| etag.as_str()),
+        )?;
+        let body = match (status, cached) {
+            (304, Some((_, body))) => return Ok((200, parse_body(&body)?)),
+            (200, _) => {
+                if let (Some(path), Some(etag)) = (&cache_file, etag) {
+                    let _ = write_private(path, &format!("{}\n{}", etag, body));
+                }
+                body
+            }
+            (_, _) => body,
+        };
+        Ok((status, parse_body(&body)?))
+    }
+
+    /// Like [`Client::request`], but treats any non-2xx status as an error.
+    pub fn get_json(&self, url: &str) -> Result<Value, Box<dyn std::error::Error>> {
+        match self.request(url)? {
+            (200..=299, body) => Ok(body),
+            (status, body) => Err(format!(
+                "Request to {} failed with HTTP {}{}",
+                url,
+                status,
+                body.get("message")
+                    .and_then(Value::as_str)
+                    .map(|m| format!(": {}", m))
+                    .unwrap_or_default()
+            )
+            .into()),
+        }
+    }
+}
+
+/// How many redirects a request follows before it fails.
+const MAX_REDIRECTS: usize = 5;
+
+/// Runs curl and returns the status, ETag and raw body. Redirects are followed here rather than
+/// by curl, so the policy is checked for every URL contacted and the token is only sent to the
+/// host it was meant for.
+fn curl(
+    url: &str,
+    token: Option<&str>,
+    proxy: Option<&str>,
+    http: &tls::HttpConfig,
+    etag: Option<&str>,
+) -> Result<(u16, Option<String>, String), Box<dyn std::error::Error>> {
+    let host = |url: &str| {
+        gix::url::parse(url.into())
+            .ok()
+            .and_then(|url| url.host().map(str::to_string))
+    };
+    let mut current = url.to_string();
+    for _ in 0..=MAX_REDIRECTS {
+        let token = token.filter(|_| host(&current) == host(url));
+        let response = curl_once(&current, token, proxy, http, etag)?;
+        match response.location {
+            Some(location) if (300..400).contains(&response.status) && response.status != 304 => {
+                log::debug!("{} redirects to {}", current, location);
+                current = location;
+            }
+            _ => return Ok((response.status, response.etag, response.body)),
+        }
+    }
+    Err(resolve::Failure::Network(format!(
+        "Request to {} was redirected more than {} times",
+        url, MAX_REDIRECTS
+    ))
+    .into())
+}
+
+/// What a single curl run returned.
+struct Response {
+    status: u16,
+    /// Where a redirect points.
+    location: Option<String>,
+    etag: Option<String>,
+    body: String,
+}
+
+/// Runs curl once, without following redirects. Headers, including the token, are passed on
+/// stdin so they never show up in the process list.
+fn curl_once(
+    url: &str,
+    token: Option<&str>,
+    proxy: Option<&str>,
+    http: &tls::HttpConfig,
+    etag: Option<&str>,
+) -> Result<Response, Box<dyn std::error::Error>> {
+    policy::check_url(url)?;
+    log::debug!("requesting {}", url);
+    let mut command = Command::new("curl");
+    command.args(tls::curl_args(url, http));
+    if let Some(proxy) = proxy {
+        command.args(["--proxy", proxy]);
+    }
+    let mut child = command
+        .args([
+            "--silent",
+            "--show-error",
+            "--header",
+            "Accept: application/vnd.github+json",
+            "--header",
+            "@-",
+            "--write-out",
+            "\n%{http_code} %{redirect_url} %header{etag}",
+            url,
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        if let Some(token) = token {
+            writeln!(stdin, "Authorization: Bearer {}", token)?;
+        }
+        if let Some(etag) = etag {
+            writeln!(stdin, "If-None-Match: {}", etag)?;
+        }
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(resolve::Failure::Network(
+            redact::redact(&format!(
+                "Request to {} failed: {}",
+                url,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+            .into_owned(),
+        )
+        .into());
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let (body, trailer) = stdout.rsplit_once('\n').unwrap_or(("", &stdout));
+    let mut fields = trailer.trim().splitn(3, ' ');
+    let status = fields.next().unwrap_or_default();
+    let mut field = || Some(fields.next()?.trim().to_string()).filter(|value| !value.is_empty());
+    Ok(Response {
+        status: status.parse()?,
+        location: field(),
+        etag: field(),
+        body: body.to_string(),
+    })
+}
+
+/// Writes a cached response to `path`, readable by the user alone along with its directory, since
+/// responses to authenticated requests may describe private repositories.
+#[cfg(unix)]
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+    if let Some(dir) = path.parent() {
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(content.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)
+}
+
+fn parse_body(body: &str) -> Result<Value, String> {
+    if body.trim().is_empty() {
+        return Ok(Value::Null);
+    }
+    json::parse(body)
+}
+
+/// A stable hash for naming cache files.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Protection {
+    pub protected: bool,
+    pub required_reviews: Option<u64>,
+    pub force_push_blocked: Option<bool>,
+}
+
+/// A repository hosted on a forge with a known API.
+#[derive(Debug, PartialEq)]
+pub enum Hosted {
+    GitHub { api_url: String, path: String },
+    GitLab { api_url: String, path: String },
+}
+
+impl Hosted {
+    /// Recognizes repositories on github.com and gitlab.com from their remote URL, and on the
+    /// self-hosted instances whose host profile names an API.
+    pub fn from_url(url: &gix::Url, hosts: &[HostProfile]) -> Option<Self> {
+        let path = url.path.to_string();
+        let path = path.trim_start_matches('/');
+        let path = path.strip_suffix(".git").unwrap_or(path).to_string();
+        let host = url.host()?;
+        match host {
+            "github.com" => Some(Hosted::GitHub {
+                api_url: "https://api.github.com".to_string(),
+                path,
+            }),
+            "gitlab.com" => Some(Hosted::GitLab {
+                api_url: "https://gitlab.com/api/v4".to_string(),
+                path,
+            }),
+            _ => match HostProfile::find(hosts, host)?.api.as_deref()? {
+                "github" => Some(Hosted::GitHub {
+                    api_url: format!("https://{}/api/v3", host),
+                    path,
+                }),
+                "gitlab" => Some(Hosted::GitLab {
+                    api_url: format!("https://{}/api/v4", host),
+                    path,
+                }),
+                _ => None,
+            },
+        }
+    }
+
+    pub fn from_remote(
+        repo: &gix::Repository,
+        remote: &str,
+        hosts: &[HostProfile],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let remote = repo.find_remote(remote)?;
+        let url = remote
+            .url(gix::remote::Direction::Fetch)
+            .ok_or("Remote has no URL")?;
+        Self::from_url(url, hosts).ok_or_else(|| {
+            format!("{} is not hosted on a supported forge", url.to_bstring()).into()
+        })
+    }
+
+    fn host(&self) -> &str {
+        match self {
+            Hosted::GitHub { api_url, .. } | Hosted::GitLab { api_url, .. } => api_host(api_url),
+        }
+    }
+
+    /// A client with the token and proxy from `profile` if given, or the forge's usual token, and
+    /// the TLS settings of `repo`, or the global ones without a repository.
+    fn client(
+        &self,
+        use_cache: bool,
+        profile: Option<&HostProfile>,
+        repo: Option<&gix::Repository>,
+    ) -> Client {
+        let token = match profile.and_then(|profile| profile.token_env.as_deref()) {
+            Some(name) => std::env::var(name).ok().filter(|token| !token.is_empty()),
+            None => match self {
+                Hosted::GitHub { .. } => github_token(self.host()),
+                Hosted::GitLab { .. } => gitlab_token(),
+            },
+        };
+        let proxy = profile.and_then(|profile| profile.proxy.clone());
+        Client::new(token, use_cache, proxy, tls::HttpConfig::load(repo))
+    }
+
+    /// Asks the forge for the repository's default branch.
+    pub fn default_branch(
+        &self,
+        use_cache: bool,
+        profile: Option<&HostProfile>,
+        repo: Option<&gix::Repository>,
+    ) -> Result<String, Box<dyn std::error::Error>> {
+        let url = match self {
+            Hosted::GitHub { api_url, path } => format!("{}/repos/{}", api_url, path),
+            Hosted::GitLab { api_url, path } => {
+                format!("{}/projects/{}", api_url, path.replace('/', "%2F"))
+            }
+        };
+        let info = self.client(use_cache, profile, repo).get_json(&url)?;
+        info.get("default_branch")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .ok_or_else(|| format!("{} reports no default branch", url).into())
+    }
+
+    /// Lists the names of the protected branches, with the token and proxy from `profile` if
+    /// given.
+    pub fn protected_branches(
+        &self,
+        use_cache: bool,
+        profile: Option<&HostProfile>,
+        repo: Option<&gix::Repository>,
+    ) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let client = self.client(use_cache, profile, repo);
+        let mut names = Vec::new();
+        for page in 1.. {
+            let url = match self {
+                Hosted::GitHub { api_url, path } => format!(
+                    "{}/repos/{}/branches?protected=true&per_page={}&page={}",
+                    api_url, path, PER_PAGE, page
+                ),
+                Hosted::GitLab { api_url, path } => format!(
+                    "{}/projects/{}/protected_branches?per_page={}&page={}",
+                    api_url,
+                    path.replace('/', "%2F"),
+                    PER_PAGE,
+                    page
+                ),
+            };
+            let branches = parse_branch_names(&client.get_json(&url)?)?;
+            let count = branches.len();
+            names.extend(branches);
+            if count < PER_PAGE {
+                break;
+            }
+        }
+        Ok(names)
+    }
+
+    /// The API URL of `branch` on GitHub, or of its protection on GitLab, with the name encoded
+    /// as a single path segment so `release/1.0` or a `#` in it reaches the right endpoint.
+    fn branch_url(&self, branch: &str) -> String {
+        match self {
+            Hosted::GitHub { api_url, path } => {
+                format!(
+                    "{}/repos/{}/branches/{}",
+                    api_url,
+                    path,
+                    path_segment(branch)
+                )
+            }
+            Hosted::GitLab { api_url, path } => format!(
+                "{}/projects/{}/protected_branches/{}",
+                api_url,
+                path.replace('/', "%2F"),
+                path_segment(branch)
+            ),
+        }
+    }
+
+    /// Looks up the protection of `branch`, with the token and proxy from `profile` if given.
+    pub fn protection(
+        &self,
+        branch: &str,
+        use_cache: bool,
+        profile: Option<&HostProfile>,
+        repo: Option<&gix::Repository>,
+    ) -> Result<Protection, Box<dyn std::error::Error>> {
+        let client = self.client(use_cache, profile, repo);
+        match self {
+            Hosted::GitHub { .. } => {
+                let url = self.branch_url(branch);
+                let info = client.get_json(&url)?;
+                if info.get("protected") != Some(&Value::Bool(true)) {
+                    return Ok(Protection::default());
+                }
+                // Protection details require admin access; report what is visible.
+                let details = match client.request(&format!("{}/protection", url))? {
+                    (200, details) => details,
+                    _ => Value::Null,
+                };
+                Ok(parse_github_protection(&details))
+            }
+            Hosted::GitLab { .. } => match client.request(&self.branch_url(branch))? {
+                (404, _) => Ok(Protection::default()),
+                (200, details) => Ok(parse_gitlab_protection(&details)),
+                (status, _) => Err(format!("GitLab API returned HTTP {}", status).into()),
+            },
+        }
+    }
+}
+
+/// Percent-encodes `s` for use as one segment of a URL path.
+fn path_segment(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn parse_github_protection(details: &Value) -> Protection {
+    Protection {
+        protected: true,
+        required_reviews: details
+            .get("required_pull_request_reviews")
+            .and_then(|r| r.get("required_approving_review_count"))
+            .and_then(Value::as_u64),
+        force_push_blocked: details
+            .get("allow_force_pushes")
+            .and_then(|f| f.get("enabled"))
+            .and_then(Value::as_bool)
+            .map(|enabled| !enabled),
+    }
+}
+
+fn parse_gitlab_protection(details: &Value) -> Protection {
+    Protection {
+        protected: true,
+        required_reviews: None,
+        force_push_blocked: details
+            .get("allow_force_push")
+            .and_then(Value::as_bool)
+            .map(|allowed| !allowed),
+    }
+}
+
+pub fn protected(
+    args: &ProtectedArgs,
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    let hosted = Hosted::from_remote(&repo, remote, &options.hosts)?;
+    let profile = repo
+        .find_remote(remote)?
+        .url(gix::remote::Direction::Fetch)
+        .and_then(|url| HostProfile::find(&options.hosts, url.host()?));
+    if args.list {
+        for name in hosted.protected_branches(!options.no_cache, profile, Some(&repo))? {
+            println!("{}", name);
+        }
+        return Ok(());
+    }
+    let branch = resolve::resolve(&repo, remote, options)?;
+    let protection = hosted.protection(&branch, !options.no_cache, profile, Some(&repo))?;
+
+    if !protection.protected {
+        println!("{}: not protected", branch);
+        return Ok(());
+    }
+    println!("{}: protected", branch);
+    if let Some(count) = protection.required_reviews {
+        println!("  required approving reviews: {}", count);
+    }
+    if let Some(blocked) = protection.force_push_blocked {
+        println!(
+            "  force pushes: {}",
+            if blocked { "blocked" } else { "allowed" }
+        );
+    }
+    Ok(())
+}
+
+fn parse_branch_names(value: &Value) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    value
+        .as_array()
+        .ok_or("Unexpected API response: expected an array of branches")?
+        .iter()
+        .map(|branch| {
+            branch
+                .get("name")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .ok_or_else(|| "Unexpected API response: missing name".into())
+        })
+        .collect()
+}
+
+fn parse_repositories(value: &Value) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    value
+        .as_array()
+        .ok_or("Unexpected API response: expected an array of repositories")?
+        .iter()
+        .map(|repo| {
+            let field = |key| {
+                repo.get(key)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| format!("Unexpected API response: missing {}", key))
+            };
+            Ok(Entry {
+                name: field("full_name")?.to_string(),
+                result: Ok(field("default_branch")?.to_string()),
+                remote: None,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repositories() {
+        let value = json::parse(
+            r#"[{"full_name":"org/a","default_branch":"main"},{"full_name":"org/b","default_branch":"master","archived":true}]"#,
+        )
+        .unwrap();
+        let entries = parse_repositories(&value).unwrap();
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| (e.name.as_str(), e.result.as_deref().unwrap()))
+                .collect::<Vec<_>>(),
+            vec![("org/a", "main"), ("org/b", "master")]
+        );
+
+        assert!(parse_repositories(&json::parse(r#"{"message":"Not Found"}"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_client_revalidates_with_etag() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let mut conditional = Vec::new();
+            for stream in listener.incoming().take(3) {
+                let mut stream = stream.unwrap();
+                let mut if_none_match = None;
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    if let Some(value) = line.strip_prefix("If-None-Match: ") {
+                        if_none_match = Some(value.trim().to_string());
+                    }
+                    line.clear();
+                }
+                let response = if if_none_match.as_deref() == Some("\"v1\"") {
+                    "HTTP/1.1 304 Not Modified\r\nETag: \"v1\"\r\nConnection: close\r\n\r\n"
+                } else {
+                    "HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nContent-Length: 15\r\nConnection: close\r\n\r\n{\"name\":\"main\"}"
+                };
+                stream.write_all(response.as_bytes()).unwrap();
+                conditional.push(if_none_match.is_some());
+            }
+            conditional
+        });
+
+        let tmp = tempfile::tempdir().unwrap();
+        let cache_dir = tmp.path().join("http");
+        let client = |token: Option<&str>| Client {
+            token: token.map(str::to_string),
+            proxy: None,
+            cache_dir: Some(cache_dir.clone()),
+            http: tls::HttpConfig::default(),
+        };
+        let url = format!("http://{}/repos/o/r", addr);
+        for _ in 0..2 {
+            let (status, body) = client(None).request(&url).unwrap();
+            assert_eq!(status, 200);
+            assert_eq!(body.get("name").and_then(Value::as_str), Some("main"));
+        }
+        // Another token does not get the cached answer.
+        assert_eq!(client(Some("secret")).request(&url).unwrap().0, 200);
+        assert_eq!(server.join().unwrap(), vec![false, true, false]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+            assert_eq!(mode(&cache_dir), 0o700);
+            for entry in fs::read_dir(&cache_dir).unwrap() {
+                assert_eq!(mode(&entry.unwrap().path()), 0o600);
+            }
+        }
+    }
+
+    #[test]
+    fn test_client_follows_redirects_without_the_token() {
+        use std::io::{BufRead, BufReader};
+        use std::net::TcpListener;
+
+        // Answers one request with `response`, reporting whether it carried a token.
+        let serve = |listener: TcpListener, response: String| {
+            std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut authorized = false;
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    authorized |= line.starts_with("Authorization: ");
+                    line.clear();
+                }
+                stream.write_all(response.as_bytes()).unwrap();
+                authorized
+            })
+        };
+        let other = TcpListener::bind("127.0.0.1:0").unwrap();
+        let other_port = other.local_addr().unwrap().port();
+        let moved = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/repos/o/r", moved.local_addr().unwrap());
+        let first = serve(
+            moved,
+            format!(
+                "HTTP/1.1 301 Moved Permanently\r\nLocation: http://localhost:{}/repositories/1\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                other_port
+            ),
+        );
+        let second = serve(
+            other,
+            "HTTP/1.1 200 OK\r\nContent-Length: 15\r\nConnection: close\r\n\r\n{\"name\":\"main\"}"
+                .to_string(),
+        );
+
+        let client = Client {
+            token: Some("secret".to_string()),
+            proxy: None,
+            cache_dir: None,
+            http: tls::HttpConfig::default(),
+        };
+        let (status, body) = client.request(&url).unwrap();
+        assert_eq!(status, 200);
+        assert_eq!(body.get("name").and_then(Value::as_str), Some("main"));
+        assert!(first.join().unwrap());
+        assert!(!second.join().unwrap());
+    }
+
+    #[test]
+    fn test_branch_url() {
+        let github = Hosted::GitHub {
+            api_url: "https://api.github.com".to_string(),
+            path: "owner/repo".to_string(),
+        };
+        assert_eq!(
+            github.branch_url("release/1.0"),
+            "https://api.github.com/repos/owner/repo/branches/release%2F1.0"
+        );
+        assert_eq!(
+            github.branch_url("fix#1?x"),
+            "https://api.github.com/repos/owner/repo/branches/fix%231%3Fx"
+        );
+        let gitlab = Hosted::GitLab {
+            api_url: "https://gitlab.com/api/v4".to_string(),
+            path: "group/repo".to_string(),
+        };
+        assert_eq!(
+            gitlab.branch_url("release/1.0"),
+            "https://gitlab.com/api/v4/projects/group%2Frepo/protected_branches/release%2F1.0"
+        );
+    }
+
+    #[test]
+    fn test_hosted_from_url() {
+        let url = gix::url::parse("git@github.com:owner/repo.git".into()).unwrap();
+        assert_eq!(
+            Hosted::from_url(&url, &[]),
+            Some(Hosted::GitHub {
+                api_url: "https://api.github.com".to_string(),
+                path: "owner/repo".to_string()
+            })
+        );
+        let url = gix::url::parse("https://gitlab.com/group/sub/repo".into()).unwrap();
+        assert_eq!(
+            Hosted::from_url(&url, &[]),
+            Some(Hosted::GitLab {
+                api_url: "https://gitlab.com/api/v4".to_string(),
+                path: "group/sub/repo".to_string()
+            })
+        );
+        let url = gix::url::parse("https://example.com/repo.git".into()).unwrap();
+        assert_eq!(Hosted::from_url(&url, &[]), None);
+        assert_eq!(
+            Hosted::from_url(
+                &url,
+                &[HostProfile {
+                    host: "example.com".to_string(),
+                    api: Some("github".to_string()),
+                    ..HostProfile::default()
+                }]
+            ),
+            Some(Hosted::GitHub {
+                api_url: "https://example.com/api/v3".to_string(),
+                path: "repo".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_gh_repo() {
+        assert_eq!(
+            parse_gh_repo("mono0x/git-default-branch", "github.com"),
+            Some(Hosted::GitHub {
+                api_url: "https://api.github.com".to_string(),
+                path: "mono0x/git-default-branch".to_string(),
+            })
+        );
+        let hosted = parse_gh_repo("ghe.example.com/org/repo", "github.com").unwrap();
+        assert_eq!(
+            hosted,
+            Hosted::GitHub {
+                api_url: "https://ghe.example.com/api/v3".to_string(),
+                path: "org/repo".to_string(),
+            }
+        );
+        assert_eq!(hosted.host(), "ghe.example.com");
+        assert_eq!(api_host("https://api.github.com"), "github.com");
+        assert_eq!(parse_gh_repo("repo", "github.com"), None);
+        assert_eq!(parse_gh_repo("/repo", "github.com"), None);
+    }
+
+    #[test]
+    fn test_gh_default_branch_fallback() {
+        let options = crate::testutil::options();
+        assert!(options.or_fallback(ask_hosted(None, &options)).is_err());
+        let fallback = resolve::Options {
+            fallback: Some("main".to_string()),
+            ..options.clone()
+        };
+        assert_eq!(
+            fallback.or_fallback(ask_hosted(None, &fallback)).unwrap(),
+            resolve::Resolved::new("main".to_string(), "fallback")
+        );
+        let invalid = resolve::Options {
+            fallback: Some("bad..name".to_string()),
+            validate: true,
+            ..options
+        };
+        assert!(invalid.or_fallback(ask_hosted(None, &invalid)).is_err());
+    }
+
+    #[test]
+    fn test_parse_branch_names() {
+        let value = json::parse(
+            r#"[{"name":"main","protected":true},{"name":"release/*","push_access_levels":[]}]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_branch_names(&value).unwrap(),
+            vec!["main".to_string(), "release/*".to_string()]
+        );
+        assert!(parse_branch_names(&json::parse(r#"{"message":"Not Found"}"#).unwrap()).is_err());
+        assert!(parse_branch_names(&json::parse(r#"[{"id":1}]"#).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_parse_protection() {
+        let details = json::parse(
+            r#"{"required_pull_request_reviews":{"required_approving_review_count":2},"allow_force_pushes":{"enabled":false}}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            parse_github_protection(&details),
+            Protection {
+                protected: true,
+                required_reviews: Some(2),
+                force_push_blocked: Some(true)
+            }
+        );
+        assert_eq!(
+            parse_github_protection(&Value::Null),
+            Protection {
+                protected: true,
+                ..Protection::default()
+            }
+        );
+
+        let details = json::parse(r#"{"name":"main","allow_force_push":true}"#).unwrap();
+        assert_eq!(
+            parse_gitlab_protection(&details).force_push_blocked,
+            Some(false)
+        );
+    }
+}
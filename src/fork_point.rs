@@ -0,0 +1,125 @@
+use crate::git::{self, git, run_dir};
+use crate::{pr_base, resolve};
+use std::path::Path;
+
+#[derive(clap::Args)]
+pub struct ForkPointArgs {
+    /// Branch or commit to find the fork point of
+    #[arg(default_value = "HEAD")]
+    rev: String,
+
+    /// Print the plain merge base, without consulting the default branch's reflog for commits it
+    /// has since dropped
+    #[arg(long)]
+    no_reflog: bool,
+
+    /// Compare with the release branch the work is built on, as `pr-base` picks it, rather than
+    /// always the default branch
+    #[arg(long)]
+    nearest: bool,
+}
+
+pub fn run(
+    args: &ForkPointArgs,
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let commit = fork_point(
+        Path::new(dir),
+        &args.rev,
+        !args.no_reflog,
+        args.nearest,
+        remote,
+        options,
+    )?;
+    println!("{}", commit);
+    Ok(())
+}
+
+/// Returns the commit where `rev` forked from the default branch, or with `nearest` from the
+/// branch [`pr_base::nearest`] picks, compared as [`pr_base::compared_ref`] does.
+///
+/// With `reflog`, `git merge-base --fork-point` also finds the fork point when the default branch
+/// was rebased or force-pushed after `rev` forked from it. The plain merge base is used when the
+/// reflog does not reach back that far, as after a fresh clone.
+pub fn fork_point(
+    dir: &Path,
+    rev: &str,
+    reflog: bool,
+    nearest: bool,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    let target = match nearest {
+        true => pr_base::nearest(&repo, rev, remote, options)?,
+        false => resolve::resolve(&repo, remote, options)?,
+    };
+    let base = pr_base::compared_ref(&repo, remote, &target);
+    let dir = run_dir(&repo);
+    if reflog
+        && let Ok(commit) = git(
+            dir,
+            &["merge-base", "--fork-point", "--end-of-options", &base, rev],
+        )
+    {
+        return Ok(commit);
+    }
+    git(dir, &["merge-base", "--end-of-options", &base, rev]).map_err(|e| {
+        log::debug!("{}", e);
+        format!("{} has no history in common with {}", rev, target).into()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+
+    #[test]
+    fn test_fork_point() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        commit(tmp.path(), "base");
+        git(tmp.path(), &["checkout", "-q", "-b", "feature"]).unwrap();
+        commit(tmp.path(), "feature 1");
+        git(tmp.path(), &["checkout", "-q", "main"]).unwrap();
+        commit(tmp.path(), "main 1");
+        let base = git(tmp.path(), &["rev-parse", "main"]).unwrap();
+        let forked = git(tmp.path(), &["rev-parse", "main~"]).unwrap();
+
+        let find = |rev, reflog| fork_point(tmp.path(), rev, reflog, false, "origin", &options());
+        assert_eq!(find("feature", true).unwrap(), forked);
+        assert_eq!(find("feature", false).unwrap(), forked);
+        assert_eq!(find("HEAD", true).unwrap(), base);
+
+        // Rewriting the default branch leaves the reflog to tell where the branch forked.
+        git(tmp.path(), &["reset", "-q", "--hard", "main~"]).unwrap();
+        git(tmp.path(), &["commit", "-q", "--amend", "-m", "rewritten"]).unwrap();
+        assert_eq!(find("feature", true).unwrap(), forked);
+        let initial = git(tmp.path(), &["rev-parse", "main~"]).unwrap();
+        assert_eq!(find("feature", false).unwrap(), initial);
+
+        git(
+            tmp.path(),
+            &["checkout", "-q", "-b", "release/2", "feature"],
+        )
+        .unwrap();
+        commit(tmp.path(), "release");
+        let release = git(tmp.path(), &["rev-parse", "HEAD"]).unwrap();
+        git(tmp.path(), &["checkout", "-q", "-b", "hotfix"]).unwrap();
+        commit(tmp.path(), "hotfix");
+        let nearest = fork_point(tmp.path(), "HEAD", true, true, "origin", &options());
+        assert_eq!(nearest.unwrap(), release);
+        assert_eq!(find("HEAD", true).unwrap(), forked);
+
+        git(tmp.path(), &["checkout", "-q", "--orphan", "unrelated"]).unwrap();
+        commit(tmp.path(), "unrelated");
+        assert_eq!(
+            find("unrelated", true).unwrap_err().to_string(),
+            "unrelated has no history in common with main"
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::scan::{self, DiscoveryArgs};
+use std::path::PathBuf;
+use std::process::Command;
+
+/// Returns the ghq roots from `GHQ_ROOT`, then `ghq.root` in the global git config, then `~/ghq`.
+pub fn roots() -> Vec<PathBuf> {
+    if let Some(roots) = std::env::var_os("GHQ_ROOT").filter(|roots| !roots.is_empty()) {
+        return std::env::split_paths(&roots).collect();
+    }
+    let configured = Command::new("git")
+        .args(["config", "--global", "--path", "--get-all", "ghq.root"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(PathBuf::from)
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    if !configured.is_empty() {
+        return configured;
+    }
+    std::env::home_dir()
+        .map(|home| vec![home.join("ghq")])
+        .unwrap_or_default()
+}
+
+/// Returns every clone under the ghq roots, labelled `host/owner/repo` like `ghq list`.
+pub fn repositories() -> Vec<(String, PathBuf)> {
+    roots()
+        .iter()
+        .flat_map(|root| repositories_in(root.clone()))
+        .collect()
+}
+
+fn repositories_in(root: PathBuf) -> Vec<(String, PathBuf)> {
+    let options = DiscoveryArgs {
+        skip_nested: true,
+        ..DiscoveryArgs::default()
+    };
+    scan::find_repositories(&root, &options)
+        .into_iter()
+        .filter_map(|path| {
+            let label = path
+                .strip_prefix(&root)
+                .ok()?
+                .to_string_lossy()
+                .replace('\\', "/");
+            Some((label, path))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::init_repo;
+    use std::fs;
+
+    #[test]
+    fn test_repositories_in() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in [
+            "github.com/owner/a",
+            "github.com/owner/a/sub",
+            "example.com/x/y",
+        ] {
+            let dir = tmp.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            init_repo(&dir, "main");
+        }
+
+        let labels = repositories_in(tmp.path().to_path_buf())
+            .into_iter()
+            .map(|(label, _)| label)
+            .collect::<Vec<_>>();
+        assert_eq!(labels, vec!["example.com/x/y", "github.com/owner/a"]);
+    }
+}
@@ -0,0 +1,119 @@
+use crate::{paths, redact};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How long to keep retrying a git command that found a ref or config file locked.
+const LOCK_RETRY_BUDGET: Duration = Duration::from_secs(2);
+
+/// Runs `git` in `dir` and returns its trimmed stdout, failing with its stderr on a non-zero exit.
+pub fn git(dir: &Path, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    log::debug!("running git {} in {}", args.join(" "), dir.display());
+    let output = output_retrying_locks(
+        Command::new("git")
+            .args(args)
+            .current_dir(paths::simplify(dir)),
+    )?;
+    if !output.status.success() {
+        return Err(redact::redact(&format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+        .into());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Runs `command`, retrying with backoff while it fails because another git process holds a lock,
+/// as a concurrent fetch does on the remote HEAD.
+///
+/// Git removes its own lock file whether it succeeds or not, so waiting is all it takes. A lock
+/// left behind by a crashed process looks the same as one in use, so it is reported once the
+/// budget runs out rather than removed.
+fn output_retrying_locks(command: &mut Command) -> io::Result<Output> {
+    retrying_locks(command, LOCK_RETRY_BUDGET)
+}
+
+fn retrying_locks(command: &mut Command, budget: Duration) -> io::Result<Output> {
+    let start = Instant::now();
+    let mut delay = Duration::from_millis(25);
+    loop {
+        let output = command.output()?;
+        if output.status.success() || !is_lock_error(&output.stderr) || start.elapsed() >= budget {
+            return Ok(output);
+        }
+        log::debug!("a lock is held; retrying in {:?}", delay);
+        thread::sleep(delay.min(budget.saturating_sub(start.elapsed())));
+        delay *= 2;
+    }
+}
+
+/// Whether git failed on a lock file another process holds.
+fn is_lock_error(stderr: &[u8]) -> bool {
+    let stderr = String::from_utf8_lossy(stderr);
+    stderr.contains(".lock': File exists") || stderr.contains("cannot lock ref")
+}
+
+/// Opens the repository containing `dir`, honouring `GIT_DIR`, `GIT_WORK_TREE` and
+/// `GIT_CEILING_DIRECTORIES` as git does. Like git, it searches upwards from the physical
+/// directory, so a symlink into another repository finds that repository rather than one around
+/// the symlink. A `\\?\` path is simplified first, so the paths the repository reports are in
+/// the form git itself would print.
+#[allow(clippy::result_large_err)]
+pub fn discover(dir: impl AsRef<Path>) -> Result<gix::Repository, gix::discover::Error> {
+    let dir = dir.as_ref();
+    let dir = paths::canonicalize(dir).unwrap_or_else(|_| paths::simplify(dir).into_owned());
+    gix::discover_with_environment_overrides(dir)
+}
+
+/// Returns the canonical root of the repository containing `dir`: its work tree, or the git
+/// directory of a bare repository.
+pub fn root(dir: impl AsRef<Path>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let repo = discover(dir)?;
+    Ok(paths::canonicalize(run_dir(&repo))?)
+}
+
+/// Returns the directory git commands for `repo` should run in.
+pub fn run_dir(repo: &gix::Repository) -> &Path {
+    repo.workdir().unwrap_or(repo.git_dir())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo};
+    use std::fs;
+
+    #[test]
+    fn test_retrying_locks() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let lock = tmp.path().join(".git/refs/heads/other.lock");
+        let mut update = Command::new("git");
+        update
+            .args(["update-ref", "refs/heads/other", "HEAD"])
+            .current_dir(tmp.path());
+
+        // A lock that stays is reported once the budget runs out, and left in place.
+        fs::write(&lock, "").unwrap();
+        let output = retrying_locks(&mut update, Duration::from_millis(100)).unwrap();
+        assert!(!output.status.success());
+        assert!(is_lock_error(&output.stderr));
+        assert!(lock.exists());
+
+        // One released meanwhile is waited for.
+        let releasing = lock.clone();
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            fs::remove_file(releasing).unwrap();
+        });
+        let output = retrying_locks(&mut update, Duration::from_secs(5)).unwrap();
+        release.join().unwrap();
+        assert!(output.status.success());
+        assert!(!lock.exists());
+    }
+}
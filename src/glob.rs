@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Expands a leading `~` and any `*`, `?` or `[...]` components of `pattern`, so batch mode
+/// works the same on shells that do not expand globs themselves. Matches are sorted; a pattern
+/// without wildcards is returned unchanged even if it does not exist.
+pub fn expand(pattern: &str) -> Vec<PathBuf> {
+    let path = expand_home(pattern);
+    if !path.components().any(|c| is_pattern(&c)) {
+        return vec![path];
+    }
+
+    let mut candidates = vec![PathBuf::new()];
+    for component in path.components() {
+        if !is_pattern(&component) {
+            for candidate in &mut candidates {
+                candidate.push(component);
+            }
+            continue;
+        }
+        let glob = component.as_os_str().to_string_lossy();
+        candidates = candidates
+            .iter()
+            .flat_map(|dir| matching_entries(dir, &glob))
+            .collect();
+    }
+    candidates.sort();
+    candidates
+}
+
+/// Expands a leading `~` of `pattern` to the home directory.
+pub fn expand_home(pattern: &str) -> PathBuf {
+    let rest = match pattern.strip_prefix('~') {
+        Some("") => "",
+        Some(rest) if rest.starts_with(['/', std::path::MAIN_SEPARATOR]) => &rest[1..],
+        _ => return PathBuf::from(pattern),
+    };
+    match std::env::home_dir() {
+        Some(home) => home.join(rest),
+        None => PathBuf::from(pattern),
+    }
+}
+
+fn is_pattern(component: &Component) -> bool {
+    matches!(component, Component::Normal(name)
+        if name.to_string_lossy().contains(['*', '?', '[']))
+}
+
+fn matching_entries(dir: &Path, glob: &str) -> Vec<PathBuf> {
+    let read_dir = if dir.as_os_str().is_empty() {
+        fs::read_dir(".")
+    } else {
+        fs::read_dir(dir)
+    };
+    let Ok(entries) = read_dir else {
+        return Vec::new();
+    };
+    entries
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            // Like shells, wildcards do not match hidden entries.
+            !name.starts_with('.')
+                && gix::glob::wildmatch(
+                    glob.into(),
+                    name.as_ref().into(),
+                    gix::glob::wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+                )
+        })
+        .map(|entry| dir.join(entry.file_name()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expand() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in [
+            "a/service-x",
+            "a/service-y",
+            "a/other",
+            "b/service-z",
+            ".c/service-w",
+        ] {
+            fs::create_dir_all(tmp.path().join(name)).unwrap();
+        }
+        let root = tmp.path().to_str().unwrap();
+
+        assert_eq!(
+            expand(&format!("{}/*/service-*", root)),
+            vec![
+                tmp.path().join("a/service-x"),
+                tmp.path().join("a/service-y"),
+                tmp.path().join("b/service-z"),
+            ]
+        );
+        assert_eq!(
+            expand(&format!("{}/a/service-[x]", root)),
+            vec![tmp.path().join("a/service-x")]
+        );
+        assert!(expand(&format!("{}/missing/*", root)).is_empty());
+        assert_eq!(
+            expand(&format!("{}/missing", root)),
+            vec![tmp.path().join("missing")]
+        );
+    }
+
+    #[test]
+    fn test_expand_home() {
+        let home = std::env::home_dir().unwrap();
+        assert_eq!(expand_home("~"), home);
+        assert_eq!(expand_home("~/src"), home.join("src"));
+        assert_eq!(expand_home("~user/src"), PathBuf::from("~user/src"));
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{git, resolve};
+use std::io::{self, Read};
+
+#[derive(clap::Args)]
+pub struct GuardArgs {
+    /// Check the refs being pushed, as a pre-push hook reads them from stdin, instead of the
+    /// current branch
+    #[arg(long)]
+    pre_push: bool,
+}
+
+/// Fails when the current branch, or with `--pre-push` a pushed ref, is the default branch, so
+/// a hook can stop direct commits and pushes to it.
+pub fn run(
+    args: &GuardArgs,
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    let default = resolve::resolve(&repo, remote, options)?;
+    if args.pre_push {
+        let mut input = String::new();
+        io::stdin().read_to_string(&mut input)?;
+        return Ok(check_push(&input, &default)?);
+    }
+    let current = repo.head_name()?;
+    if current.is_some_and(|name| name.shorten() == default.as_str()) {
+        return Err(format!(
+            "Refusing to work on the default branch {}; switch to another branch, or bypass \
+             the hook with --no-verify",
+            default
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Checks the `<local ref> <local sha> <remote ref> <remote sha>` lines git passes to pre-push
+/// hooks. Deleting the default branch is left to the server to refuse.
+fn check_push(input: &str, default: &str) -> Result<(), String> {
+    let target = format!("refs/heads/{}", default);
+    let pushed = input.lines().any(|line| {
+        let fields = line.split_whitespace().collect::<Vec<_>>();
+        matches!(fields.as_slice(), [_, local_sha, remote_ref, _]
+            if *remote_ref == target && local_sha.bytes().any(|b| b != b'0'))
+    });
+    if pushed {
+        return Err(format!(
+            "Refusing to push to the default branch {}; push a branch and open a pull request, \
+             or bypass the hook with --no-verify",
+            default
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::git;
+    use crate::testutil::{commit, init_repo, options};
+
+    #[test]
+    fn test_guard() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let dir = tmp.path().to_str().unwrap();
+        let args = GuardArgs { pre_push: false };
+
+        assert!(run(&args, dir, "origin", &options()).is_err());
+        git(tmp.path(), &["checkout", "-q", "-b", "feature"]).unwrap();
+        assert!(run(&args, dir, "origin", &options()).is_ok());
+    }
+
+    #[test]
+    fn test_check_push() {
+        let sha = "1".repeat(40);
+        let zero = "0".repeat(40);
+        let line = |remote_ref: &str, local_sha: &str| {
+            format!("refs/heads/x {} {} {}\n", local_sha, remote_ref, zero)
+        };
+        assert!(check_push(&line("refs/heads/feature", &sha), "main").is_ok());
+        assert!(check_push(&line("refs/heads/main", &zero), "main").is_ok());
+        assert!(
+            check_push(
+                &(line("refs/heads/feature", &sha) + line("refs/heads/main", &sha).as_str()),
+                "main"
+            )
+            .is_err()
+        );
+    }
+}
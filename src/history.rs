@@ -0,0 +1,360 @@
+use crate::git::{git, run_dir};
+use crate::resolve;
+use gix::bstr::ByteSlice;
+use std::path::Path;
+
+/// A change of the remote HEAD recorded in its reflog.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    /// When the change was made, in seconds since the Unix epoch.
+    pub time: i64,
+    /// The branch the remote HEAD pointed to afterwards, or `None` when it was deleted or the
+    /// reflog does not tell.
+    pub branch: Option<String>,
+}
+
+/// Reports what the default branch of the repository at `path` was at `when`, a date git
+/// understands, like `2024-01-31` or `2.weeks.ago`, or a revision, for the time it was committed.
+pub fn at(
+    path: impl AsRef<Path>,
+    when: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<resolve::Resolved, Box<dyn std::error::Error>> {
+    let repo = resolve::open(path)?;
+    let time = time(&repo, when)?;
+    let entries = entries(&repo, remote, options)?;
+    let Some(entry) = entries.iter().rev().find(|entry| entry.time <= time) else {
+        return Err(format!(
+            "The reflog of the remote HEAD does not reach back to {}",
+            when
+        )
+        .into());
+    };
+    let branch = entry.branch.clone().ok_or_else(|| {
+        format!(
+            "The reflog does not tell which branch the remote HEAD pointed to at {}",
+            when
+        )
+    })?;
+    Ok(resolve::Resolved {
+        branch,
+        provenance: "reflog",
+    })
+}
+
+/// Returns the branch the remote HEAD of the repository at `path` last pointed to before `current`,
+/// the default branch now, or `None` if its reflog records no other.
+pub fn previous(
+    path: impl AsRef<Path>,
+    current: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let repo = resolve::open(path)?;
+    Ok(entries(&repo, remote, options)?
+        .into_iter()
+        .rev()
+        .filter_map(|entry| entry.branch)
+        .find(|branch| branch != current))
+}
+
+/// Returns the seconds since the Unix epoch `when` stands for: the commit time of a revision, or
+/// else a date as `git log --since` reads it. Git takes what it cannot read for now, so anything
+/// read as now but not written as such is refused.
+fn time(repo: &gix::Repository, when: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let dir = run_dir(repo);
+    let commit = format!("{}^{{commit}}", when);
+    if let Ok(time) = git(
+        dir,
+        &["show", "-s", "--format=%ct", "--end-of-options", &commit],
+    ) {
+        return Ok(time.parse()?);
+    }
+    let since = format!("--since={}", when);
+    let ages = git(dir, &["rev-parse", &since, "--since=now"])?;
+    let mut ages = ages
+        .lines()
+        .map(|age| age.strip_prefix("--max-age=")?.parse::<i64>().ok());
+    match (ages.next().flatten(), ages.next().flatten()) {
+        (Some(time), Some(now))
+            if time != now || ["now", "today"].contains(&when.trim().to_lowercase().as_str()) =>
+        {
+            Ok(time)
+        }
+        _ => Err(format!("Not a date or revision: {}", when).into()),
+    }
+}
+
+/// Lists the changes of `refs/remotes/<remote>/HEAD`, oldest first, or of `HEAD` in a bare
+/// repository without one, where HEAD names the default branch.
+///
+/// Git records only the commit a symbolic ref resolved to, so a change is attributed to a branch
+/// by its message when git-default-branch wrote it, and otherwise to the one branch that was at
+/// that commit at the time.
+pub fn entries(
+    repo: &gix::Repository,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    let remote = options.for_repo(repo, remote).remotes(remote)[0].to_string();
+    let head = format!("refs/remotes/{}/HEAD", remote);
+    let mut lines = reflog(repo, &head)?;
+    let branches = if lines.is_empty() && repo.is_bare() {
+        lines = reflog(repo, "HEAD")?;
+        resolve::local_branches(repo)?
+            .into_iter()
+            .map(|branch| (format!("refs/heads/{}", branch), branch))
+            .collect::<Vec<_>>()
+    } else {
+        resolve::remote_branches(repo, &remote)?
+            .into_iter()
+            .map(|branch| (resolve::tracking_ref(repo, &remote, &branch), branch))
+            .collect::<Vec<_>>()
+    };
+    let logs = branches
+        .into_iter()
+        .map(|(name, branch)| {
+            let current = repo
+                .find_reference(name.as_str())
+                .ok()
+                .and_then(|reference| reference.try_id().map(|id| id.detach()));
+            Ok((reflog(repo, &name)?, current, branch))
+        })
+        .collect::<Result<Vec<_>, Box<dyn std::error::Error>>>()?;
+    Ok(lines
+        .into_iter()
+        .map(|line| {
+            let branch = named_branch(&line.message, &remote).or_else(|| {
+                let mut at = logs
+                    .iter()
+                    .filter(|(log, current, _)| {
+                        value_at(log, *current, line.time) == Some(line.new_oid)
+                    })
+                    .map(|(_, _, branch)| branch);
+                at.next().filter(|_| at.next().is_none()).cloned()
+            });
+            Entry {
+                time: line.time,
+                branch: branch.filter(|_| !line.new_oid.is_null()),
+            }
+        })
+        .collect())
+}
+
+/// A reflog entry, as far as telling branches apart needs.
+struct Line {
+    time: i64,
+    previous_oid: gix::ObjectId,
+    new_oid: gix::ObjectId,
+    message: String,
+}
+
+/// Reads the reflog of `name`, oldest first; empty when there is none.
+fn reflog(repo: &gix::Repository, name: &str) -> Result<Vec<Line>, Box<dyn std::error::Error>> {
+    let mut buf = Vec::new();
+    let Some(lines) = repo.refs.reflog_iter(name, &mut buf)? else {
+        return Ok(Vec::new());
+    };
+    Ok(lines
+        .filter_map(Result::ok)
+        .map(|line| Line {
+            time: line.signature.seconds(),
+            previous_oid: line.previous_oid(),
+            new_oid: line.new_oid(),
+            message: line.message.to_str_lossy().into_owned(),
+        })
+        .collect())
+}
+
+/// The commit a ref was at by `time`, from its reflog, or `current` when it has none, as for the
+/// branches a clone packs without logging them.
+fn value_at(log: &[Line], current: Option<gix::ObjectId>, time: i64) -> Option<gix::ObjectId> {
+    match log.iter().rev().find(|line| line.time <= time) {
+        Some(line) => Some(line.new_oid),
+        None => match log.first() {
+            Some(first) => Some(first.previous_oid).filter(|id| !id.is_null()),
+            None => current,
+        },
+    }
+}
+
+/// The branch a reflog message names: one [`resolve::write_remote_head`] wrote, or a checkout
+/// in a bare repository's HEAD.
+fn named_branch(message: &str, remote: &str) -> Option<String> {
+    let suffix = format!(" is the default branch of {}", remote);
+    message
+        .strip_prefix("git-default-branch: ")
+        .and_then(|rest| rest.strip_suffix(&suffix))
+        .or_else(|| {
+            message
+                .strip_prefix("checkout: moving from ")?
+                .split_once(" to ")
+                .map(|(_, to)| to)
+        })
+        .map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+    use std::fs;
+
+    /// Writes a reflog line for `name` in `git_dir` at `time`.
+    fn log(git_dir: &Path, name: &str, old: &str, new: &str, time: i64, message: &str) {
+        let path = git_dir.join("logs").join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let mut content = fs::read_to_string(&path).unwrap_or_default();
+        content.push_str(&format!(
+            "{} {} Test <test@example.com> {} +0000\t{}\n",
+            old, new, time, message
+        ));
+        fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_at() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "master");
+        commit(&repo_dir, "initial");
+        git(&repo_dir, &["checkout", "-q", "-b", "main"]).unwrap();
+        commit(&repo_dir, "main");
+        git(
+            tmp.path(),
+            &[
+                "clone",
+                "-q",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+
+        // A clone logs the remote HEAD but packs the branches without logging them.
+        git(&clone_dir, &["remote", "set-head", "origin", "master"]).unwrap();
+        let found = previous(&clone_dir, "master", "origin", &options()).unwrap();
+        assert_eq!(found.as_deref(), Some("main"));
+
+        let master = git(&clone_dir, &["rev-parse", "origin/master"]).unwrap();
+        let main = git(&clone_dir, &["rev-parse", "origin/main"]).unwrap();
+        let null = "0".repeat(master.len());
+        let logs = clone_dir.join(".git");
+        fs::remove_dir_all(logs.join("logs/refs/remotes")).unwrap();
+        log(
+            &logs,
+            "refs/remotes/origin/master",
+            &null,
+            &master,
+            1_600_001_000,
+            "clone",
+        );
+        log(
+            &logs,
+            "refs/remotes/origin/main",
+            &null,
+            &main,
+            1_600_001_000,
+            "clone",
+        );
+        log(
+            &logs,
+            "refs/remotes/origin/HEAD",
+            &null,
+            &master,
+            1_600_001_000,
+            "clone",
+        );
+        log(
+            &logs,
+            "refs/remotes/origin/HEAD",
+            &master,
+            &main,
+            1_600_002_000,
+            "remote set-head",
+        );
+        let message = "git-default-branch: master is the default branch of origin";
+        log(
+            &logs,
+            "refs/remotes/origin/HEAD",
+            &main,
+            &master,
+            1_600_003_000,
+            message,
+        );
+        git(&clone_dir, &["remote", "set-head", "origin", "main"]).unwrap();
+
+        let at = |when| at(&clone_dir, when, "origin", &options()).map(|r| r.branch);
+        assert_eq!(at("1600001500").unwrap(), "master");
+        assert_eq!(at("1600002000").unwrap(), "main");
+        assert_eq!(at("1600002500").unwrap(), "main");
+        assert_eq!(at("1600003500").unwrap(), "master");
+        assert_eq!(at("now").unwrap(), "main");
+        assert!(at("1600000500").is_err());
+
+        let previous = |current| previous(&clone_dir, current, "origin", &options()).unwrap();
+        assert_eq!(previous("main").as_deref(), Some("master"));
+        assert_eq!(previous("master").as_deref(), Some("main"));
+        assert_eq!(previous("trunk").as_deref(), Some("main"));
+
+        // Both branches were at the same commit, so the reflog cannot tell them apart.
+        log(
+            &logs,
+            "refs/remotes/origin/main",
+            &main,
+            &master,
+            1_600_004_000,
+            "fetch",
+        );
+        log(
+            &logs,
+            "refs/remotes/origin/HEAD",
+            &main,
+            &master,
+            1_600_004_000,
+            "remote set-head",
+        );
+        assert!(at("1600004000").is_err());
+    }
+
+    #[test]
+    fn test_at_bare() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let bare = tmp.path().join("bare.git");
+        git(
+            tmp.path(),
+            &["clone", "-q", "--bare", ".", bare.to_str().unwrap()],
+        )
+        .unwrap();
+        git(&bare, &["branch", "trunk", "main"]).unwrap();
+        let id = git(&bare, &["rev-parse", "main"]).unwrap();
+        log(
+            &bare,
+            "HEAD",
+            &id,
+            &id,
+            1_600_001_000,
+            "checkout: moving from master to main",
+        );
+        log(
+            &bare,
+            "HEAD",
+            &id,
+            &id,
+            1_600_002_000,
+            "checkout: moving from main to trunk",
+        );
+
+        let at = |when| at(&bare, when, "origin", &options()).map(|r| r.branch);
+        assert_eq!(at("1600001500").unwrap(), "main");
+        assert_eq!(at("1600002500").unwrap(), "trunk");
+        assert_eq!(at("now").unwrap(), "trunk");
+        let error = at("garbage").unwrap_err().to_string();
+        assert_eq!(error, "Not a date or revision: garbage");
+    }
+}
@@ -0,0 +1,136 @@
+use crate::git;
+use clap::Subcommand;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Marks hooks written by this tool so they can be updated or removed safely.
+const MARKER: &str = "# Installed by git-default-branch";
+
+/// Git has no post-fetch hook; post-merge runs after every `git pull`.
+const HOOKS: &[&str] = &["post-merge"];
+
+#[derive(Subcommand)]
+pub enum HookAction {
+    /// Install hooks that refresh the remote HEAD after pulling
+    Install {
+        /// Overwrite existing hooks that were not installed by this tool
+        #[arg(long)]
+        force: bool,
+    },
+    /// Remove hooks installed by this tool
+    Uninstall,
+}
+
+pub fn run(
+    action: &HookAction,
+    dir: &str,
+    remote: &str,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    let hooks_dir = hooks_dir(&repo)?;
+
+    match action {
+        HookAction::Install { force } => {
+            if !dry_run {
+                fs::create_dir_all(&hooks_dir)?;
+            }
+            for name in HOOKS {
+                let path = hooks_dir.join(name);
+                if !force && fs::read_to_string(&path).is_ok_and(|s| !s.contains(MARKER)) {
+                    return Err(format!(
+                        "{} already exists; use --force to overwrite it",
+                        path.display()
+                    )
+                    .into());
+                }
+                if dry_run {
+                    println!("Would install {}", path.display());
+                    continue;
+                }
+                fs::write(&path, script(remote))?;
+                make_executable(&path)?;
+                println!("Installed {}", path.display());
+            }
+        }
+        HookAction::Uninstall => {
+            for name in HOOKS {
+                let path = hooks_dir.join(name);
+                if !fs::read_to_string(&path).is_ok_and(|s| s.contains(MARKER)) {
+                    continue;
+                }
+                if dry_run {
+                    println!("Would remove {}", path.display());
+                } else {
+                    fs::remove_file(&path)?;
+                    println!("Removed {}", path.display());
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn hooks_dir(repo: &gix::Repository) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let Some(path) = repo.config_snapshot().trusted_path("core.hooksPath") else {
+        return Ok(repo.common_dir().join("hooks"));
+    };
+    let path = path?;
+    // Relative hook paths are resolved against the directory hooks run in.
+    Ok(repo.workdir().unwrap_or(repo.git_dir()).join(path))
+}
+
+/// The hook asks the remote as `check-changed` does and records its HEAD with `--set-head`, so
+/// the network policy, the reflog message and the audit log apply as for any other change.
+fn script(remote: &str) -> String {
+    format!(
+        "#!/bin/sh\n{}\ngit-default-branch check-changed --remote '{}' --set-head --yes >/dev/null 2>&1 &\n",
+        MARKER,
+        remote.replace('\'', "'\\''")
+    )
+}
+
+#[cfg(unix)]
+fn make_executable(path: &Path) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(not(unix))]
+fn make_executable(_path: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo};
+
+    #[test]
+    fn test_install_and_uninstall() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let dir = tmp.path().to_str().unwrap();
+        let hook = tmp.path().join(".git/hooks/post-merge");
+
+        run(&HookAction::Install { force: false }, dir, "origin", true).unwrap();
+        assert!(!hook.exists());
+        run(&HookAction::Install { force: false }, dir, "origin", false).unwrap();
+        let content = fs::read_to_string(&hook).unwrap();
+        assert!(content.contains(MARKER));
+        assert!(content.contains("check-changed --remote 'origin' --set-head --yes"));
+        // Reinstalling over our own hook does not need --force.
+        run(&HookAction::Install { force: false }, dir, "origin", false).unwrap();
+
+        run(&HookAction::Uninstall, dir, "origin", true).unwrap();
+        assert!(hook.exists());
+        run(&HookAction::Uninstall, dir, "origin", false).unwrap();
+        assert!(!hook.exists());
+
+        fs::write(&hook, "#!/bin/sh\n").unwrap();
+        assert!(run(&HookAction::Install { force: false }, dir, "origin", false).is_err());
+        run(&HookAction::Uninstall, dir, "origin", false).unwrap();
+        assert!(hook.exists());
+    }
+}
@@ -0,0 +1,234 @@
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Language {
+    English,
+    Japanese,
+}
+
+/// Messages by key. `{name}` is replaced with the argument of that name.
+const ENGLISH: &[(&str, &str)] = &[
+    ("hint", "hint: {hint}"),
+    (
+        "no-repositories",
+        "No repositories match the given patterns",
+    ),
+    ("not-a-repository", "Not a Git repository: {message}"),
+    ("remote-not-found", "No such remote: {remote}"),
+    ("invalid-remote", "Invalid remote name: {remote}"),
+    (
+        "invalid-branch",
+        "The resolved name is not a valid branch name: {branch}",
+    ),
+    ("undetermined", "Could not determine default branch"),
+    (
+        "undetermined-local",
+        "Could not determine default branch from local refs",
+    ),
+    ("network", "Could not reach the remote: {error}"),
+    (
+        "not-authoritative",
+        "The remote does not report a default branch and --strict forbids guessing",
+    ),
+    (
+        "hint-not-a-repository",
+        "Run inside a Git repository or pass --dir",
+    ),
+    (
+        "hint-remote-not-found",
+        "Check `git remote -v` or pass --remote",
+    ),
+    (
+        "hint-undetermined",
+        "Run `git remote set-head <remote> --auto`, or pass --fallback <branch>",
+    ),
+    (
+        "hint-network",
+        "Check the connection to the remote, or pass --offline",
+    ),
+    (
+        "hint-not-authoritative",
+        "Run `git remote set-head <remote> --auto` while online, or drop --strict",
+    ),
+    ("diagnose-missing", "{path} does not exist"),
+    (
+        "diagnose-file",
+        "{path} is a file; pass the directory of the repository instead",
+    ),
+    (
+        "diagnose-bare",
+        "{path} looks like a bare repository that Git does not accept; check that it is complete \
+         and owned by you, or list it in safe.directory",
+    ),
+    (
+        "diagnose-ancestor",
+        "{path} is a repository but was not accepted; check GIT_CEILING_DIRECTORIES and \
+         safe.directory",
+    ),
+    (
+        "diagnose-nested-one",
+        "{path} contains 1 repository; use `scan` or --dir '{path}/*'",
+    ),
+    (
+        "diagnose-nested-other",
+        "{path} contains {count} repositories; use `scan` or --dir '{path}/*'",
+    ),
+];
+
+const JAPANESE: &[(&str, &str)] = &[
+    ("hint", "ヒント: {hint}"),
+    (
+        "no-repositories",
+        "指定されたパターンに一致するリポジトリがありません",
+    ),
+    (
+        "not-a-repository",
+        "Git リポジトリではありません: {message}",
+    ),
+    ("remote-not-found", "リモートが見つかりません: {remote}"),
+    ("invalid-remote", "リモート名が不正です: {remote}"),
+    (
+        "invalid-branch",
+        "解決した名前はブランチ名として不正です: {branch}",
+    ),
+    ("undetermined", "デフォルトブランチを特定できませんでした"),
+    (
+        "undetermined-local",
+        "ローカルの参照からデフォルトブランチを特定できませんでした",
+    ),
+    ("network", "リモートに接続できませんでした: {error}"),
+    (
+        "not-authoritative",
+        "リモートがデフォルトブランチを報告しておらず、--strict のため推測できません",
+    ),
+    (
+        "hint-not-a-repository",
+        "Git リポジトリの中で実行するか、--dir を指定してください",
+    ),
+    (
+        "hint-remote-not-found",
+        "`git remote -v` を確認するか、--remote を指定してください",
+    ),
+    (
+        "hint-undetermined",
+        "`git remote set-head <remote> --auto` を実行するか、--fallback <branch> を指定してください",
+    ),
+    (
+        "hint-network",
+        "リモートへの接続を確認するか、--offline を指定してください",
+    ),
+    (
+        "hint-not-authoritative",
+        "オンラインのときに `git remote set-head <remote> --auto` を実行するか、--strict を外してください",
+    ),
+    ("diagnose-missing", "{path} は存在しません"),
+    (
+        "diagnose-file",
+        "{path} はファイルです。リポジトリのディレクトリを指定してください",
+    ),
+    (
+        "diagnose-bare",
+        "{path} はベアリポジトリのようですが、Git に受け入れられませんでした。欠けている\
+         ファイルがなく自分が所有していることを確認するか、safe.directory に追加してください",
+    ),
+    (
+        "diagnose-ancestor",
+        "{path} はリポジトリですが、受け入れられませんでした。GIT_CEILING_DIRECTORIES と \
+         safe.directory を確認してください",
+    ),
+    (
+        "diagnose-nested-one",
+        "{path} にはリポジトリが 1 個あります。`scan` か --dir '{path}/*' を使ってください",
+    ),
+    (
+        "diagnose-nested-other",
+        "{path} にはリポジトリが {count} 個あります。`scan` か --dir '{path}/*' を使ってください",
+    ),
+];
+
+/// Picks the language from `LC_ALL`, `LC_MESSAGES` and `LANG`, the first that is set winning as
+/// in gettext. Tests always get English, since they assert on messages.
+fn language() -> Language {
+    static LANGUAGE: OnceLock<Language> = OnceLock::new();
+    *LANGUAGE.get_or_init(|| {
+        if cfg!(test) {
+            return Language::English;
+        }
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        if locale.starts_with("ja") {
+            Language::Japanese
+        } else {
+            Language::English
+        }
+    })
+}
+
+fn catalog(language: Language) -> &'static [(&'static str, &'static str)] {
+    match language {
+        Language::English => ENGLISH,
+        Language::Japanese => JAPANESE,
+    }
+}
+
+fn lookup_in(language: Language, key: &str) -> &'static str {
+    let find = |catalog: &'static [(&str, &'static str)]| {
+        catalog.iter().find(|(k, _)| *k == key).map(|(_, v)| *v)
+    };
+    find(catalog(language))
+        .or_else(|| find(ENGLISH))
+        .unwrap_or_else(|| panic!("no message for {}", key))
+}
+
+/// The message for `key` in the user's language, for messages without arguments.
+pub fn lookup(key: &str) -> &'static str {
+    lookup_in(language(), key)
+}
+
+/// The message for `key` in the user's language, with its placeholders filled in.
+pub fn text(key: &str, args: &[(&str, &dyn Display)]) -> String {
+    format_in(language(), key, args)
+}
+
+fn format_in(language: Language, key: &str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter().fold(
+        lookup_in(language, key).to_string(),
+        |message, (name, value)| message.replace(&format!("{{{}}}", name), &value.to_string()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_catalogs() {
+        let keys = |catalog: &'static [(&'static str, &str)]| {
+            let mut keys = catalog.iter().map(|(k, _)| *k).collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+        assert_eq!(keys(ENGLISH), keys(JAPANESE));
+
+        assert_eq!(
+            format_in(
+                Language::Japanese,
+                "remote-not-found",
+                &[("remote", &"origin")]
+            ),
+            "リモートが見つかりません: origin"
+        );
+        assert_eq!(
+            format_in(
+                Language::English,
+                "diagnose-nested-other",
+                &[("path", &"/src"), ("count", &2)]
+            ),
+            "/src contains 2 repositories; use `scan` or --dir '/src/*'"
+        );
+    }
+}
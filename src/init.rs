@@ -0,0 +1,89 @@
+use crate::git::git;
+use std::path::Path;
+
+#[derive(clap::Args)]
+pub struct InitArgs {
+    /// Write the aliases to the repository configuration instead of the global one
+    #[arg(long)]
+    local: bool,
+
+    /// Also add `switch-default` and `rebase-default` helper aliases
+    #[arg(long)]
+    helpers: bool,
+}
+
+pub fn run(args: &InitArgs, dir: &str, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let exe = std::env::current_exe()?;
+    let scope = if args.local { "--local" } else { "--global" };
+    for (name, value) in aliases(&exe, args.helpers) {
+        if dry_run {
+            println!("Would configure git {} as {}", name, value);
+            continue;
+        }
+        git(
+            Path::new(dir),
+            &["config", scope, &format!("alias.{}", name), &value],
+        )?;
+        println!("Configured git {}", name);
+    }
+    Ok(())
+}
+
+/// Returns the git aliases to configure for the binary at `exe`.
+pub fn aliases(exe: &Path, helpers: bool) -> Vec<(&'static str, String)> {
+    let mut aliases = vec![(
+        "default-branch",
+        format!("!'{}'", exe.display().to_string().replace('\'', "'\\''")),
+    )];
+    if helpers {
+        aliases.push((
+            "switch-default",
+            "!git switch \"$(git default-branch)\"".to_string(),
+        ));
+        aliases.push((
+            "rebase-default",
+            "!git rebase \"$(git default-branch)\"".to_string(),
+        ));
+    }
+    aliases
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo};
+
+    #[test]
+    fn test_aliases() {
+        let aliases = aliases(Path::new("/opt/it's/git-default-branch"), false);
+        assert_eq!(
+            aliases,
+            vec![(
+                "default-branch",
+                "!'/opt/it'\\''s/git-default-branch'".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn test_init_local() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+
+        let args = InitArgs {
+            local: true,
+            helpers: true,
+        };
+        run(&args, tmp.path().to_str().unwrap(), false).unwrap();
+        assert_eq!(
+            git(tmp.path(), &["config", "--local", "alias.switch-default"]).unwrap(),
+            "!git switch \"$(git default-branch)\""
+        );
+        assert!(
+            git(tmp.path(), &["config", "--local", "alias.default-branch"])
+                .unwrap()
+                .starts_with("!'")
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use crate::git::{git, run_dir};
+use std::io::{self, BufRead, IsTerminal, Write};
+
+/// Whether the user can be asked: both stdin and stderr must be terminals.
+pub fn available() -> bool {
+    io::stdin().is_terminal() && io::stderr().is_terminal()
+}
+
+/// Asks which of `candidates`, all existing local branches, is the default branch, offering to
+/// remember the answer as `defaultBranch.candidates` in the repository configuration, which a
+/// dry run only reports.
+pub fn choose(
+    repo: &gix::Repository,
+    candidates: &[&str],
+    dry_run: bool,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let (index, remember) = ask(&mut io::stdin().lock(), &mut io::stderr(), candidates)?;
+    let branch = candidates[index].to_string();
+    if remember && dry_run {
+        eprintln!("Would set defaultBranch.candidates to {}", branch);
+    } else if remember {
+        git(
+            run_dir(repo),
+            &["config", "--local", "defaultBranch.candidates", &branch],
+        )?;
+    }
+    Ok(branch)
+}
+
+/// Asks a yes/no `question`, taking anything but yes, or no answer at all, as no.
+#[cfg(feature = "subprocess")]
+pub fn confirm(question: &str) -> bool {
+    confirm_with(&mut io::stdin().lock(), &mut io::stderr(), question).unwrap_or(false)
+}
+
+fn confirm_with(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    question: &str,
+) -> io::Result<bool> {
+    write!(output, "{} [y/N] ", question)?;
+    output.flush()?;
+    Ok(matches!(read_line(input)?.as_str(), "y" | "Y" | "yes"))
+}
+
+/// Prompts on `output` and reads the answers from `input`. An empty answer picks the first
+/// candidate and does not remember it.
+fn ask(
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+    candidates: &[&str],
+) -> io::Result<(usize, bool)> {
+    writeln!(
+        output,
+        "The remote does not report a default branch, and several candidates exist locally:"
+    )?;
+    for (i, name) in candidates.iter().enumerate() {
+        writeln!(output, "  {}) {}", i + 1, name)?;
+    }
+    let index = loop {
+        write!(output, "Default branch [1]: ")?;
+        output.flush()?;
+        let answer = read_line(input)?;
+        if answer.is_empty() {
+            break 0;
+        }
+        let chosen = answer.parse::<usize>().ok().and_then(|n| n.checked_sub(1));
+        match chosen
+            .filter(|&i| i < candidates.len())
+            .or_else(|| candidates.iter().position(|name| *name == answer))
+        {
+            Some(index) => break index,
+            None => writeln!(output, "Enter a number from 1 to {}", candidates.len())?,
+        }
+    };
+    let remember = confirm_with(
+        input,
+        output,
+        &format!("Remember {} for this repository?", candidates[index]),
+    )?;
+    Ok((index, remember))
+}
+
+fn read_line(input: &mut impl BufRead) -> io::Result<String> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "No answer given",
+        ));
+    }
+    Ok(line.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ask() {
+        let candidates = ["main", "master"];
+        let answer = |input: &str| {
+            let mut output = Vec::new();
+            ask(&mut input.as_bytes(), &mut output, &candidates)
+                .map(|answer| (answer, String::from_utf8(output).unwrap()))
+        };
+        assert_eq!(answer("\n\n").unwrap().0, (0, false));
+        assert_eq!(answer("2\ny\n").unwrap().0, (1, true));
+        assert_eq!(answer("master\nn\n").unwrap().0, (1, false));
+
+        let (choice, output) = answer("3\n1\n\n").unwrap();
+        assert_eq!(choice, (0, false));
+        assert!(output.contains("Enter a number from 1 to 2"));
+        assert!(answer("").is_err());
+    }
+
+    #[test]
+    fn test_confirm() {
+        let confirm = |input: &str| confirm_with(&mut input.as_bytes(), &mut Vec::new(), "Go?");
+        assert!(confirm("yes\n").unwrap());
+        assert!(!confirm("\n").unwrap());
+        assert!(!confirm("sure\n").unwrap());
+        assert!(confirm("").is_err());
+    }
+}
@@ -0,0 +1,659 @@
+//! Resolution through libgit2 rather than gitoxide, with `--libgit2`, for builds that link
+//! libgit2 anyway. The same refs and settings are read and the same strategies tried, but
+//! nothing is written: `--set-head` and `--interactive` are not offered with it.
+
+use crate::resolve::{self, Failure, Options, RemoteRefs, RepoSettings, Resolved, Strategy};
+#[cfg(feature = "scripting")]
+use crate::script;
+use crate::{cache, diagnose, i18n};
+#[cfg(feature = "subprocess")]
+use crate::{plugin, policy};
+use git2::{BranchType, ConfigLevel, Direction, Oid, Repository, RepositoryOpenFlags};
+use std::ffi::OsStr;
+use std::path::Path;
+#[cfg(feature = "subprocess")]
+use std::path::PathBuf;
+
+/// A remote to resolve, as libgit2 sees it.
+struct Remote<'a> {
+    refs: RemoteRefs<'a>,
+    /// Whether a remote of that name is configured.
+    exists: bool,
+    /// Its fetch URL.
+    url: Option<String>,
+}
+
+impl<'a> Remote<'a> {
+    fn new(repo: &Repository, name: &'a str) -> Self {
+        let Ok(found) = repo.find_remote(name) else {
+            return Remote {
+                refs: RemoteRefs::from_refspecs(name, []),
+                exists: false,
+                url: None,
+            };
+        };
+        let refspecs = found
+            .refspecs()
+            .filter(|spec| spec.direction() == Direction::Fetch)
+            .filter_map(|spec| Some((spec.src()?.to_string(), spec.dst()?.to_string())))
+            .collect::<Vec<_>>();
+        Remote {
+            refs: RemoteRefs::from_refspecs(
+                name,
+                refspecs
+                    .iter()
+                    .map(|(src, dst)| (src.as_str(), dst.as_str())),
+            ),
+            exists: true,
+            url: found.url().map(str::to_string),
+        }
+    }
+
+    /// The URL cache entries are keyed by.
+    fn canonical_url(&self, repo: &Repository) -> Option<String> {
+        let url = gix::url::parse(self.url.as_deref()?.into()).ok()?;
+        let dir = repo.workdir().unwrap_or(repo.path());
+        Some(cache::canonical_url_in(&url, dir))
+    }
+}
+
+/// Resolves the default branch of the repository at `path` as [`resolve::run`] does, reading it
+/// with libgit2.
+pub fn resolve(
+    path: &Path,
+    remote: &str,
+    options: &Options,
+) -> Result<Resolved, Box<dyn std::error::Error>> {
+    let repo = open(path)?;
+    let options = &options.with_repo_settings(settings(&repo, &options.remotes(remote), options));
+    let remotes = options.remotes(remote);
+    for remote in &remotes {
+        resolve::validate_remote(remote)?;
+    }
+    let remotes = remotes
+        .into_iter()
+        .map(|remote| Remote::new(&repo, remote))
+        .collect::<Vec<_>>();
+    for strategy in options.strategies() {
+        log::trace!("trying {}", strategy.name());
+        if let Some(branch) = attempt(strategy, &repo, &remotes, options)? {
+            log::trace!("{} found {}", strategy.name(), branch);
+            return Ok(Resolved::new(branch, strategy.name()));
+        }
+    }
+    if remotes.iter().all(|remote| !remote.exists) {
+        return Err(Failure::RemoteNotFound(remotes[0].refs.remote.to_string()).into());
+    }
+    if let Some(error) = remotes
+        .iter()
+        .find_map(|remote| remote.refs.network_error.borrow_mut().take())
+    {
+        return Err(Failure::Network(error).into());
+    }
+    if options.strict {
+        return Err(Failure::NotAuthoritative.into());
+    }
+    Err(Failure::Undetermined(i18n::lookup("undetermined").to_string()).into())
+}
+
+/// Opens the repository containing `path`, honouring `GIT_DIR` and `GIT_CEILING_DIRECTORIES` as
+/// [`crate::git::discover`] does.
+fn open(path: &Path) -> Result<Repository, Box<dyn std::error::Error>> {
+    log::debug!(
+        "discovering the repository from {} with libgit2",
+        path.display()
+    );
+    let repo = match std::env::var_os("GIT_DIR") {
+        Some(_) => Repository::open_from_env(),
+        None => Repository::open_ext(path, RepositoryOpenFlags::FROM_ENV, &[] as &[&OsStr]),
+    }
+    .map_err(|e| Failure::NotARepository {
+        message: e.message().to_string(),
+        suggestion: diagnose::not_a_repository(path),
+    })?;
+    log::debug!("found the repository at {}", repo.path().display());
+    Ok(repo)
+}
+
+/// Reads what [`Options::for_repo`] reads, from the repository's own config files.
+fn settings(repo: &Repository, remotes: &[&str], options: &Options) -> RepoSettings {
+    let levels = local_config(repo);
+    let list = |key: &str| {
+        let mut values = Vec::new();
+        for config in &levels {
+            if let Ok(entries) = config.multivar(key, None) {
+                let _ = entries.for_each(|entry| {
+                    if let Some(value) = entry.value() {
+                        values.push(value.to_string());
+                    }
+                });
+            }
+        }
+        (!values.is_empty()).then(|| resolve::config_list(values))
+    };
+    RepoSettings {
+        host: remotes
+            .iter()
+            .find_map(|remote| repo.find_remote(remote).ok())
+            .and_then(|remote| {
+                let url = gix::url::parse(remote.url()?.into()).ok()?;
+                url.host().map(str::to_string)
+            }),
+        remotes: list("defaultBranch.remote"),
+        candidates: list("defaultBranch.candidates"),
+        release_patterns: list("defaultBranch.releasePatterns"),
+        allow_network: levels
+            .iter()
+            .filter_map(|config| config.get_bool("defaultBranch.allowNetwork").ok())
+            .next_back(),
+        push_remote: options.follows_push().then(|| push_remote(repo)).flatten(),
+    }
+}
+
+/// The repository's own config files, without the global and system ones.
+fn local_config(repo: &Repository) -> Vec<git2::Config> {
+    repo.config()
+        .map(|config| {
+            [ConfigLevel::Local, ConfigLevel::Worktree]
+                .into_iter()
+                .filter_map(|level| config.open_level(level).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The remote `git push` sends the current branch to, from `branch.<name>.pushRemote`,
+/// `remote.pushDefault` or `branch.<name>.remote`, when it is configured by name.
+fn push_remote(repo: &Repository) -> Option<String> {
+    let config = repo.config().ok()?;
+    let branch = repo
+        .find_reference("HEAD")
+        .ok()?
+        .symbolic_target()
+        .and_then(|target| target.strip_prefix("refs/heads/"))
+        .map(str::to_string);
+    let keys = match &branch {
+        Some(branch) => vec![
+            format!("branch.{}.pushRemote", branch),
+            "remote.pushDefault".to_string(),
+            format!("branch.{}.remote", branch),
+        ],
+        None => vec!["remote.pushDefault".to_string()],
+    };
+    keys.iter()
+        .find_map(|key| config.get_string(key).ok())
+        .filter(|name| git2::Remote::is_valid_name(name))
+}
+
+/// Tries `strategy` for each of `remotes` in turn, as [`Strategy`] does with gitoxide.
+fn attempt(
+    strategy: Strategy,
+    repo: &Repository,
+    remotes: &[Remote],
+    options: &Options,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    if strategy == Strategy::LocalNames {
+        let branches = local_branches(repo)?;
+        let tracked = |name: &&str| {
+            remotes
+                .iter()
+                .any(|remote| repo.find_reference(&remote.refs.tracking_ref(name)).is_ok())
+        };
+        return Ok(options
+            .candidates()
+            .into_iter()
+            .find_map(|name| resolve::matching_branch(&branches, name))
+            .or_else(|| {
+                options
+                    .candidates()
+                    .into_iter()
+                    .find(tracked)
+                    .map(str::to_string)
+            }));
+    }
+    for remote in remotes {
+        if let Some(branch) = attempt_remote(strategy, repo, remote, options)? {
+            return Ok(Some(branch));
+        }
+    }
+    Ok(None)
+}
+
+fn attempt_remote(
+    strategy: Strategy,
+    repo: &Repository,
+    remote: &Remote,
+    options: &Options,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let refs = &remote.refs;
+    match strategy {
+        Strategy::RemoteHead => head_branch(repo, refs),
+        Strategy::Cache => Ok(options.cache_ttl().and_then(|ttl| {
+            let cache = cache::Cache::open_default().ok()?;
+            Some(
+                cache
+                    .fresh(&remote.canonical_url(repo)?, ttl)?
+                    .branch
+                    .clone(),
+            )
+        })),
+        #[cfg(feature = "subprocess")]
+        Strategy::SetHead => {
+            if !allowed(remote) {
+                return Ok(None);
+            }
+            let dir = repo.workdir().unwrap_or(repo.path());
+            let branch = match resolve::ls_remote_head(dir, refs.remote, options) {
+                Ok(Some(branch)) => branch,
+                Ok(None) => return Ok(None),
+                Err(error) => {
+                    if remote.exists {
+                        *refs.network_error.borrow_mut() = Some(error);
+                    }
+                    return Ok(None);
+                }
+            };
+            record(repo, remote, &branch, options);
+            Ok(Some(branch))
+        }
+        #[cfg(feature = "subprocess")]
+        Strategy::Plugins => {
+            if !allowed(remote) {
+                return Ok(None);
+            }
+            let (git_dir, workdir) = (normalize(repo.path()), repo.workdir().map(normalize));
+            let branch = plugin::find().iter().find_map(|path| {
+                plugin::ask(
+                    path,
+                    &git_dir,
+                    workdir.as_deref(),
+                    refs.remote,
+                    remote.url.as_deref(),
+                )
+            });
+            if let Some(branch) = &branch {
+                record(repo, remote, branch, options);
+            }
+            Ok(branch)
+        }
+        #[cfg(feature = "scripting")]
+        Strategy::Script => match &options.script {
+            Some(path) => Ok(script::run(path, &script_context(repo, remote)?)?),
+            None => Ok(None),
+        },
+        Strategy::RemoteBranches => {
+            let branches = remote_branches(repo, refs)?;
+            Ok(options
+                .candidates()
+                .into_iter()
+                .chain(resolve::REMOTE_NAMES)
+                .find(|name| branches.iter().any(|branch| branch == name))
+                .map(str::to_string)
+                .or_else(|| match branches.as_slice() {
+                    [only] => Some(only.clone()),
+                    _ => None,
+                }))
+        }
+        Strategy::LocalNames => Ok(None),
+    }
+}
+
+/// Describes `remote` and `repo` for the `script` strategy.
+#[cfg(feature = "scripting")]
+fn script_context(
+    repo: &Repository,
+    remote: &Remote,
+) -> Result<script::Context, Box<dyn std::error::Error>> {
+    let mut refs = Vec::new();
+    for r in repo.references()? {
+        if let Some(name) = r?.name() {
+            refs.push(name.to_string());
+        }
+    }
+    let mut config: Vec<(String, String)> = Vec::new();
+    for level in local_config(repo) {
+        level.entries(None)?.for_each(|entry| {
+            if let Some(key) = entry.name() {
+                config.retain(|(k, _)| k != key);
+                config.push((
+                    key.to_string(),
+                    entry.value().unwrap_or_default().to_string(),
+                ));
+            }
+        })?;
+    }
+    Ok(script::Context {
+        remote: remote.refs.remote.to_string(),
+        url: remote.url.clone(),
+        remote_head: head_branch(repo, &remote.refs)?,
+        refs,
+        branches: local_branches(repo)?,
+        remote_branches: remote_branches(repo, &remote.refs)?,
+        config,
+    })
+}
+
+/// Whether the policy lets `remote` be contacted.
+#[cfg(feature = "subprocess")]
+fn allowed(remote: &Remote) -> bool {
+    let url = remote.exists.then(|| {
+        remote
+            .url
+            .as_deref()
+            .and_then(|url| gix::url::parse(url.into()).ok())
+    });
+    policy::allows_remote_url(remote.refs.remote, url)
+}
+
+/// Caches a branch the remote reported, unless `--no-cache` was given.
+#[cfg(feature = "subprocess")]
+fn record(repo: &Repository, remote: &Remote, branch: &str, options: &Options) {
+    if let Some(url) = remote.canonical_url(repo).filter(|_| !options.no_cache) {
+        cache::record_url(&url, branch);
+    }
+}
+
+/// libgit2 reports directories with a trailing slash, which gitoxide and git leave off.
+#[cfg(feature = "subprocess")]
+fn normalize(path: &Path) -> PathBuf {
+    path.components().collect()
+}
+
+/// The branch the remote HEAD points to, following symbolic refs as far as git would, or the
+/// branch at its commit when it was written detached.
+fn head_branch(
+    repo: &Repository,
+    refs: &RemoteRefs,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let Some(r) = refs
+        .heads
+        .iter()
+        .find_map(|head| repo.find_reference(head).ok())
+    else {
+        log::debug!("{} does not exist", refs.heads.join(", "));
+        return Ok(None);
+    };
+    let head = r.name().unwrap_or("HEAD").to_string();
+    let Some(mut name) = r.symbolic_target().map(str::to_string) else {
+        let id = r.target().ok_or("HEAD is not symbolic")?;
+        log::debug!("{} is detached at {}; looking for a branch there", head, id);
+        return branch_at(repo, refs, id);
+    };
+    log::debug!("{} points to {}", head, name);
+    for _ in 0..resolve::MAX_SYMREF_DEPTH {
+        let next = repo
+            .find_reference(&name)
+            .ok()
+            .and_then(|r| r.symbolic_target().map(str::to_string));
+        let Some(next) = next else {
+            return Ok(Some(refs.branch_name(&name)?));
+        };
+        log::debug!("{} points to {}", name, next);
+        name = next;
+    }
+    log::warn!(
+        "{} leads through more than {} symbolic refs, which is probably a loop",
+        head,
+        resolve::MAX_SYMREF_DEPTH
+    );
+    Ok(None)
+}
+
+/// Finds a branch of the remote whose tip is `id`.
+fn branch_at(
+    repo: &Repository,
+    refs: &RemoteRefs,
+    id: Oid,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    for (name, target) in tracking_refs(repo, refs)? {
+        if target == Some(id) {
+            return Ok(Some(refs.branch_name(&name)?));
+        }
+    }
+    Ok(None)
+}
+
+/// Lists the local branches.
+fn local_branches(repo: &Repository) -> Result<Vec<String>, git2::Error> {
+    let mut branches = Vec::new();
+    for branch in repo.branches(Some(BranchType::Local))? {
+        if let Some(name) = branch?.0.name()? {
+            branches.push(name.to_string());
+        }
+    }
+    Ok(branches)
+}
+
+/// Lists the branches fetched from the remote, by their names there.
+fn remote_branches(
+    repo: &Repository,
+    refs: &RemoteRefs,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut branches = Vec::new();
+    for (name, _) in tracking_refs(repo, refs)? {
+        let branch = refs.branch_name(&name)?;
+        if !branches.contains(&branch) {
+            branches.push(branch);
+        }
+    }
+    Ok(branches)
+}
+
+/// The remote-tracking refs of the remote other than its HEAD, with the commits they point to
+/// directly, in the order of its fetch refspecs.
+fn tracking_refs(
+    repo: &Repository,
+    refs: &RemoteRefs,
+) -> Result<Vec<(String, Option<Oid>)>, git2::Error> {
+    let mut all = Vec::new();
+    for r in repo.references()? {
+        let Ok(r) = r else { continue };
+        if let Some(name) = r.name() {
+            all.push((name.to_string(), r.target()));
+        }
+    }
+    all.sort();
+    let mut found = Vec::new();
+    for prefix in refs.prefixes() {
+        for (name, target) in &all {
+            if name.starts_with(prefix) && !refs.heads.contains(name) {
+                found.push((name.clone(), *target));
+            }
+        }
+    }
+    Ok(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git;
+    use crate::testutil::{commit, init_repo, options};
+    use std::fs;
+
+    /// Resolves with both backends, which must agree.
+    fn both(path: &Path, options: &Options) -> Result<String, String> {
+        let gix =
+            resolve::run(path.to_str().unwrap(), "origin", options).map_err(|e| e.to_string());
+        let git2 = resolve(path, "origin", options)
+            .map(|resolved| resolved.branch)
+            .map_err(|e| e.to_string());
+        assert_eq!(gix, git2);
+        git2
+    }
+
+    #[test]
+    fn test_resolve() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "trunk");
+        commit(&repo_dir, "initial");
+        git::git(&repo_dir, &["branch", "other"]).unwrap();
+        git::git(
+            tmp.path(),
+            &[
+                "clone",
+                "-q",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        let git = |args: &[&str]| git::git(&clone_dir, args).unwrap();
+        let remote_head = Options {
+            strategies: vec![Strategy::RemoteHead],
+            ..options()
+        };
+        assert_eq!(both(&clone_dir, &options()).unwrap(), "trunk");
+
+        // Detached, the branch at its commit answers.
+        git(&[
+            "update-ref",
+            "--no-deref",
+            "refs/remotes/origin/HEAD",
+            "origin/other",
+        ]);
+        git(&["update-ref", "-d", "refs/remotes/origin/trunk"]);
+        assert_eq!(both(&clone_dir, &remote_head).unwrap(), "other");
+
+        // Chained symbolic refs are followed.
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/origin/current",
+            "refs/remotes/origin/other",
+        ]);
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/current",
+        ]);
+        assert_eq!(both(&clone_dir, &remote_head).unwrap(), "other");
+        git(&["symbolic-ref", "-d", "refs/remotes/origin/current"]);
+        git(&[
+            "update-ref",
+            "--no-deref",
+            "refs/remotes/origin/HEAD",
+            "origin/other",
+        ]);
+
+        // Custom refspecs map the names back.
+        git(&["update-ref", "-d", "refs/remotes/origin/HEAD"]);
+        git(&[
+            "config",
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/mirror/*",
+        ]);
+        git(&["fetch", "-q", "origin"]);
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/mirror/HEAD",
+            "refs/remotes/mirror/trunk",
+        ]);
+        assert_eq!(both(&clone_dir, &remote_head).unwrap(), "trunk");
+
+        // Without a remote HEAD, the remote branches are guessed from.
+        git(&["update-ref", "-d", "refs/remotes/mirror/HEAD"]);
+        git(&["branch", "-q", "-m", "trunk", "work"]);
+        let guess = Options {
+            strategies: vec![Strategy::RemoteBranches],
+            candidates: vec!["other".to_string()],
+            ..options()
+        };
+        assert_eq!(both(&clone_dir, &guess).unwrap(), "other");
+
+        // The repository's own config picks the candidates for local names.
+        git(&["config", "defaultBranch.candidates", "work"]);
+        let local = Options {
+            strategies: vec![Strategy::LocalNames],
+            ..options()
+        };
+        assert_eq!(both(&clone_dir, &local).unwrap(), "work");
+
+        let missing = Options {
+            strategies: vec![Strategy::RemoteHead],
+            ..options()
+        };
+        let error = resolve(&clone_dir, "upstream", &missing).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Failure>(),
+            Some(Failure::RemoteNotFound(remote)) if remote == "upstream"
+        ));
+        let error = resolve(tmp.path(), "origin", &options()).unwrap_err();
+        assert!(matches!(
+            error.downcast_ref::<Failure>(),
+            Some(Failure::NotARepository { .. })
+        ));
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_script() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let git = |args: &[&str]| git::git(tmp.path(), args).unwrap();
+        git(&[
+            "remote",
+            "add",
+            "origin",
+            "https://example.com/team/app.git",
+        ]);
+        git(&["update-ref", "refs/remotes/origin/main", "HEAD"]);
+        git(&["update-ref", "refs/remotes/origin/stable", "HEAD"]);
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/main",
+        ]);
+        git(&["config", "team.defaultBranch", "stable"]);
+        let script = tmp.path().join("describe.rhai");
+        fs::write(
+            &script,
+            r#"`${remote} ${url} ${remote_head} ${refs} ${branches} ${remote_branches} ${config["team.defaultbranch"]}`"#,
+        )
+        .unwrap();
+        let scripted = Options {
+            script: Some(script),
+            strategies: vec![Strategy::Script],
+            ..options()
+        };
+        assert_eq!(
+            both(tmp.path(), &scripted).unwrap(),
+            "origin https://example.com/team/app.git main \
+             [\"refs/heads/main\", \"refs/remotes/origin/HEAD\", \"refs/remotes/origin/main\", \
+             \"refs/remotes/origin/stable\"] [\"main\"] [\"main\", \"stable\"] stable"
+        );
+    }
+
+    #[test]
+    fn test_push_remote() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let git = |args: &[&str]| git::git(tmp.path(), args).unwrap();
+        for (remote, branch) in [("origin", "main"), ("fork", "dev")] {
+            git(&["remote", "add", remote, "/nonexistent"]);
+            let tracking = format!("refs/remotes/{}/{}", remote, branch);
+            git(&["update-ref", &tracking, "HEAD"]);
+            git(&[
+                "symbolic-ref",
+                &format!("refs/remotes/{}/HEAD", remote),
+                &tracking,
+            ]);
+        }
+        let push = Options {
+            push: true,
+            strategies: vec![Strategy::RemoteHead],
+            ..options()
+        };
+        assert_eq!(both(tmp.path(), &push).unwrap(), "main");
+        git(&["config", "remote.pushDefault", "fork"]);
+        assert_eq!(both(tmp.path(), &push).unwrap(), "dev");
+        git(&["config", "branch.main.pushRemote", "origin"]);
+        assert_eq!(both(tmp.path(), &push).unwrap(), "main");
+        git(&["checkout", "-q", "--detach"]);
+        assert_eq!(both(tmp.path(), &push).unwrap(), "dev");
+    }
+}
@@ -0,0 +1,127 @@
+use crate::redact::redact;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use std::io::Write;
+
+#[derive(clap::Args)]
+pub struct LogArgs {
+    /// Explain what is being consulted on stderr; repeat for more detail
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Log filter such as `debug` or `resolve=trace,forge=debug`; overrides -v
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_LOG", global = true, hide = true)]
+    log: Option<String>,
+}
+
+/// Installs the stderr logger. `-v` shows discovery, the refs consulted, subprocesses and
+/// network requests; `-vv` also shows each strategy attempt.
+pub fn init(args: &LogArgs) -> Result<(), String> {
+    let filter = match &args.log {
+        Some(spec) => Filter::parse(spec)?,
+        None => Filter {
+            default: match args.verbose {
+                0 => LevelFilter::Warn,
+                1 => LevelFilter::Debug,
+                _ => LevelFilter::Trace,
+            },
+            modules: Vec::new(),
+        },
+    };
+    log::set_max_level(filter.max());
+    log::set_logger(Box::leak(Box::new(filter))).map_err(|e| e.to_string())
+}
+
+/// A level per module of this crate, in the spirit of `RUST_LOG`.
+#[derive(Debug, PartialEq)]
+struct Filter {
+    default: LevelFilter,
+    modules: Vec<(String, LevelFilter)>,
+}
+
+impl Filter {
+    fn parse(spec: &str) -> Result<Self, String> {
+        let mut filter = Filter {
+            default: LevelFilter::Warn,
+            modules: Vec::new(),
+        };
+        for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+            let level = |s: &str| {
+                s.parse::<LevelFilter>()
+                    .map_err(|_| format!("Invalid log level: {}", s))
+            };
+            match directive.split_once('=') {
+                Some((module, value)) => filter.modules.push((module.to_string(), level(value)?)),
+                None => filter.default = level(directive)?,
+            }
+        }
+        Ok(filter)
+    }
+
+    fn max(&self) -> LevelFilter {
+        self.modules
+            .iter()
+            .map(|(_, level)| *level)
+            .fold(self.default, Ord::max)
+    }
+
+    fn level(&self, target: &str) -> LevelFilter {
+        let module = target
+            .strip_prefix(concat!(env!("CARGO_CRATE_NAME"), "::"))
+            .unwrap_or(target);
+        self.modules
+            .iter()
+            .filter(|(name, _)| {
+                module == name
+                    || module
+                        .strip_prefix(name.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+            })
+            .map(|(_, level)| *level)
+            .next_back()
+            .unwrap_or(self.default)
+    }
+}
+
+impl Log for Filter {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level(metadata.target())
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let level = match record.level() {
+            Level::Error => "error",
+            Level::Warn => "warning",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        let message = record.args().to_string();
+        let _ = writeln!(std::io::stderr(), "{}: {}", level, redact(&message));
+    }
+
+    fn flush(&self) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter() {
+        let filter = Filter::parse("info, resolve=trace,forge=off").unwrap();
+        assert_eq!(filter.max(), LevelFilter::Trace);
+        assert_eq!(
+            filter.level("git_default_branch::resolve"),
+            LevelFilter::Trace
+        );
+        assert_eq!(filter.level("git_default_branch::forge"), LevelFilter::Off);
+        assert_eq!(
+            filter.level("git_default_branch::resolver"),
+            LevelFilter::Info
+        );
+        assert!(Filter::parse("resolve=loud").is_err());
+    }
+}
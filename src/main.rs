@@ -1,202 +1,735 @@
-use clap::Parser;
-use gix::bstr::ByteSlice;
+mod audit;
+mod batch;
+mod branches;
+mod cache;
+mod capabilities;
+#[cfg(feature = "subprocess")]
+mod check_changed;
+mod config;
+mod diagnose;
+mod fast;
+#[cfg(feature = "forge")]
+mod forge;
+mod fork_point;
+mod ghq;
+mod git;
+mod glob;
+mod guard;
+mod history;
+mod hook;
+mod i18n;
+mod init;
+mod interactive;
+mod json;
+#[cfg(feature = "libgit2")]
+mod libgit2;
+mod logging;
+mod manifest;
+#[cfg(feature = "network")]
+mod metrics;
+mod migrate;
+mod paths;
+#[cfg(feature = "subprocess")]
+mod plugin;
+mod policy;
+mod pr_base;
+mod redact;
+mod repo_manifest;
+mod resolve;
+mod rpc;
+mod scan;
+#[cfg(feature = "scripting")]
+mod script;
+#[cfg(feature = "network")]
+mod serve;
+mod shell;
+mod ssh;
+mod submodules;
+#[cfg(test)]
+mod testutil;
+#[cfg(any(feature = "forge", feature = "subprocess"))]
+mod tls;
+mod toml;
+#[cfg(feature = "tui")]
+mod tui;
+mod verify;
+mod watch;
+
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
+use resolve::Source;
+use std::env;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
 
 #[derive(Parser)]
-#[command(version, about = "Get the default branch of a Git repository")]
+#[command(
+    version,
+    about = "Get the default branch of a Git repository",
+    after_help = "Exit status:
+  0   success
+  1   any other error
+  2   not a Git repository
+  3   remote not found
+  4   could not determine the default branch
+  5   could not reach the remote
+  6   --strict and only a guess was available
+  64  invalid command line"
+)]
 struct Args {
-    #[arg(short, long, default_value = ".")]
-    dir: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Repository to inspect; repeat or use a glob such as '~/src/*' to resolve several at once
+    #[arg(short, long, default_value = ".", global = true)]
+    dir: Vec<String>,
 
-    #[arg(short, long, default_value = "origin")]
+    #[arg(
+        short,
+        long,
+        default_value = "origin",
+        env = "GIT_DEFAULT_BRANCH_REMOTE",
+        global = true
+    )]
     remote: String,
+
+    /// Repositories to inspect, as with --dir
+    #[arg(value_name = "DIR", conflicts_with_all = ["dir", "stdin", "ghq"])]
+    dirs: Vec<String>,
+
+    /// Run as if started in this directory, like `git -C`; repeat to chain relative paths
+    #[arg(short = 'C', value_name = "PATH")]
+    chdir: Vec<PathBuf>,
+
+    /// Read repository paths from standard input, one per line
+    #[arg(long, conflicts_with = "dir")]
+    stdin: bool,
+
+    /// Resolve the repositories listed in this manifest, `[repos.<label>]` sections with a `path`
+    /// or a `url` and an optional `remote` in TOML, or the same as a `repos` object in a `.json`
+    /// file, so the inventory can be committed next to the automation using it
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["dir", "dirs", "stdin", "ghq"])]
+    manifest: Option<PathBuf>,
+
+    /// Resolve the projects of an Android `repo` manifest, from their checkouts when the manifest
+    /// is in a repo client or else from their fetch URLs, and fail when a project's revision is a
+    /// branch other than its default branch
+    #[arg(long, value_name = "FILE",
+        conflicts_with_all = ["dir", "dirs", "stdin", "ghq", "manifest"])]
+    repo_manifest: Option<PathBuf>,
+
+    /// Answer JSON-RPC requests (`resolve`, `watch`, `invalidate`), one per line on stdin, for
+    /// editor plugins that keep one process running
+    #[arg(long, conflicts_with_all = ["stdin", "watch", "prompt"])]
+    stdio: bool,
+
+    #[command(flatten)]
+    batch: batch::BatchArgs,
+
+    #[command(flatten)]
+    resolve: resolve::Options,
+
+    #[command(flatten)]
+    config: config::ConfigArgs,
+
+    #[command(flatten)]
+    log: logging::LogArgs,
+
+    #[command(flatten)]
+    audit: audit::AuditArgs,
+
+    #[cfg(any(feature = "forge", feature = "subprocess"))]
+    #[command(flatten)]
+    tls: tls::TlsArgs,
+
+    #[cfg(feature = "subprocess")]
+    #[command(flatten)]
+    ssh: ssh::SshArgs,
+
+    /// Output format for the branch and for errors
+    #[arg(
+        long,
+        value_enum,
+        default_value_t = Output::Text,
+        env = "GIT_DEFAULT_BRANCH_OUTPUT",
+        global = true
+    )]
+    output: Output,
+
+    /// Resolve every repository managed by ghq, labelled by its path under the ghq root
+    #[arg(long, conflicts_with_all = ["dir", "stdin"])]
+    ghq: bool,
+
+    /// Keep running and print the default branch again whenever it changes
+    #[arg(short, long)]
+    watch: bool,
+
+    /// With --watch, run this shell command whenever the default branch changes, with the new
+    /// branch in `DEFAULT_BRANCH` and the old one in `PREVIOUS_DEFAULT_BRANCH`
+    #[cfg(feature = "subprocess")]
+    #[arg(long, value_name = "COMMAND", requires = "watch")]
+    exec: Option<String>,
+
+    /// Translate paths written for the other side of WSL, such as `C:\src\repo` from Linux or
+    /// `/mnt/c/src/repo` from Windows; the default inside WSL
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_WSL_PATHS",
+        value_parser = BoolishValueParser::new(), global = true)]
+    wsl_paths: bool,
+
+    /// Also print the canonical root of each repository, as `root<TAB>branch` or a `root` field in
+    /// JSON, for tooling given a symlinked path that needs both
+    #[arg(long, conflicts_with_all = ["prompt", "stdio", "watch", "ghq"])]
+    show_root: bool,
+
+    /// Also report whether a local branch of the default branch's name exists and what it tracks,
+    /// as `branch<TAB>yes|no<TAB>upstream` or `local` and `upstream` fields in JSON; for a single
+    /// repository
+    #[arg(long, conflicts_with_all = ["prompt", "stdio", "watch", "ghq", "stdin"])]
+    tracking: bool,
+
+    /// Report what the default branch was at a date, like `2024-01-31` or `2.weeks.ago`, or when
+    /// a revision was committed, from the reflog of the remote HEAD; for a single repository
+    #[arg(long, value_name = "DATE|REV",
+        conflicts_with_all = ["prompt", "stdio", "watch", "ghq", "stdin", "tracking"])]
+    at: Option<String>,
+
+    /// Also report the branch the remote HEAD pointed to before it moved to the current default
+    /// branch, as `branch<TAB>previous` or a `previous` field in JSON, so a rename can be noticed;
+    /// for a single repository
+    #[arg(long, conflicts_with_all = ["prompt", "stdio", "watch", "ghq", "stdin", "at"])]
+    show_previous: bool,
+
+    /// Quote each printed field for the shell, so `eval "branch=$(git-default-branch
+    /// --shell-quote)"` is safe whatever the name contains; `powershell` quotes for PowerShell
+    /// instead. For a single repository
+    #[arg(long, value_enum, value_name = "SHELL", num_args = 0..=1, require_equals = true,
+        default_missing_value = "posix",
+        conflicts_with_all = ["prompt", "stdio", "output", "watch", "stdin", "ghq", "manifest",
+            "repo_manifest"])]
+    shell_quote: Option<shell::Quote>,
+
+    /// Write the result to this file instead of stdout, atomically through a temporary file
+    /// beside it, so hooks can publish it while other processes read it; the file is left as it
+    /// was on failure. For a single repository
+    #[arg(long, value_name = "PATH",
+        conflicts_with_all = ["prompt", "stdio", "watch", "ghq", "stdin"])]
+    output_file: Option<PathBuf>,
+
+    /// For shell prompts: like --fast, but print the branch without a newline, give up after a
+    /// few milliseconds, and print nothing on any failure
+    #[arg(long, conflicts_with_all = ["watch", "stdin", "ghq"])]
+    prompt: bool,
+
+    /// Whether a repository was named with --dir, -C or positionally rather than defaulting to
+    /// the current directory.
+    #[arg(skip)]
+    explicit_repo: bool,
 }
 
-fn main() {
-    let args = Args::parse();
+#[derive(clap::ValueEnum, Clone, Copy, PartialEq)]
+enum Output {
+    Text,
+    /// `{"branch": ..., "provenance": ...}` on stdout, errors as JSON on stderr
+    Json,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Find Git repositories under a directory and print their default branches
+    Scan(scan::ScanArgs),
+    #[cfg(feature = "network")]
+    /// Answer resolution requests over HTTP, keeping repositories open between requests, with
+    /// Prometheus metrics at `/metrics`
+    Serve(serve::ServeArgs),
+    /// Manage Git hooks that keep the remote HEAD up to date
+    Hook {
+        #[command(subcommand)]
+        action: hook::HookAction,
+    },
+    /// Rename the default branch locally and point upstreams at the new name
+    Migrate(migrate::MigrateArgs),
+    /// Point branches tracking a renamed default branch at its new name
+    Retarget(migrate::RetargetArgs),
+    /// Configure a `git default-branch` alias for this binary
+    Init(init::InitArgs),
+    /// Inspect or clear cached network results
+    Cache {
+        #[command(subcommand)]
+        action: cache::CacheAction,
+    },
+    #[cfg(feature = "forge")]
+    /// List the default branch of every repository in a GitHub organization
+    OrgScan(forge::OrgScanArgs),
+    /// Fail if any repository under the given directories has a missing, stale or disallowed remote HEAD
+    VerifyAll(verify::VerifyAllArgs),
+    #[cfg(feature = "forge")]
+    /// Report whether the default branch is protected on GitHub or GitLab
+    Protected(forge::ProtectedArgs),
+    /// Show the effective configuration and where each value came from
+    Config,
+    /// List local branches, the default branch first, with how far each is ahead and behind it
+    Branches(branches::BranchesArgs),
+    /// Fail when on the default branch, for pre-commit and pre-push hooks
+    Guard(guard::GuardArgs),
+    /// Print the commit where the current branch, or another given, forked from the default branch
+    ForkPoint(fork_point::ForkPointArgs),
+    /// Suggest the branch a pull request should target: the default branch, or the release
+    /// branch the work is built on
+    PrBase(pr_base::PrBaseArgs),
+    #[cfg(feature = "subprocess")]
+    /// Fail and print `old -> new` when the remote's default branch differs from the one cached
+    /// by the previous run, for cron jobs that alert on renames
+    CheckChanged,
+    /// Print each submodule's path, remote and default branch, nested submodules included, for
+    /// tooling that moves submodules to their upstream default branches
+    Submodules,
+    /// Print the strategies and backends this binary was built with, so scripts can check before
+    /// relying on a flag; as JSON with --output json
+    Capabilities,
+    /// Print shell functions such as `gdb-switch` and `gdb-rebase` to source from a shell's rc file
+    ShellInit(shell::ShellArgs),
+}
+
+impl Args {
+    /// Whether to ask the API for the repository `GH_REPO` names, as a gh extension does, rather
+    /// than resolve locally: only when no repository is named here, no option needs a local one,
+    /// and the network may be used.
+    #[cfg(feature = "forge")]
+    fn uses_gh_repo(&self) -> bool {
+        let local = self.explicit_repo || self.show_root || self.tracking || self.show_previous;
+        !local && !self.resolve.offline && policy::network_allowed() && forge::gh_repo().is_some()
+    }
 
-    match run(&args.dir, &args.remote) {
-        Ok(branch) => println!("{}", branch),
-        Err(e) => {
-            eprintln!("{}", e);
-            process::exit(1);
+    /// Returns the repository for commands that operate on exactly one.
+    fn dir(&self) -> Result<&str, Box<dyn std::error::Error>> {
+        match self.dir.as_slice() {
+            [dir] => Ok(dir),
+            _ => Err("This command accepts a single --dir".into()),
         }
     }
 }
 
-fn remote_head_branch(
-    repo: &gix::Repository,
-    remote: &str,
-) -> Result<Option<String>, Box<dyn std::error::Error>> {
-    let Ok(r) = repo.find_reference(&format!("refs/remotes/{}/HEAD", remote)) else {
-        return Ok(None);
+fn main() {
+    let command = Args::command();
+    #[cfg(feature = "forge")]
+    let command = if forge::is_gh_extension() {
+        command.bin_name("gh default-branch")
+    } else {
+        command
     };
-    let target = r.target();
-    let name = target.try_name().ok_or("HEAD is not symbolic")?;
-    Ok(Some(
-        name.as_bstr()
-            .to_str()?
-            .strip_prefix(&format!("refs/remotes/{}/", remote))
-            .ok_or("Invalid ref format")?
-            .to_string(),
-    ))
-}
+    let matches = command.try_get_matches().unwrap_or_else(|e| {
+        // Keep 2 for "not a repository" rather than clap's usual status for usage errors.
+        if e.use_stderr() {
+            let _ = e.print();
+            process::exit(64);
+        }
+        e.exit()
+    });
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    record_sources(&mut args.resolve, &matches);
+    args.explicit_repo = !args.dirs.is_empty()
+        || !args.chdir.is_empty()
+        || matches.value_source("dir") != Some(ValueSource::DefaultValue);
+    if !args.dirs.is_empty() {
+        args.dir = std::mem::take(&mut args.dirs);
+    }
+    if args.wsl_paths || paths::in_wsl() {
+        args.wsl_paths = true;
+        for dir in &mut args.dir {
+            *dir = paths::translate_wsl(dir).unwrap_or(std::mem::take(dir));
+        }
+        for path in &mut args.chdir {
+            if let Some(translated) = path.to_str().and_then(paths::translate_wsl) {
+                *path = PathBuf::from(translated);
+            }
+        }
+    }
+    if let Err(e) = logging::init(&args.log)
+        .map_err(Into::into)
+        .and_then(|()| change_directory(&args.chdir))
+        .and_then(|()| config::Config::load(&args.config))
+        .and_then(|config| {
+            audit::init(args.audit.audit_log.clone().or(config.audit_log.clone()));
+            policy::init(policy::Policy::load(&config)?);
+            #[cfg(any(feature = "forge", feature = "subprocess"))]
+            tls::init(
+                args.tls.ca_cert.clone().or(config.ca_cert.clone()),
+                args.tls.insecure,
+            );
+            #[cfg(feature = "subprocess")]
+            ssh::init(
+                args.ssh
+                    .ssh_host_key_checking
+                    .or(config.ssh_host_key_checking),
+            );
+            Ok(config.apply(&mut args.resolve)?)
+        })
+        .and_then(|()| expand_remote_group(&mut args))
+        .and_then(|()| validate_remotes(&args))
+    {
+        eprintln!("{}", redact::redact(&e.to_string()));
+        process::exit(1);
+    }
 
-fn run(path: &str, remote: &str) -> Result<String, Box<dyn std::error::Error>> {
-    let repo = gix::discover(path)?;
+    let result = match &args.command {
+        Some(Command::Scan(scan_args)) => {
+            scan::run(scan_args, &args.remote, &args.resolve, &args.batch)
+        }
+        #[cfg(feature = "network")]
+        Some(Command::Serve(serve_args)) => serve::run(serve_args, &args.remote, &args.resolve),
+        Some(Command::Hook { action }) => args
+            .dir()
+            .and_then(|dir| hook::run(action, dir, &args.remote, args.resolve.dry_run)),
+        Some(Command::Migrate(migrate_args)) => args
+            .dir()
+            .and_then(|dir| migrate::run(migrate_args, dir, &args.remote, args.resolve.dry_run)),
+        Some(Command::Retarget(retarget_args)) => args
+            .dir()
+            .and_then(|dir| migrate::retarget(retarget_args, dir, &args.remote, &args.resolve)),
+        Some(Command::Init(init_args)) => args
+            .dir()
+            .and_then(|dir| init::run(init_args, dir, args.resolve.dry_run)),
+        Some(Command::Cache { action }) => cache::run(action),
+        #[cfg(feature = "forge")]
+        Some(Command::OrgScan(org_scan_args)) => {
+            forge::org_scan(org_scan_args, !args.resolve.no_cache)
+        }
+        Some(Command::VerifyAll(verify_args)) => verify::run(verify_args, &args.remote),
+        #[cfg(feature = "forge")]
+        Some(Command::Protected(protected_args)) => args
+            .dir()
+            .and_then(|dir| forge::protected(protected_args, dir, &args.remote, &args.resolve)),
+        Some(Command::Branches(branches_args)) => args
+            .dir()
+            .and_then(|dir| branches::run(branches_args, dir, &args.remote, &args.resolve)),
+        Some(Command::Guard(guard_args)) => args
+            .dir()
+            .and_then(|dir| guard::run(guard_args, dir, &args.remote, &args.resolve)),
+        Some(Command::ForkPoint(fork_point_args)) => args
+            .dir()
+            .and_then(|dir| fork_point::run(fork_point_args, dir, &args.remote, &args.resolve)),
+        Some(Command::PrBase(pr_base_args)) => args
+            .dir()
+            .and_then(|dir| pr_base::run(pr_base_args, dir, &args.remote, &args.resolve)),
+        #[cfg(feature = "subprocess")]
+        Some(Command::CheckChanged) => args
+            .dir()
+            .and_then(|dir| check_changed::run(dir, &args.remote, &args.resolve)),
+        Some(Command::ShellInit(shell_args)) => shell::run(shell_args),
+        Some(Command::Submodules) => args.dir().and_then(|dir| {
+            submodules::run(
+                dir,
+                &args.remote,
+                &args.resolve,
+                &args.batch,
+                args.output == Output::Json,
+            )
+        }),
+        Some(Command::Capabilities) => capabilities::run(args.output == Output::Json),
+        Some(Command::Config) => args
+            .dir()
+            .and_then(|dir| config::show(&args.config, dir, &args.remote, &args.resolve)),
+        None if args.prompt => prompt(&args),
+        None if args.stdio => rpc::run(&args.remote, &args.resolve),
+        None if args.watch => args.dir().and_then(|dir| {
+            #[cfg(feature = "subprocess")]
+            let exec = args.exec.as_deref();
+            #[cfg(not(feature = "subprocess"))]
+            let exec = None;
+            watch::run(Path::new(dir), &args.remote, &args.resolve, exec)
+        }),
+        None if args.stdin => batch::print(
+            &io::stdin()
+                .lines()
+                .map_while(Result::ok)
+                .filter(|line| !line.is_empty())
+                .map(|line| {
+                    let path = args
+                        .wsl_paths
+                        .then(|| paths::translate_wsl(&line))
+                        .flatten()
+                        .unwrap_or_else(|| line.clone());
+                    labelled(line, PathBuf::from(path), args.show_root)
+                })
+                .collect::<Vec<_>>(),
+            &args.remote,
+            &args.resolve,
+            &args.batch,
+            args.output == Output::Json,
+        ),
+        None if let Some(when) = &args.at => args.dir().and_then(|dir| {
+            history::at(dir, when, &args.remote, &args.resolve)
+                .and_then(|resolved| print_resolved(&resolved, None, None, None, &args))
+        }),
+        None if let Some(manifest) = &args.manifest => manifest::print(
+            manifest,
+            &args.remote,
+            &args.resolve,
+            &args.batch,
+            args.output == Output::Json,
+        ),
+        None if let Some(manifest) = &args.repo_manifest => repo_manifest::print(
+            manifest,
+            &args.resolve,
+            &args.batch,
+            args.output == Output::Json,
+        ),
+        None if args.ghq => batch::print(
+            &ghq::repositories(),
+            &args.remote,
+            &args.resolve,
+            &args.batch,
+            args.output == Output::Json,
+        ),
+        #[cfg(feature = "forge")]
+        None if args.uses_gh_repo() => forge::gh_default_branch(&args.resolve)
+            .and_then(|resolved| print_resolved(&resolved, None, None, None, &args)),
+        None => print_branches(&args),
+    };
 
-    if let Some(branch) = remote_head_branch(&repo, remote)? {
-        return Ok(branch);
+    if let Err(e) = result {
+        let code = match e.downcast_ref::<resolve::Failure>() {
+            Some(failure) => failure.exit_code(),
+            None if e.is::<gix::discover::Error>() => 2,
+            None => 1,
+        };
+        // Fast mode is meant for prompts, which want no output at all on failure.
+        if args.output == Output::Json {
+            eprintln!("{}", error_json(e.as_ref(), code));
+        } else if !args.resolve.fast {
+            eprintln!("{}", redact::redact(&e.to_string()));
+            if let Some(suggestion) = e
+                .downcast_ref::<resolve::Failure>()
+                .and_then(resolve::Failure::suggestion)
+            {
+                eprintln!("{}", i18n::text("hint", &[("hint", &suggestion)]));
+            }
+        }
+        process::exit(code);
     }
+}
 
-    // https://qiita.com/ymm1x/items/b22bddc9fbc192ae1a70
-    // https://stackoverflow.com/questions/28666357/how-to-get-default-git-branch/44750379#44750379
-    let _ = std::process::Command::new("git")
-        .args(["remote", "set-head", remote, "--auto"])
-        .current_dir(path)
-        .output();
+/// How long `--prompt` waits before printing nothing, so a slow disk never stalls the shell.
+const PROMPT_BUDGET: Duration = Duration::from_millis(50);
 
-    if let Some(branch) = remote_head_branch(&repo, remote)? {
-        return Ok(branch);
+/// Resolves with `--fast` on another thread so the budget holds even when a read blocks.
+fn prompt(args: &Args) -> ! {
+    let Ok(dir) = args.dir() else {
+        process::exit(1);
+    };
+    let (path, remote) = (PathBuf::from(dir), args.remote.clone());
+    let options = resolve::Options {
+        fast: true,
+        ..args.resolve.clone()
+    };
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = sender.send(resolve::run(&path, &remote, &options).ok());
+    });
+    match receiver.recv_timeout(PROMPT_BUDGET) {
+        Ok(Some(branch)) => {
+            print!("{}", branch);
+            let _ = io::stdout().flush();
+            process::exit(0)
+        }
+        _ => process::exit(1),
     }
+}
 
-    // Fallback to common default branch names
-    Ok(["main", "master"]
-        .iter()
-        .find(|&&name| repo.find_reference(&format!("refs/heads/{}", name)).is_ok())
-        .ok_or("Could not determine default branch")?
-        .to_string())
+/// Describes `e` as `{"code", "error", "message", "strategy", "hint"}`, with `null` for what is
+/// not known.
+fn error_json(e: &(dyn std::error::Error + 'static), code: i32) -> String {
+    let failure = e.downcast_ref::<resolve::Failure>();
+    let optional = |value: Option<&str>| value.map_or("null".to_string(), json::quote);
+    format!(
+        "{{\"code\":{},\"error\":{},\"message\":{},\"strategy\":{},\"hint\":{}}}",
+        code,
+        json::quote(failure.map_or("error", resolve::Failure::kind)),
+        json::quote(&redact::redact(&e.to_string())),
+        optional(failure.and_then(resolve::Failure::strategy)),
+        optional(failure.map(resolve::Failure::hint)),
+    )
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
-    use std::process::Command;
-
-    fn init_repo(dir: &std::path::Path, branch: &str) {
-        Command::new("git")
-            .args(["init", "--initial-branch", branch])
-            .current_dir(dir)
-            .output()
-            .unwrap();
-        Command::new("git")
-            .args(["config", "user.name", "Test"])
-            .current_dir(dir)
-            .output()
-            .unwrap();
-        Command::new("git")
-            .args(["config", "user.email", "test@example.com"])
-            .current_dir(dir)
-            .output()
-            .unwrap();
+/// Applies `-C` as git does, each path relative to the one before. Relative `GIT_DIR` and
+/// `GIT_WORK_TREE` are then made absolute, so git subprocesses run elsewhere still find them;
+/// on Windows this also covers drive-relative values like `C:repo`.
+fn change_directory(paths: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let dir = paths
+        .iter()
+        .fold(PathBuf::new(), |dir, path| dir.join(path));
+    if !dir.as_os_str().is_empty() {
+        env::set_current_dir(&dir)
+            .map_err(|e| format!("Cannot change to '{}': {}", dir.display(), e))?;
     }
-
-    fn commit(dir: &std::path::Path, msg: &str) {
-        fs::write(dir.join("test.txt"), msg).unwrap();
-        Command::new("git")
-            .args(["add", "."])
-            .current_dir(dir)
-            .output()
-            .unwrap();
-        Command::new("git")
-            .args(["commit", "-m", msg])
-            .current_dir(dir)
-            .output()
-            .unwrap();
+    for name in ["GIT_DIR", "GIT_WORK_TREE"] {
+        if let Some(value) = env::var_os(name).filter(|value| Path::new(value).is_relative()) {
+            let absolute = std::path::absolute(value)?;
+            // SAFETY: this runs at startup, before any other thread exists.
+            unsafe { env::set_var(name, absolute) };
+        }
     }
+    Ok(())
+}
 
-    #[test]
-    fn test_main_branch() {
-        let tmp = tempfile::tempdir().unwrap();
-        init_repo(tmp.path(), "main");
-        commit(tmp.path(), "initial");
-
-        let result = run(tmp.path().to_str().unwrap(), "origin").unwrap();
-        assert_eq!(result, "main");
+/// Expands `--remote @group` into the group's priority list. Commands that act on a single remote
+/// use the first one.
+fn expand_remote_group(args: &mut Args) -> Result<(), Box<dyn std::error::Error>> {
+    if !args.remote.starts_with('@') {
+        return Ok(());
     }
+    let remotes = args
+        .resolve
+        .expand_groups(std::slice::from_ref(&args.remote))?;
+    args.remote = remotes[0].clone();
+    args.resolve.remotes = remotes;
+    Ok(())
+}
 
-    #[test]
-    fn test_master_branch() {
-        let tmp = tempfile::tempdir().unwrap();
-        init_repo(tmp.path(), "master");
-        commit(tmp.path(), "initial");
+/// Checks every remote given before any command passes one to git.
+fn validate_remotes(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    for remote in std::iter::once(&args.remote).chain(&args.resolve.remotes) {
+        resolve::validate_remote(remote)?;
+    }
+    Ok(())
+}
 
-        let result = run(tmp.path().to_str().unwrap(), "origin").unwrap();
-        assert_eq!(result, "master");
+/// Notes which resolution settings were given on the command line or in the environment, so
+/// configuration files do not override them.
+fn record_sources(options: &mut resolve::Options, matches: &ArgMatches) {
+    for name in [
+        "remote",
+        "candidates",
+        "release_patterns",
+        "offline",
+        "cache_ttl",
+        "no_cache",
+        #[cfg(feature = "scripting")]
+        "script",
+    ] {
+        let source = match matches.value_source(name) {
+            Some(ValueSource::CommandLine) => Source::CommandLine,
+            Some(ValueSource::EnvVariable) => Source::Environment,
+            _ => continue,
+        };
+        options.set_source(name, source);
     }
+}
 
-    #[test]
-    fn test_origin_head() {
-        let tmp = tempfile::tempdir().unwrap();
-        let repo_dir = tmp.path().join("repo");
-        let clone_dir = tmp.path().join("clone");
-
-        fs::create_dir(&repo_dir).unwrap();
-        init_repo(&repo_dir, "default");
-        commit(&repo_dir, "initial");
-
-        Command::new("git")
-            .args([
-                "clone",
-                repo_dir.to_str().unwrap(),
-                clone_dir.to_str().unwrap(),
-            ])
-            .output()
-            .unwrap();
-
-        let result = run(clone_dir.to_str().unwrap(), "origin").unwrap();
-        assert_eq!(result, "default");
+/// Prints the default branch of each `--dir`, prefixed with the directory when there are several.
+fn print_branches(args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let (remote, resolve) = (&args.remote, &args.resolve);
+    let repos = args
+        .dir
+        .iter()
+        .flat_map(|dir| glob::expand(dir))
+        .map(|path| labelled(path.display().to_string(), path, args.show_root))
+        .collect::<Vec<_>>();
+    match repos.as_slice() {
+        [] => Err(i18n::lookup("no-repositories").into()),
+        [(_, path)] => {
+            let resolved = resolve::run_detailed(path, remote, resolve)?;
+            let root = args.show_root.then(|| git::root(path)).transpose()?;
+            let tracking = args
+                .tracking
+                .then(|| resolve::tracking(path, &resolved.branch))
+                .transpose()?;
+            let previous = args
+                .show_previous
+                .then(|| history::previous(path, &resolved.branch, remote, resolve))
+                .transpose()?;
+            print_resolved(
+                &resolved,
+                root.as_deref(),
+                tracking.as_ref(),
+                previous.as_ref().map(Option::as_deref),
+                args,
+            )
+        }
+        _ if args.output_file.is_some() => Err("--output-file takes a single repository".into()),
+        _ if args.shell_quote.is_some() => Err("--shell-quote takes a single repository".into()),
+        _ => batch::print(
+            &repos,
+            remote,
+            resolve,
+            &args.batch,
+            args.output == Output::Json,
+        ),
     }
+}
 
-    #[test]
-    fn test_non_origin_remote() {
-        let tmp = tempfile::tempdir().unwrap();
-        let repo_dir = tmp.path().join("repo");
-        let clone_dir = tmp.path().join("clone");
-
-        fs::create_dir(&repo_dir).unwrap();
-        init_repo(&repo_dir, "default");
-        commit(&repo_dir, "initial");
-
-        Command::new("git")
-            .args([
-                "clone",
-                "--origin",
-                "upstream",
-                repo_dir.to_str().unwrap(),
-                clone_dir.to_str().unwrap(),
-            ])
-            .output()
-            .unwrap();
-
-        let result = run(clone_dir.to_str().unwrap(), "upstream").unwrap();
-        assert_eq!(result, "default");
+/// Labels `path` for batch output with its canonical root instead of `label` under `--show-root`,
+/// so several paths to the same repository are reported alike.
+fn labelled(label: String, path: PathBuf, show_root: bool) -> (String, PathBuf) {
+    match show_root.then(|| git::root(&path).ok()).flatten() {
+        Some(root) => (root.display().to_string(), path),
+        None => (label, path),
     }
+}
 
-    #[test]
-    fn test_deleted_origin_head() {
-        let tmp = tempfile::tempdir().unwrap();
-        let repo_dir = tmp.path().join("repo");
-        let clone_dir = tmp.path().join("clone");
-
-        fs::create_dir(&repo_dir).unwrap();
-        init_repo(&repo_dir, "default");
-        commit(&repo_dir, "initial");
-
-        Command::new("git")
-            .args([
-                "clone",
-                repo_dir.to_str().unwrap(),
-                clone_dir.to_str().unwrap(),
-            ])
-            .output()
-            .unwrap();
-
-        let origin_head_file = clone_dir.join(".git/refs/remotes/origin/HEAD");
-        let _ = fs::remove_file(&origin_head_file);
-
-        let result = run(clone_dir.to_str().unwrap(), "origin").unwrap();
-        assert_eq!(result, "default");
+/// Prints `resolved` in the format `args` ask for, with whichever of the root, tracking and
+/// previous branch were asked for; a previous branch of `Some(None)` was asked for but there is
+/// none.
+fn print_resolved(
+    resolved: &resolve::Resolved,
+    root: Option<&Path>,
+    tracking: Option<&resolve::Tracking>,
+    previous: Option<Option<&str>>,
+    args: &Args,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let root = root.map(|root| root.display().to_string());
+    let line = match args.output {
+        Output::Text => {
+            let mut fields = root.into_iter().collect::<Vec<_>>();
+            fields.push(resolved.branch.clone());
+            if let Some(tracking) = tracking {
+                fields.push(if tracking.local { "yes" } else { "no" }.to_string());
+                fields.push(tracking.upstream.clone().unwrap_or_default());
+            }
+            if let Some(previous) = previous {
+                fields.push(previous.unwrap_or_default().to_string());
+            }
+            if let Some(style) = args.shell_quote {
+                fields = fields
+                    .iter()
+                    .map(|field| shell::quote(field, style))
+                    .collect();
+            }
+            fields.join("\t")
+        }
+        Output::Json => format!(
+            "{{\"branch\":{},\"provenance\":{}{}{}{}}}",
+            json::quote(&resolved.branch),
+            json::quote(resolved.provenance),
+            root.map_or(String::new(), |root| format!(
+                ",\"root\":{}",
+                json::quote(&root)
+            )),
+            tracking.map_or(String::new(), |tracking| format!(
+                ",\"local\":{},\"upstream\":{}",
+                tracking.local,
+                tracking
+                    .upstream
+                    .as_deref()
+                    .map_or("null".to_string(), json::quote)
+            )),
+            previous.map_or(String::new(), |previous| format!(
+                ",\"previous\":{}",
+                previous.map_or("null".to_string(), json::quote)
+            ))
+        ),
+    };
+    match &args.output_file {
+        Some(path) => paths::write_atomic(path, format!("{}\n", line).as_bytes())
+            .map_err(|e| format!("Cannot write {}: {}", path.display(), e).into()),
+        None => {
+            println!("{}", line);
+            Ok(())
+        }
     }
 }
@@ -0,0 +1,259 @@
+use crate::batch::{self, BatchArgs};
+use crate::scan::{self, Format};
+use crate::{glob, json, resolve, toml};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// A repository listed in a manifest, under its label.
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub label: String,
+    pub location: Location,
+    /// The remote to resolve instead of the one on the command line.
+    pub remote: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Location {
+    /// A clone, relative to the manifest unless absolute.
+    Path(PathBuf),
+    /// A repository to ask over the network, with no clone needed.
+    Url(String),
+}
+
+/// Reads the manifest at `path`: a table of repositories by label, each with a `path` or a `url`
+/// and optionally a `remote`, as `[repos.<label>]` sections in TOML or a `repos` object in JSON
+/// for files ending in `.json`.
+pub fn load(path: &Path) -> Result<Vec<Entry>, Box<dyn std::error::Error>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read the manifest {}: {}", path.display(), e))?;
+    let base = path.parent().unwrap_or(Path::new(""));
+    let fields = match path.extension().is_some_and(|ext| ext == "json") {
+        true => json_fields(&text)?,
+        false => toml_fields(&text)?,
+    };
+    fields
+        .into_iter()
+        .map(|(label, fields)| entry(label, fields, base))
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("{}: {}", path.display(), e).into())
+}
+
+/// The `path`, `url` and `remote` of an entry.
+type Fields = [Option<String>; 3];
+
+fn toml_fields(text: &str) -> Result<Vec<(String, Fields)>, String> {
+    let root = toml::parse(text)?;
+    let Some(repos) = root.entries().and_then(|root| {
+        root.iter()
+            .find(|(key, _)| key == "repos")
+            .map(|(_, repos)| repos)
+    }) else {
+        return Ok(Vec::new());
+    };
+    let repos = repos.entries().ok_or("repos must be a table")?;
+    repos
+        .iter()
+        .map(|(label, value)| {
+            let table = value
+                .entries()
+                .ok_or_else(|| format!("repos.{} must be a table", label))?;
+            Ok((label.clone(), fields(label, table, toml::Value::as_str)?))
+        })
+        .collect()
+}
+
+fn json_fields(text: &str) -> Result<Vec<(String, Fields)>, String> {
+    let root = json::parse(text)?;
+    let Some(repos) = root.get("repos") else {
+        return Ok(Vec::new());
+    };
+    let repos = repos.entries().ok_or("repos must be an object")?;
+    repos
+        .iter()
+        .map(|(label, value)| {
+            let object = value
+                .entries()
+                .ok_or_else(|| format!("repos.{} must be an object", label))?;
+            Ok((label.clone(), fields(label, object, json::Value::as_str)?))
+        })
+        .collect()
+}
+
+/// Reads the fields of the entry labelled `label` from its keys and values.
+fn fields<V>(
+    label: &str,
+    entries: &[(String, V)],
+    as_str: impl Fn(&V) -> Option<&str>,
+) -> Result<Fields, String> {
+    let mut fields = Fields::default();
+    for (key, value) in entries {
+        let [path, url, remote] = &mut fields;
+        let slot = match key.as_str() {
+            "path" => path,
+            "url" => url,
+            "remote" => remote,
+            _ => return Err(format!("Unknown key repos.{}.{}", label, key)),
+        };
+        *slot = Some(
+            as_str(value)
+                .ok_or_else(|| format!("repos.{}.{} must be a string", label, key))?
+                .to_string(),
+        );
+    }
+    Ok(fields)
+}
+
+fn entry(label: String, fields: Fields, base: &Path) -> Result<Entry, String> {
+    let [path, url, remote] = fields;
+    let location = match (path, url) {
+        (Some(path), None) => Location::Path(base.join(glob::expand_home(&path))),
+        (None, Some(url)) if remote.is_none() => Location::Url(url),
+        (None, Some(_)) => {
+            return Err(format!(
+                "repos.{} gives a remote, which only applies to a path",
+                label
+            ));
+        }
+        _ => return Err(format!("repos.{} needs either a path or a url", label)),
+    };
+    Ok(Entry {
+        label,
+        location,
+        remote,
+    })
+}
+
+/// Prints `label<TAB>branch` for each repository in the manifest at `path`, as batch mode does
+/// for directories, with each entry's own remote if it names one, or one JSON array with `json`.
+pub fn print(
+    path: &Path,
+    remote: &str,
+    options: &resolve::Options,
+    batch: &BatchArgs,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let entries = load(path)?;
+    let results = batch::map_parallel(&entries, batch.jobs, |entry| {
+        resolve_entry(entry, remote, options, batch).map_err(|e| e.to_string())
+    });
+    if !batch.summary_only && json {
+        let written = entries
+            .iter()
+            .zip(&results)
+            .map(|(entry, result)| scan::Entry {
+                name: entry.label.clone(),
+                result: result
+                    .as_ref()
+                    .map(|(_, branch)| branch.clone())
+                    .map_err(String::clone),
+                remote: match (result, batch.with_remote) {
+                    (Ok((remote, _)), Some(_)) => Some(remote.clone()),
+                    _ => None,
+                },
+            })
+            .collect::<Vec<_>>();
+        scan::write_entries(&mut io::stdout().lock(), "label", &written, Format::Json)?;
+    } else if !batch.summary_only {
+        for (entry, result) in entries.iter().zip(&results) {
+            match (result, batch.with_remote) {
+                (Ok((remote, branch)), Some(style)) => {
+                    println!("{}\t{}", entry.label, style.join(remote, branch))
+                }
+                (Ok((_, branch)), None) => println!("{}\t{}", entry.label, branch),
+                (Err(e), _) => eprintln!("{}: {}", entry.label, e),
+            }
+        }
+    }
+    batch::print_summary(
+        results
+            .iter()
+            .map(|r| r.as_ref().ok().map(|(_, branch)| branch.as_str())),
+        batch,
+    );
+
+    let failures = results.iter().filter(|r| r.is_err()).count();
+    if failures > 0 {
+        return Err(format!("Failed to resolve {} repositories", failures).into());
+    }
+    Ok(())
+}
+
+/// Resolves `entry`, returning the remote or URL asked with the branch.
+fn resolve_entry(
+    entry: &Entry,
+    remote: &str,
+    options: &resolve::Options,
+    batch: &BatchArgs,
+) -> Result<(String, String), Box<dyn std::error::Error>> {
+    match &entry.location {
+        Location::Path(path) => {
+            let (remote, options) = match &entry.remote {
+                Some(remote) => (remote.as_str(), options.with_explicit_remote()),
+                None => (remote, options.clone()),
+            };
+            let branch = resolve::run(path, remote, &options)?;
+            let remote = match batch.with_remote {
+                Some(_) => resolve::primary_remote(path, remote, &options),
+                None => remote.to_string(),
+            };
+            Ok((remote, branch))
+        }
+        #[cfg(feature = "subprocess")]
+        Location::Url(url) => Ok((url.clone(), resolve::run_url(url, options)?.branch)),
+        #[cfg(not(feature = "subprocess"))]
+        Location::Url(_) => Err("Asking a URL needs the subprocess feature".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        let expected = vec![
+            Entry {
+                label: "app".to_string(),
+                location: Location::Path(tmp.path().join("services/app")),
+                remote: Some("upstream".to_string()),
+            },
+            Entry {
+                label: "tool".to_string(),
+                location: Location::Url("https://github.com/org/tool.git".to_string()),
+                remote: None,
+            },
+        ];
+
+        let toml = tmp.path().join("repos.toml");
+        fs::write(
+            &toml,
+            "[repos.app]\npath = \"services/app\"\nremote = \"upstream\"\n\n\
+             [repos.tool]\nurl = \"https://github.com/org/tool.git\"\n",
+        )
+        .unwrap();
+        assert_eq!(load(&toml).unwrap(), expected);
+
+        let json = tmp.path().join("repos.json");
+        fs::write(
+            &json,
+            r#"{"repos": {"app": {"path": "services/app", "remote": "upstream"},
+                "tool": {"url": "https://github.com/org/tool.git"}}}"#,
+        )
+        .unwrap();
+        assert_eq!(load(&json).unwrap(), expected);
+
+        for invalid in [
+            "[repos.app]\nremote = \"upstream\"\n",
+            "[repos.app]\npath = \"a\"\nurl = \"https://example.com/a.git\"\n",
+            "[repos.app]\nurl = \"https://example.com/a.git\"\nremote = \"upstream\"\n",
+            "[repos.app]\npath = \"a\"\nbranch = \"main\"\n",
+            "[repos.app]\npath = 1\n",
+        ] {
+            fs::write(&toml, invalid).unwrap();
+            assert!(load(&toml).is_err(), "{}", invalid);
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Upper bounds of the resolution latency histogram, in seconds.
+const BUCKETS: [f64; 8] = [0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// Counters for `serve`'s `/metrics` endpoint, in the Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    /// Requests by path and status code.
+    requests: BTreeMap<(&'static str, u16), u64>,
+    /// Requests answered from a repository kept open since an earlier request.
+    repository_hits: u64,
+    repository_misses: u64,
+    /// Resolutions by the strategy that answered.
+    resolutions: BTreeMap<&'static str, u64>,
+    /// Failed resolutions by the kind of failure.
+    failures: BTreeMap<&'static str, u64>,
+    /// Resolutions no slower than each bucket's bound.
+    latency_buckets: [u64; BUCKETS.len()],
+    latency_sum: f64,
+    latency_count: u64,
+}
+
+impl Metrics {
+    /// Counts a request for `path`, one of those `serve` answers or `other`.
+    pub fn request(&mut self, path: &'static str, status: u16) {
+        *self.requests.entry((path, status)).or_default() += 1;
+    }
+
+    pub fn repository(&mut self, reused: bool) {
+        match reused {
+            true => self.repository_hits += 1,
+            false => self.repository_misses += 1,
+        }
+    }
+
+    /// Records a resolution that took `elapsed` and was answered by `provenance`, or failed with
+    /// an error of `kind`.
+    pub fn resolution(&mut self, elapsed: Duration, outcome: Result<&'static str, &'static str>) {
+        match outcome {
+            Ok(provenance) => *self.resolutions.entry(provenance).or_default() += 1,
+            Err(kind) => *self.failures.entry(kind).or_default() += 1,
+        }
+        let seconds = elapsed.as_secs_f64();
+        for (count, bound) in self.latency_buckets.iter_mut().zip(BUCKETS) {
+            if seconds <= bound {
+                *count += 1;
+            }
+        }
+        self.latency_sum += seconds;
+        self.latency_count += 1;
+    }
+
+    /// Renders every metric, with its `HELP` and `TYPE` lines.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        header(
+            &mut out,
+            "requests_total",
+            "counter",
+            "HTTP requests by path and status.",
+        );
+        for ((path, status), count) in &self.requests {
+            let _ = writeln!(
+                out,
+                "git_default_branch_requests_total{{path=\"{}\",status=\"{}\"}} {}",
+                path, status, count
+            );
+        }
+        header(
+            &mut out,
+            "repository_cache_hits_total",
+            "counter",
+            "Requests for a repository kept open since an earlier request.",
+        );
+        let _ = writeln!(
+            out,
+            "git_default_branch_repository_cache_hits_total {}",
+            self.repository_hits
+        );
+        header(
+            &mut out,
+            "repository_cache_misses_total",
+            "counter",
+            "Requests that had to discover the repository.",
+        );
+        let _ = writeln!(
+            out,
+            "git_default_branch_repository_cache_misses_total {}",
+            self.repository_misses
+        );
+        header(
+            &mut out,
+            "resolutions_total",
+            "counter",
+            "Default branches resolved, by the strategy that answered.",
+        );
+        for (strategy, count) in &self.resolutions {
+            let _ = writeln!(
+                out,
+                "git_default_branch_resolutions_total{{strategy=\"{}\"}} {}",
+                strategy, count
+            );
+        }
+        header(
+            &mut out,
+            "resolution_failures_total",
+            "counter",
+            "Failed resolutions, by the kind of failure.",
+        );
+        for (kind, count) in &self.failures {
+            let _ = writeln!(
+                out,
+                "git_default_branch_resolution_failures_total{{kind=\"{}\"}} {}",
+                kind, count
+            );
+        }
+        header(
+            &mut out,
+            "resolution_duration_seconds",
+            "histogram",
+            "How long resolutions took.",
+        );
+        for (count, bound) in self.latency_buckets.iter().zip(BUCKETS) {
+            let _ = writeln!(
+                out,
+                "git_default_branch_resolution_duration_seconds_bucket{{le=\"{}\"}} {}",
+                bound, count
+            );
+        }
+        let _ = writeln!(
+            out,
+            "git_default_branch_resolution_duration_seconds_bucket{{le=\"+Inf\"}} {}",
+            self.latency_count
+        );
+        let _ = writeln!(
+            out,
+            "git_default_branch_resolution_duration_seconds_sum {}",
+            self.latency_sum
+        );
+        let _ = writeln!(
+            out,
+            "git_default_branch_resolution_duration_seconds_count {}",
+            self.latency_count
+        );
+        out
+    }
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP git_default_branch_{} {}", name, help);
+    let _ = writeln!(out, "# TYPE git_default_branch_{} {}", name, kind);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::default();
+        metrics.request("/resolve", 200);
+        metrics.request("/resolve", 200);
+        metrics.request("other", 404);
+        metrics.repository(false);
+        metrics.repository(true);
+        metrics.resolution(Duration::from_millis(3), Ok("remote-head"));
+        metrics.resolution(Duration::from_millis(300), Err("undetermined"));
+
+        let text = metrics.render();
+        for line in [
+            "git_default_branch_requests_total{path=\"/resolve\",status=\"200\"} 2",
+            "git_default_branch_requests_total{path=\"other\",status=\"404\"} 1",
+            "git_default_branch_repository_cache_hits_total 1",
+            "git_default_branch_repository_cache_misses_total 1",
+            "git_default_branch_resolutions_total{strategy=\"remote-head\"} 1",
+            "git_default_branch_resolution_failures_total{kind=\"undetermined\"} 1",
+            "git_default_branch_resolution_duration_seconds_bucket{le=\"0.001\"} 0",
+            "git_default_branch_resolution_duration_seconds_bucket{le=\"0.005\"} 1",
+            "git_default_branch_resolution_duration_seconds_bucket{le=\"0.5\"} 2",
+            "git_default_branch_resolution_duration_seconds_bucket{le=\"+Inf\"} 2",
+            "git_default_branch_resolution_duration_seconds_count 2",
+            "# TYPE git_default_branch_resolution_duration_seconds histogram",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {}", line);
+        }
+    }
+}
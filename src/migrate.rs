@@ -0,0 +1,387 @@
+use crate::git::{self, git, run_dir};
+use crate::{audit, resolve};
+use gix::bstr::ByteSlice;
+use std::fmt;
+
+#[derive(clap::Args)]
+pub struct MigrateArgs {
+    /// Current name of the default branch
+    from: String,
+
+    /// New name of the default branch
+    to: String,
+
+    /// Also set the global init.defaultBranch to the new name
+    #[arg(long)]
+    init_default_branch: bool,
+}
+
+#[derive(clap::Args)]
+pub struct RetargetArgs {
+    /// Previous name of the default branch
+    from: String,
+
+    /// New name of the default branch [default: the resolved default branch]
+    to: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    RenameBranch { from: String, to: String },
+    SetRemoteHead { remote: String, branch: String },
+    SetUpstream { branch: String, merge: String },
+    SetInitDefaultBranch { branch: String },
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Step::RenameBranch { from, to } => write!(f, "rename branch {} to {}", from, to),
+            Step::SetRemoteHead { remote, branch } => {
+                write!(f, "set refs/remotes/{}/HEAD to {}", remote, branch)
+            }
+            Step::SetUpstream { branch, merge } => {
+                write!(f, "set branch.{}.merge to {}", branch, merge)
+            }
+            Step::SetInitDefaultBranch { branch } => {
+                write!(f, "set global init.defaultBranch to {}", branch)
+            }
+        }
+    }
+}
+
+impl Step {
+    pub fn apply(&self, repo: &gix::Repository) -> Result<(), Box<dyn std::error::Error>> {
+        let dir = run_dir(repo);
+        match self {
+            Step::RenameBranch { from, to } => {
+                let (old, new) = (format!("refs/heads/{}", from), format!("refs/heads/{}", to));
+                let change = audit::Change {
+                    action: "rename-branch",
+                    name: &old,
+                    old: Some(&old),
+                    new: Some(&new),
+                };
+                return audit::record(repo, &change, || {
+                    git(dir, &["branch", "-m", "--", from, to])?;
+                    Ok(())
+                });
+            }
+            Step::SetRemoteHead { remote, branch } => {
+                return resolve::write_remote_head(repo, remote, branch);
+            }
+            Step::SetUpstream { branch, merge } => {
+                let key = format!("branch.{}.merge", branch);
+                let old = repo.config_snapshot().string(key.as_str());
+                let change = audit::Change {
+                    action: "set-upstream",
+                    name: &key,
+                    old: old.as_ref().and_then(|old| old.to_str().ok()),
+                    new: Some(merge),
+                };
+                return audit::record(repo, &change, || {
+                    git(dir, &["config", &key, merge])?;
+                    Ok(())
+                });
+            }
+            Step::SetInitDefaultBranch { branch } => {
+                git(dir, &["config", "--global", "init.defaultBranch", branch])?
+            }
+        };
+        Ok(())
+    }
+}
+
+pub fn run(
+    args: &MigrateArgs,
+    dir: &str,
+    remote: &str,
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    let steps = plan(
+        &repo,
+        remote,
+        &args.from,
+        &args.to,
+        args.init_default_branch,
+    )?;
+    apply_steps(&repo, &steps, dry_run)
+}
+
+pub fn retarget(
+    args: &RetargetArgs,
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    let to = match &args.to {
+        Some(to) => to.clone(),
+        None => resolve::resolve(&repo, remote, options)?,
+    };
+    if to == args.from {
+        return Err(format!("The default branch is still {}", to).into());
+    }
+    apply_steps(
+        &repo,
+        &upstream_steps(&repo, remote, &args.from, &to),
+        options.dry_run,
+    )
+}
+
+/// Applies `steps` in order, stopping at the first that fails with an error saying which were
+/// applied before it, since they are not undone.
+pub fn apply_steps(
+    repo: &gix::Repository,
+    steps: &[Step],
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for (done, step) in steps.iter().enumerate() {
+        if dry_run {
+            println!("Would {}", step);
+            continue;
+        }
+        if let Err(e) = step.apply(repo) {
+            let applied = match &steps[..done] {
+                [] => "nothing was changed".to_string(),
+                applied => format!(
+                    "already done: {}",
+                    applied
+                        .iter()
+                        .map(Step::to_string)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+            };
+            return Err(format!("Could not {}: {}; {}", step, e, applied).into());
+        }
+        println!("Did {}", step);
+    }
+    Ok(())
+}
+
+pub fn plan(
+    repo: &gix::Repository,
+    remote: &str,
+    from: &str,
+    to: &str,
+    init_default_branch: bool,
+) -> Result<Vec<Step>, Box<dyn std::error::Error>> {
+    // The remote HEAD can only point at a branch that has been fetched, which is checked before
+    // anything is changed.
+    let tracking = resolve::tracking_ref(repo, remote, to);
+    if repo.find_reference(tracking.as_str()).is_err() {
+        return Err(format!("{} does not exist; fetch {} first", tracking, remote).into());
+    }
+    let renamed = repo.find_reference(&format!("refs/heads/{}", from)).is_ok();
+
+    let mut steps = Vec::new();
+    if renamed {
+        steps.push(Step::RenameBranch {
+            from: from.to_string(),
+            to: to.to_string(),
+        });
+    }
+    steps.push(Step::SetRemoteHead {
+        remote: remote.to_string(),
+        branch: to.to_string(),
+    });
+    steps.extend(
+        upstream_steps(repo, remote, from, to)
+            .into_iter()
+            .map(|step| match step {
+                // `git branch -m` carries the branch configuration over to the new name.
+                Step::SetUpstream { branch, merge } if renamed && branch == from => {
+                    Step::SetUpstream {
+                        branch: to.to_string(),
+                        merge,
+                    }
+                }
+                step => step,
+            }),
+    );
+    if init_default_branch {
+        steps.push(Step::SetInitDefaultBranch {
+            branch: to.to_string(),
+        });
+    }
+    Ok(steps)
+}
+
+/// Returns steps pointing every branch that tracks `from` on `remote` at `to` instead.
+pub fn upstream_steps(repo: &gix::Repository, remote: &str, from: &str, to: &str) -> Vec<Step> {
+    let config = repo.config_snapshot();
+    let old_merge = format!("refs/heads/{}", from);
+    let Some(sections) = config.plumbing().sections_by_name("branch") else {
+        return Vec::new();
+    };
+    sections
+        .filter(|section| {
+            section.value("remote").as_deref() == Some(remote.as_bytes().as_bstr())
+                && section.value("merge").as_deref() == Some(old_merge.as_bytes().as_bstr())
+        })
+        .filter_map(|section| section.header().subsection_name()?.to_str().ok())
+        .map(|branch| Step::SetUpstream {
+            branch: branch.to_string(),
+            merge: format!("refs/heads/{}", to),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+    use std::fs;
+    use std::process::Command;
+
+    /// Clones a repository tracking `master` on a feature branch, then renames the remote's branch to `main`.
+    fn renamed_clone(tmp: &std::path::Path) -> std::path::PathBuf {
+        let repo_dir = tmp.join("repo");
+        let clone_dir = tmp.join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "master");
+        commit(&repo_dir, "initial");
+        Command::new("git")
+            .args([
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["checkout", "-b", "feature", "--track", "origin/master"])
+            .current_dir(&clone_dir)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["branch", "-m", "master", "main"])
+            .current_dir(&repo_dir)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["fetch", "--prune"])
+            .current_dir(&clone_dir)
+            .output()
+            .unwrap();
+
+        clone_dir
+    }
+
+    #[test]
+    fn test_migrate() {
+        let tmp = tempfile::tempdir().unwrap();
+        let clone_dir = renamed_clone(tmp.path());
+
+        let repo = gix::discover(&clone_dir).unwrap();
+        let error = plan(&repo, "origin", "master", "trunk", false).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "refs/remotes/origin/trunk does not exist; fetch origin first"
+        );
+        let steps = plan(&repo, "origin", "master", "main", false).unwrap();
+        assert_eq!(
+            steps,
+            vec![
+                Step::RenameBranch {
+                    from: "master".to_string(),
+                    to: "main".to_string()
+                },
+                Step::SetRemoteHead {
+                    remote: "origin".to_string(),
+                    branch: "main".to_string()
+                },
+                Step::SetUpstream {
+                    branch: "main".to_string(),
+                    merge: "refs/heads/main".to_string()
+                },
+                Step::SetUpstream {
+                    branch: "feature".to_string(),
+                    merge: "refs/heads/main".to_string()
+                },
+            ]
+        );
+
+        apply_steps(&repo, &steps, false).unwrap();
+        assert_eq!(
+            resolve::run(&clone_dir, "origin", &options()).unwrap(),
+            "main"
+        );
+        assert_eq!(
+            git(
+                &clone_dir,
+                &["rev-parse", "--abbrev-ref", "main@{upstream}"]
+            )
+            .unwrap(),
+            "origin/main"
+        );
+        assert_eq!(
+            git(
+                &clone_dir,
+                &["rev-parse", "--abbrev-ref", "feature@{upstream}"]
+            )
+            .unwrap(),
+            "origin/main"
+        );
+    }
+
+    #[test]
+    fn test_apply_steps_failure() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "master");
+        commit(tmp.path(), "initial");
+        let repo = gix::discover(tmp.path()).unwrap();
+        let rename = |from: &str, to: &str| Step::RenameBranch {
+            from: from.to_string(),
+            to: to.to_string(),
+        };
+
+        let error = apply_steps(&repo, &[rename("missing", "main")], false).unwrap_err();
+        assert!(
+            error.to_string().ends_with("; nothing was changed"),
+            "{}",
+            error
+        );
+        let steps = [rename("master", "main"), rename("missing", "trunk")];
+        let error = apply_steps(&repo, &steps, false).unwrap_err().to_string();
+        assert!(error.starts_with("Could not rename branch missing to trunk: "));
+        assert!(
+            error.ends_with("; already done: rename branch master to main"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn test_retarget() {
+        let tmp = tempfile::tempdir().unwrap();
+        let clone_dir = renamed_clone(tmp.path());
+        git(&clone_dir, &["remote", "set-head", "origin", "--auto"]).unwrap();
+
+        let args = RetargetArgs {
+            from: "master".to_string(),
+            to: None,
+        };
+        retarget(&args, clone_dir.to_str().unwrap(), "origin", &options()).unwrap();
+        assert_eq!(
+            git(
+                &clone_dir,
+                &["rev-parse", "--abbrev-ref", "feature@{upstream}"]
+            )
+            .unwrap(),
+            "origin/main"
+        );
+        // The local master branch is left alone apart from its upstream.
+        assert_eq!(
+            git(
+                &clone_dir,
+                &["rev-parse", "--abbrev-ref", "master@{upstream}"]
+            )
+            .unwrap(),
+            "origin/main"
+        );
+    }
+}
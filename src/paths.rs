@@ -0,0 +1,239 @@
+use std::borrow::Cow;
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Like [`fs::canonicalize`], but without the `\\?\` prefix Windows adds wherever the path works
+/// as well without it, since git and users expect `C:\repo` or `\\server\share\repo`.
+pub fn canonicalize(path: &Path) -> io::Result<PathBuf> {
+    let path = fs::canonicalize(path)?;
+    Ok(simplify(&path).into_owned())
+}
+
+/// Replaces the file at `path` with `contents` through a temporary file beside it, so concurrent
+/// readers see either the old or the new content, never part of it.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    static WRITES: AtomicUsize = AtomicUsize::new(0);
+    let name = path
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let mut tmp_name = name.to_os_string();
+    tmp_name.push(format!(
+        ".tmp.{}.{}",
+        std::process::id(),
+        WRITES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let tmp = path.with_file_name(tmp_name);
+    let result = fs::File::create(&tmp)
+        .and_then(|mut file| {
+            file.write_all(contents)?;
+            file.sync_all()
+        })
+        .and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result
+}
+
+/// Drops a `\\?\` or `\\?\UNC\` prefix when the path means the same without it. Paths that need
+/// it, being too long for `MAX_PATH` or naming something Win32 would reinterpret, are kept.
+pub fn simplify(path: &Path) -> Cow<'_, Path> {
+    match path.to_str().and_then(strip_verbatim) {
+        Some(simplified) if cfg!(windows) => Cow::Owned(PathBuf::from(simplified)),
+        _ => Cow::Borrowed(path),
+    }
+}
+
+fn strip_verbatim(path: &str) -> Option<String> {
+    let rest = path.strip_prefix(r"\\?\")?;
+    let simplified = match rest.strip_prefix(r"UNC\") {
+        Some(share) => format!(r"\\{}", share),
+        None => {
+            let bytes = rest.as_bytes();
+            if bytes.len() < 3 || !bytes[0].is_ascii_alphabetic() || &rest[1..3] != r":\" {
+                return None;
+            }
+            rest.to_string()
+        }
+    };
+    let components = simplified.trim_start_matches('\\').split('\\').skip(1);
+    if simplified.len() >= 260 || simplified.contains('/') || components.clone().any(reinterpreted)
+    {
+        return None;
+    }
+    Some(simplified)
+}
+
+/// Whether this runs inside WSL, where paths copied from Windows are translated by default.
+pub fn in_wsl() -> bool {
+    cfg!(target_os = "linux") && env::var_os("WSL_DISTRO_NAME").is_some()
+}
+
+/// Translates a path written for the other side of WSL into one that works here.
+///
+/// Under Linux, `C:\repo` becomes `/mnt/c/repo` and `\\wsl$\Ubuntu\home\repo` becomes
+/// `/home/repo`. Under Windows, `/mnt/c/repo` becomes `C:\repo`, and other absolute paths go
+/// through `\\wsl.localhost\<distro>` when `WSL_DISTRO_NAME` is shared through `WSLENV`.
+/// Returns `None` for paths that need no translation.
+pub fn translate_wsl(path: &str) -> Option<String> {
+    if cfg!(windows) {
+        to_windows(path, env::var("WSL_DISTRO_NAME").ok().as_deref())
+    } else {
+        to_linux(path)
+    }
+}
+
+fn to_linux(path: &str) -> Option<String> {
+    let unix = |rest: &str| rest.replace('\\', "/");
+    let bytes = path.as_bytes();
+    if bytes.len() >= 3
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && matches!(bytes[2], b'\\' | b'/')
+    {
+        let rest = unix(&path[3..]);
+        let drive = path[..1].to_ascii_lowercase();
+        return Some(
+            format!("/mnt/{}/{}", drive, rest)
+                .trim_end_matches('/')
+                .to_string(),
+        );
+    }
+    let share = unix(path);
+    let rest = share
+        .strip_prefix("//wsl$/")
+        .or_else(|| share.strip_prefix("//wsl.localhost/"))?;
+    let (_, rest) = rest.split_once('/').unwrap_or((rest, ""));
+    Some(format!("/{}", rest))
+}
+
+fn to_windows(path: &str, distro: Option<&str>) -> Option<String> {
+    let rest = path.strip_prefix('/')?;
+    if let Some(mounted) = rest.strip_prefix("mnt/") {
+        let (drive, rest) = mounted.split_once('/').unwrap_or((mounted, ""));
+        if drive.len() == 1 && drive.as_bytes()[0].is_ascii_alphabetic() {
+            return Some(format!(
+                "{}:\\{}",
+                drive.to_ascii_uppercase(),
+                rest.replace('/', "\\")
+            ));
+        }
+    }
+    Some(format!(
+        r"\\wsl.localhost\{}\{}",
+        distro?,
+        rest.replace('/', "\\")
+    ))
+}
+
+/// Whether Win32 path parsing would change what `name` refers to: `.` and `..` are resolved,
+/// trailing dots and spaces are dropped, and device names like `NUL` or `COM1.txt` are devices.
+fn reinterpreted(name: &str) -> bool {
+    if name.ends_with(['.', ' ']) {
+        return true;
+    }
+    let stem = name.split('.').next().unwrap_or(name).trim_end();
+    let upper = stem.to_ascii_uppercase();
+    matches!(
+        upper.as_str(),
+        "CON" | "PRN" | "AUX" | "NUL" | "CONIN$" | "CONOUT$"
+    ) || (upper.len() == 4
+        && (upper.starts_with("COM") || upper.starts_with("LPT"))
+        && upper.as_bytes()[3].is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_atomic() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("branch.txt");
+        write_atomic(&path, b"master\n").unwrap();
+        write_atomic(&path, b"main\n").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "main\n");
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+
+        assert!(write_atomic(&tmp.path().join("missing/branch.txt"), b"main\n").is_err());
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_strip_verbatim() {
+        assert_eq!(
+            strip_verbatim(r"\\?\C:\Users\me\repo"),
+            Some(r"C:\Users\me\repo".to_string())
+        );
+        assert_eq!(
+            strip_verbatim(r"\\?\UNC\server\share\repo"),
+            Some(r"\\server\share\repo".to_string())
+        );
+        assert_eq!(strip_verbatim(r"C:\Users\me\repo"), None);
+        assert_eq!(strip_verbatim(r"\\?\Volume{1234}\repo"), None);
+        assert_eq!(strip_verbatim(r"\\?\C:\repo\nul"), None);
+        assert_eq!(strip_verbatim(r"\\?\C:\repo\com1.txt"), None);
+        assert_eq!(strip_verbatim(r"\\?\C:\repo\trailing."), None);
+        assert_eq!(strip_verbatim(r"\\?\C:\repo\a/b"), None);
+        assert_eq!(
+            strip_verbatim(r"\\?\C:\repo\computer"),
+            Some(r"C:\repo\computer".to_string())
+        );
+        let long = format!(r"\\?\C:\{}", "a\\".repeat(130));
+        assert_eq!(strip_verbatim(&long), None);
+    }
+
+    #[test]
+    fn test_translate_wsl() {
+        assert_eq!(
+            to_linux(r"C:\Users\me\repo"),
+            Some("/mnt/c/Users/me/repo".to_string())
+        );
+        assert_eq!(to_linux("D:/src/repo"), Some("/mnt/d/src/repo".to_string()));
+        assert_eq!(to_linux(r"C:\"), Some("/mnt/c".to_string()));
+        assert_eq!(
+            to_linux(r"\\wsl$\Ubuntu\home\me\repo"),
+            Some("/home/me/repo".to_string())
+        );
+        assert_eq!(
+            to_linux("//wsl.localhost/Ubuntu/home/me"),
+            Some("/home/me".to_string())
+        );
+        assert_eq!(to_linux("/home/me/repo"), None);
+        assert_eq!(to_linux("src/repo"), None);
+
+        assert_eq!(
+            to_windows("/mnt/c/Users/me/repo", None),
+            Some(r"C:\Users\me\repo".to_string())
+        );
+        assert_eq!(
+            to_windows("/home/me/repo", Some("Ubuntu")),
+            Some(r"\\wsl.localhost\Ubuntu\home\me\repo".to_string())
+        );
+        assert_eq!(to_windows("/home/me/repo", None), None);
+        assert_eq!(to_windows(r"C:\repo", Some("Ubuntu")), None);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn test_canonicalize() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = canonicalize(tmp.path()).unwrap();
+        assert!(!path.to_str().unwrap().starts_with(r"\\?\"), "{:?}", path);
+        assert!(path.is_absolute());
+
+        // A share path to the same directory resolves and is reported without the prefix too.
+        let drive = path.to_str().unwrap().chars().next().unwrap();
+        let share = format!(r"\\localhost\{}$\{}", drive, &path.to_str().unwrap()[3..]);
+        if let Ok(shared) = canonicalize(Path::new(&share)) {
+            assert!(
+                !shared.to_str().unwrap().starts_with(r"\\?\"),
+                "{:?}",
+                shared
+            );
+        }
+    }
+}
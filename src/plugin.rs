@@ -0,0 +1,168 @@
+use crate::json;
+use std::env;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Executables on `PATH` whose names start with this are resolvers.
+const PREFIX: &str = "git-default-branch-resolver-";
+
+/// Finds the resolvers on `PATH`, ordered by name. An earlier `PATH` entry shadows a resolver of
+/// the same name in a later one, as for any command.
+pub fn find() -> Vec<PathBuf> {
+    env::var_os("PATH")
+        .map(|path| find_in(env::split_paths(&path)))
+        .unwrap_or_default()
+}
+
+fn find_in(dirs: impl Iterator<Item = PathBuf>) -> Vec<PathBuf> {
+    let mut found: Vec<(String, PathBuf)> = Vec::new();
+    for dir in dirs {
+        let Ok(entries) = dir.read_dir() else {
+            continue;
+        };
+        for entry in entries.filter_map(Result::ok) {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let name = name.strip_suffix(".exe").unwrap_or(&name).to_string();
+            if name.starts_with(PREFIX)
+                && is_executable(&entry.path())
+                && !found.iter().any(|(n, _)| *n == name)
+            {
+                found.push((name, entry.path()));
+            }
+        }
+    }
+    found.sort();
+    found.into_iter().map(|(_, path)| path).collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata()
+        .is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// Asks the resolver at `plugin` for the default branch of `remote`.
+///
+/// The resolver reads `{"version": 1, "git_dir", "workdir", "remote", "url"}` on stdin and
+/// answers `{"branch": "..."}`, or `{"branch": null}` when it does not know. Any other answer or
+/// a non-zero exit counts as not knowing.
+pub fn resolve(plugin: &Path, repo: &gix::Repository, remote: &str) -> Option<String> {
+    let url = repo.find_remote(remote).ok().and_then(|remote| {
+        remote
+            .url(gix::remote::Direction::Fetch)
+            .map(|url| url.to_bstring().to_string())
+    });
+    ask(
+        plugin,
+        repo.git_dir(),
+        repo.workdir(),
+        remote,
+        url.as_deref(),
+    )
+}
+
+/// Like [`resolve`] for a repository opened some other way, described by its directories and
+/// the fetch URL of `remote`, if it has one.
+pub fn ask(
+    plugin: &Path,
+    git_dir: &Path,
+    workdir: Option<&Path>,
+    remote: &str,
+    url: Option<&str>,
+) -> Option<String> {
+    let request = format!(
+        "{{\"version\":1,\"git_dir\":{},\"workdir\":{},\"remote\":{},\"url\":{}}}",
+        json::quote(&git_dir.to_string_lossy()),
+        workdir.map_or("null".to_string(), |dir| json::quote(
+            &dir.to_string_lossy()
+        )),
+        json::quote(remote),
+        url.map_or("null".to_string(), json::quote),
+    );
+    log::debug!("running resolver {}", plugin.display());
+    let mut child = Command::new(plugin)
+        .current_dir(workdir.unwrap_or(git_dir))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    // A resolver that answers without reading its input closes the pipe early, which is fine.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(request.as_bytes());
+    }
+    let output = child.wait_with_output().ok()?;
+    if !output.status.success() {
+        log::debug!("{} exited with {}", plugin.display(), output.status);
+        return None;
+    }
+    parse_response(&String::from_utf8_lossy(&output.stdout))
+}
+
+fn parse_response(response: &str) -> Option<String> {
+    let branch = json::parse(response)
+        .ok()?
+        .get("branch")?
+        .as_str()?
+        .to_string();
+    (!branch.is_empty()).then_some(branch)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo};
+    use std::fs;
+
+    #[cfg(unix)]
+    fn write_plugin(path: &Path, script: &str) {
+        use std::os::unix::fs::PermissionsExt;
+        fs::write(path, script).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_plugins() {
+        let tmp = tempfile::tempdir().unwrap();
+        let (first, second) = (tmp.path().join("a"), tmp.path().join("b"));
+        fs::create_dir(&first).unwrap();
+        fs::create_dir(&second).unwrap();
+        let repo_dir = tmp.path().join("repo");
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "main");
+        commit(&repo_dir, "initial");
+
+        // The resolver echoes the remote it was asked about as the branch.
+        let echo = format!("{}echo", PREFIX);
+        write_plugin(
+            &first.join(&echo),
+            "#!/bin/sh\nsed -e 's/.*\"remote\":\"\\([^\"]*\\)\".*/{\"branch\":\"\\1\"}/'\n",
+        );
+        write_plugin(&second.join(&echo), "#!/bin/sh\nexit 1\n");
+        write_plugin(
+            &second.join(format!("{}unsure", PREFIX)),
+            "#!/bin/sh\necho '{\"branch\":null}'\n",
+        );
+        fs::write(second.join(format!("{}not-executable", PREFIX)), "").unwrap();
+
+        let plugins = find_in([first.clone(), second.clone()].into_iter());
+        assert_eq!(
+            plugins,
+            vec![first.join(&echo), second.join(format!("{}unsure", PREFIX))]
+        );
+        let repo = gix::discover(&repo_dir).unwrap();
+        assert_eq!(
+            resolve(&plugins[0], &repo, "upstream"),
+            Some("upstream".to_string())
+        );
+        assert_eq!(resolve(&plugins[1], &repo, "upstream"), None);
+        assert_eq!(resolve(&second.join(&echo), &repo, "upstream"), None);
+    }
+}
@@ -0,0 +1,289 @@
+use crate::config::{self, Config};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// Whether the tool may reach the network at all.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Network {
+    /// As the command line and configuration allow.
+    #[default]
+    Auto,
+    /// Never, whatever else says otherwise.
+    Never,
+}
+
+impl Network {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "auto" => Some(Network::Auto),
+            "never" => Some(Network::Never),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Network::Auto => "auto",
+            Network::Never => "never",
+        }
+    }
+}
+
+/// Restrictions an administrator sets in the configuration file or the environment, which no
+/// command-line flag or repository setting can lift.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Policy {
+    pub network: Network,
+    /// The hosts the network may be used with, and their subdomains; any when `None`.
+    pub allowed_hosts: Option<Vec<String>>,
+}
+
+impl Policy {
+    /// Combines the system-wide and default configuration files, the one `--config` names, and
+    /// `GIT_DEFAULT_BRANCH_NETWORK` and `GIT_DEFAULT_BRANCH_ALLOWED_HOSTS`, the stricter winning:
+    /// hosts must be allowed by each that lists any. The fixed files are read even with
+    /// `--config` or `--no-config`, so a configuration the user picks can only add restrictions.
+    pub fn load(config: &Config) -> Result<Self, String> {
+        Policy::load_from(&fixed_paths(), config)
+    }
+
+    fn load_from(paths: &[PathBuf], config: &Config) -> Result<Self, String> {
+        let mut configs = Vec::new();
+        for path in paths {
+            match fs::read_to_string(path) {
+                Ok(content) => configs.push(
+                    Config::parse(&content).map_err(|e| format!("{}: {}", path.display(), e))?,
+                ),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(format!("{}: {}", path.display(), e)),
+            }
+        }
+        let env = match std::env::var("GIT_DEFAULT_BRANCH_NETWORK") {
+            Ok(value) if !value.is_empty() => Network::parse(&value).ok_or_else(|| {
+                format!(
+                    "GIT_DEFAULT_BRANCH_NETWORK must be \"auto\" or \"never\", not {}",
+                    value
+                )
+            })?,
+            _ => Network::Auto,
+        };
+        let env_hosts = std::env::var("GIT_DEFAULT_BRANCH_ALLOWED_HOSTS")
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|host| host.trim().to_string())
+                    .filter(|host| !host.is_empty())
+                    .collect::<Vec<_>>()
+            });
+        let configs = configs.iter().chain([config]);
+        Ok(Policy {
+            network: match configs.clone().any(|c| c.network == Some(Network::Never)) {
+                true => Network::Never,
+                false => env,
+            },
+            allowed_hosts: configs
+                .filter_map(|c| c.allowed_hosts.clone())
+                .chain(env_hosts)
+                .reduce(|allowed, hosts| intersect(&allowed, &hosts)),
+        })
+    }
+
+    /// Whether the network may be used with `host`.
+    #[cfg(any(feature = "forge", feature = "subprocess"))]
+    pub fn allows_host(&self, host: &str) -> bool {
+        self.allowed_hosts
+            .as_ref()
+            .is_none_or(|hosts| hosts.iter().any(|allowed| matches_host(host, allowed)))
+    }
+}
+
+/// The hosts both `a` and `b` allow: those of each that the other allows, subdomains included.
+fn intersect(a: &[String], b: &[String]) -> Vec<String> {
+    let mut hosts = Vec::new();
+    for (hosts_of, other) in [(a, b), (b, a)] {
+        for host in hosts_of {
+            if other.iter().any(|allowed| matches_host(host, allowed))
+                && !hosts.iter().any(|h: &String| h.eq_ignore_ascii_case(host))
+            {
+                hosts.push(host.clone());
+            }
+        }
+    }
+    hosts
+}
+
+/// Configuration files the policy is read from whatever `--config` and `--no-config` say: the
+/// system-wide one and the user's default one.
+fn fixed_paths() -> Vec<PathBuf> {
+    let system = match cfg!(windows) {
+        true => std::env::var_os("ProgramData").map(PathBuf::from),
+        false => Some(PathBuf::from("/etc")),
+    };
+    system
+        .map(|dir| dir.join("git-default-branch/config.toml"))
+        .into_iter()
+        .chain(config::default_path())
+        .collect()
+}
+
+/// Whether `host` is `allowed` or one of its subdomains, so allowing `github.com` allows its API
+/// at `api.github.com` too.
+fn matches_host(host: &str, allowed: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    let allowed = allowed.to_ascii_lowercase();
+    host == allowed || host.ends_with(&format!(".{}", allowed))
+}
+
+static POLICY: OnceLock<Policy> = OnceLock::new();
+
+/// Applies `policy` for the rest of the process.
+pub fn init(policy: Policy) {
+    let _ = POLICY.set(policy);
+}
+
+pub fn get() -> Policy {
+    POLICY.get().cloned().unwrap_or_default()
+}
+
+/// Whether the network may be used.
+pub fn network_allowed() -> bool {
+    get().network != Network::Never
+}
+
+/// Fails when the policy forbids the network, before `what` is attempted.
+#[cfg(any(feature = "forge", feature = "subprocess"))]
+pub fn check_network(what: &str) -> Result<(), String> {
+    match network_allowed() {
+        true => Ok(()),
+        false => Err(format!(
+            "{} needs the network, which the network = \"never\" policy forbids",
+            what
+        )),
+    }
+}
+
+/// Fails when the policy forbids contacting the host of `url`, before it is contacted.
+#[cfg(any(feature = "forge", feature = "subprocess"))]
+pub fn check_url(url: &str) -> Result<(), String> {
+    check_network(&format!("Contacting {}", url))?;
+    let host = gix::url::parse(url.into())
+        .ok()
+        .and_then(|url| url.host().map(str::to_string));
+    match host {
+        Some(host) if !get().allows_host(&host) => Err(format!(
+            "Not contacting {}: {} is not in allowed_hosts",
+            url, host
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the policy lets `remote` of `repo` be contacted, warning when it does not so the
+/// resolution falls back to what is known locally. Remotes without a host, like paths, are
+/// always allowed.
+#[cfg(feature = "subprocess")]
+pub fn allows_remote(repo: &gix::Repository, remote: &str) -> bool {
+    let url = repo
+        .find_remote(remote)
+        .ok()
+        .map(|found| found.url(gix::remote::Direction::Fetch).cloned());
+    allows_remote_url(remote, url)
+}
+
+/// Like [`allows_remote`] for a remote whose fetch URL was looked up already: `None` when no
+/// remote of that name exists, so `remote` may be a URL itself.
+#[cfg(feature = "subprocess")]
+pub fn allows_remote_url(remote: &str, url: Option<Option<gix::Url>>) -> bool {
+    let url = url.unwrap_or_else(|| gix::url::parse(remote.into()).ok());
+    let Some(host) = url.as_ref().and_then(|url| url.host()) else {
+        return true;
+    };
+    let allowed = get().allows_host(host);
+    if !allowed {
+        log::warn!(
+            "not contacting {}: {} is not in allowed_hosts; resolving locally",
+            remote,
+            host
+        );
+    }
+    allowed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network() {
+        assert_eq!(Network::parse("never"), Some(Network::Never));
+        assert_eq!(Network::parse("auto"), Some(Network::Auto));
+        assert_eq!(Network::parse("always"), None);
+        let config = Config {
+            network: Some(Network::Never),
+            ..Config::default()
+        };
+        assert_eq!(
+            Policy::load_from(&[], &config).unwrap().network,
+            Network::Never
+        );
+    }
+
+    #[test]
+    fn test_fixed_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        let path = tmp.path().join("config.toml");
+        fs::write(
+            &path,
+            "network = \"never\"\nallowed_hosts = [\"github.com\", \"gitlab.com\"]\n",
+        )
+        .unwrap();
+        let paths = [tmp.path().join("missing.toml"), path];
+
+        // What `--no-config` loads cannot lift the fixed file's policy.
+        let policy = Policy::load_from(&paths, &Config::default()).unwrap();
+        assert_eq!(policy.network, Network::Never);
+        assert_eq!(
+            policy.allowed_hosts,
+            Some(vec!["github.com".to_string(), "gitlab.com".to_string()])
+        );
+
+        // Another file from `--config` can only narrow it.
+        let other = Config {
+            network: Some(Network::Auto),
+            allowed_hosts: Some(vec![
+                "api.github.com".to_string(),
+                "evil.example".to_string(),
+            ]),
+            ..Config::default()
+        };
+        let policy = Policy::load_from(&paths, &other).unwrap();
+        assert_eq!(policy.network, Network::Never);
+        assert_eq!(
+            policy.allowed_hosts,
+            Some(vec!["api.github.com".to_string()])
+        );
+    }
+
+    #[test]
+    #[cfg(any(feature = "forge", feature = "subprocess"))]
+    fn test_allows_host() {
+        assert!(Policy::default().allows_host("example.com"));
+        let policy = Policy {
+            allowed_hosts: Some(vec!["github.com".to_string()]),
+            ..Policy::default()
+        };
+        assert!(policy.allows_host("github.com"));
+        assert!(policy.allows_host("API.GitHub.com"));
+        assert!(!policy.allows_host("gitlab.com"));
+        assert!(!policy.allows_host("evilgithub.com"));
+        assert!(!policy.allows_host("github.com.evil.example"));
+        let nothing = Policy {
+            allowed_hosts: Some(vec![]),
+            ..Policy::default()
+        };
+        assert!(!nothing.allows_host("github.com"));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::git::{self, git, run_dir};
+use crate::resolve;
+
+#[derive(clap::Args)]
+pub struct PrBaseArgs {
+    /// Branch or commit the pull request is for
+    #[arg(default_value = "HEAD")]
+    rev: String,
+}
+
+pub fn run(
+    args: &PrBaseArgs,
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = git::discover(dir)?;
+    println!("{}", nearest(&repo, &args.rev, remote, options)?);
+    Ok(())
+}
+
+/// Returns the branch work on `rev` should target: the branch matching one of the release
+/// patterns that `rev` has the fewest commits beyond, when that is fewer than beyond the default
+/// branch, and the default branch otherwise.
+///
+/// A release branch cut after `rev` forked is as close as the default branch, which then wins,
+/// while a fix built on a release branch's own commits targets that branch.
+pub fn nearest(
+    repo: &gix::Repository,
+    rev: &str,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let default = resolve::resolve(repo, remote, options)?;
+    let options = options.for_repo(repo, remote);
+    let patterns = options.release_patterns();
+    let current = match rev {
+        "HEAD" => repo.head_name()?.map(|name| name.shorten().to_string()),
+        rev => Some(rev.to_string()),
+    };
+    let mut names = resolve::remote_branches(repo, remote)?;
+    for branch in resolve::local_branches(repo)? {
+        if !names.contains(&branch) {
+            names.push(branch);
+        }
+    }
+
+    let distance = |name: &str| -> Result<usize, Box<dyn std::error::Error>> {
+        let range = format!("{}..{}", compared_ref(repo, remote, name), rev);
+        let count = git(
+            run_dir(repo),
+            &["rev-list", "--count", "--end-of-options", &range],
+        )?;
+        Ok(count.parse()?)
+    };
+    let mut best = (distance(&default)?, default.clone());
+    for name in names {
+        if name == default
+            || current.as_deref() == Some(name.as_str())
+            || !matches(&patterns, &name)
+        {
+            continue;
+        }
+        match distance(&name) {
+            Ok(distance) if distance < best.0 => best = (distance, name),
+            Ok(_) => {}
+            Err(e) => log::debug!("skipping {}: {}", name, e),
+        }
+    }
+    Ok(best.1)
+}
+
+/// The ref work is compared against for `branch`: its remote-tracking branch when fetched, as
+/// that is what the work will be merged into, or else the local branch.
+pub fn compared_ref(repo: &gix::Repository, remote: &str, branch: &str) -> String {
+    let tracking = resolve::tracking_ref(repo, remote, branch);
+    if repo.find_reference(tracking.as_str()).is_ok() {
+        tracking
+    } else {
+        format!("refs/heads/{}", branch)
+    }
+}
+
+fn matches(patterns: &[&str], name: &str) -> bool {
+    patterns.iter().any(|pattern| {
+        gix::glob::wildmatch(
+            (*pattern).into(),
+            name.into(),
+            gix::glob::wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+
+    #[test]
+    fn test_nearest() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        git(tmp.path(), &["branch", "release/1.0"]).unwrap();
+        git(tmp.path(), &["branch", "feature"]).unwrap();
+        commit(tmp.path(), "main 1");
+
+        let base = |rev, options: &resolve::Options| {
+            let repo = git::discover(tmp.path()).unwrap();
+            nearest(&repo, rev, "origin", options).unwrap()
+        };
+        // The release branch was cut where the feature forked, so the default branch wins.
+        assert_eq!(base("feature", &options()), "main");
+
+        git(tmp.path(), &["checkout", "-q", "release/1.0"]).unwrap();
+        commit(tmp.path(), "release fix");
+        assert_eq!(base("HEAD", &options()), "main");
+        git(tmp.path(), &["checkout", "-q", "-b", "backport"]).unwrap();
+        commit(tmp.path(), "backport");
+        assert_eq!(base("HEAD", &options()), "release/1.0");
+        assert_eq!(base("feature", &options()), "main");
+
+        let stable = resolve::Options {
+            release_patterns: vec!["stable-*".to_string()],
+            ..options()
+        };
+        assert_eq!(base("backport", &stable), "main");
+        git(
+            tmp.path(),
+            &["config", "defaultBranch.releasePatterns", "stable-*"],
+        )
+        .unwrap();
+        assert_eq!(base("backport", &options()), "main");
+    }
+}
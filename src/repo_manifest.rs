@@ -0,0 +1,399 @@
+use crate::batch::{self, BatchArgs};
+use crate::resolve;
+use crate::scan::{self, Format};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// How deeply `<include>` may nest, so a manifest including itself fails instead of looping.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// A project of an Android `repo` manifest, with the remote and revision it inherits filled in.
+#[derive(Debug, PartialEq)]
+pub struct Project {
+    pub name: String,
+    /// Where the project is checked out, relative to the top of the repo client.
+    pub path: String,
+    pub remote: String,
+    /// The base URL the project's name is appended to for fetching.
+    pub fetch: Option<String>,
+    /// The revision the manifest tracks, if any.
+    pub revision: Option<String>,
+}
+
+/// An element's name and attributes.
+struct Tag {
+    name: String,
+    attributes: Vec<(String, String)>,
+}
+
+impl Tag {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// The elements of a manifest that decide its projects, gathered across its includes.
+#[derive(Default)]
+struct Manifest {
+    /// Remotes by name, with their fetch URL and revision.
+    remotes: Vec<(String, String, Option<String>)>,
+    default_remote: Option<String>,
+    default_revision: Option<String>,
+    projects: Vec<Tag>,
+}
+
+/// Reads the manifest at `path` and the manifests it includes, returning its projects.
+pub fn load(path: &Path) -> Result<Vec<Project>, Box<dyn std::error::Error>> {
+    let mut manifest = Manifest::default();
+    read(path, &mut manifest, 0)?;
+    manifest
+        .projects
+        .iter()
+        .map(|tag| {
+            let name = tag.get("name").ok_or("A <project> has no name")?;
+            let remote = tag
+                .get("remote")
+                .map(str::to_string)
+                .or_else(|| manifest.default_remote.clone())
+                .ok_or_else(|| format!("Project {} has no remote and there is no default", name))?;
+            let found = manifest.remotes.iter().find(|(n, _, _)| *n == remote);
+            Ok(Project {
+                name: name.to_string(),
+                path: tag.get("path").unwrap_or(name).to_string(),
+                fetch: found.map(|(_, fetch, _)| fetch.clone()),
+                revision: tag
+                    .get("revision")
+                    .map(str::to_string)
+                    .or_else(|| found.and_then(|(_, _, revision)| revision.clone()))
+                    .or_else(|| manifest.default_revision.clone()),
+                remote,
+            })
+        })
+        .collect()
+}
+
+fn read(path: &Path, manifest: &mut Manifest, depth: usize) -> Result<(), String> {
+    if depth > MAX_INCLUDE_DEPTH {
+        return Err(format!(
+            "{}: includes nest more than {} deep",
+            path.display(),
+            MAX_INCLUDE_DEPTH
+        ));
+    }
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Cannot read the manifest {}: {}", path.display(), e))?;
+    for tag in tags(&text).map_err(|e| format!("{}: {}", path.display(), e))? {
+        match tag.name.as_str() {
+            "remote" => {
+                let (Some(name), Some(fetch)) = (tag.get("name"), tag.get("fetch")) else {
+                    return Err(format!(
+                        "{}: a <remote> needs a name and a fetch URL",
+                        path.display()
+                    ));
+                };
+                manifest.remotes.retain(|(n, _, _)| n != name);
+                manifest.remotes.push((
+                    name.to_string(),
+                    fetch.to_string(),
+                    tag.get("revision").map(str::to_string),
+                ));
+            }
+            "default" => {
+                if let Some(remote) = tag.get("remote") {
+                    manifest.default_remote = Some(remote.to_string());
+                }
+                if let Some(revision) = tag.get("revision") {
+                    manifest.default_revision = Some(revision.to_string());
+                }
+            }
+            "project" => manifest.projects.push(tag),
+            "remove-project" => {
+                let name = tag.get("name");
+                manifest
+                    .projects
+                    .retain(|project| project.get("name") != name);
+            }
+            "include" => {
+                let name = tag
+                    .get("name")
+                    .ok_or_else(|| format!("{}: an <include> has no name", path.display()))?;
+                let included = path.parent().unwrap_or(Path::new("")).join(name);
+                read(&included, manifest, depth + 1)?;
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Returns the start and empty-element tags of an XML document in order, skipping declarations,
+/// comments, end tags and text, which is all a manifest needs.
+fn tags(text: &str) -> Result<Vec<Tag>, String> {
+    let mut tags = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        if let Some(comment) = rest.strip_prefix("!--") {
+            let end = comment.find("-->").ok_or("unterminated comment")?;
+            rest = &comment[end + 3..];
+            continue;
+        }
+        let end = tag_end(rest).ok_or("unterminated tag")?;
+        let content = &rest[..end];
+        rest = &rest[end + 1..];
+        if content.starts_with(['?', '!', '/']) {
+            continue;
+        }
+        tags.push(tag(content.trim_end_matches('/'))?);
+    }
+    Ok(tags)
+}
+
+/// The position of the `>` closing the tag `rest` starts with, outside quoted attribute values.
+fn tag_end(rest: &str) -> Option<usize> {
+    let mut quote = None;
+    for (i, c) in rest.char_indices() {
+        match (quote, c) {
+            (None, '>') => return Some(i),
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            _ => {}
+        }
+    }
+    None
+}
+
+fn tag(content: &str) -> Result<Tag, String> {
+    let content = content.trim();
+    let name_end = content
+        .find(|c: char| c.is_whitespace())
+        .unwrap_or(content.len());
+    let mut tag = Tag {
+        name: content[..name_end].to_string(),
+        attributes: Vec::new(),
+    };
+    let mut rest = content[name_end..].trim_start();
+    while !rest.is_empty() {
+        let (key, after) = rest
+            .split_once('=')
+            .ok_or_else(|| format!("expected an attribute value in <{}>", tag.name))?;
+        let after = after.trim_start();
+        let quote = after
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\''))
+            .ok_or_else(|| format!("unquoted attribute value in <{}>", tag.name))?;
+        let value_end = after[1..]
+            .find(quote)
+            .ok_or_else(|| format!("unterminated attribute value in <{}>", tag.name))?;
+        tag.attributes
+            .push((key.trim().to_string(), unescape(&after[1..1 + value_end])));
+        rest = after[value_end + 2..].trim_start();
+    }
+    Ok(tag)
+}
+
+fn unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// The branch a manifest revision tracks, or `None` for a commit or tag, which cannot drift from
+/// a default branch.
+fn tracked_branch(revision: &str) -> Option<&str> {
+    if revision.starts_with("refs/tags/")
+        || revision.len() == 40 && revision.chars().all(|c| c.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    Some(revision.strip_prefix("refs/heads/").unwrap_or(revision))
+}
+
+/// The top of the repo client holding `manifest`, found as the parent of its `.repo` directory,
+/// or the current directory for a manifest kept elsewhere.
+fn client_top(manifest: &Path) -> PathBuf {
+    let manifest = fs::canonicalize(manifest).unwrap_or_else(|_| manifest.to_path_buf());
+    manifest
+        .ancestors()
+        .find(|dir| dir.file_name().is_some_and(|name| name == ".repo"))
+        .and_then(Path::parent)
+        .map_or_else(|| PathBuf::from("."), Path::to_path_buf)
+}
+
+/// Prints `path<TAB>branch` for each project of the manifest at `manifest`, or one JSON array
+/// with `json`, from its checkout in the repo client or else by asking its fetch URL, and reports
+/// on stderr each project whose manifest revision is a branch other than its default branch.
+/// Fails if any project could not be resolved or does not track its default branch.
+pub fn print(
+    manifest: &Path,
+    options: &resolve::Options,
+    batch: &BatchArgs,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let projects = load(manifest)?;
+    let top = client_top(manifest);
+    let results = batch::map_parallel(&projects, batch.jobs, |project| {
+        resolve_project(&top, project, options).map_err(|e| e.to_string())
+    });
+    if !batch.summary_only && json {
+        let entries = projects
+            .iter()
+            .zip(&results)
+            .map(|(project, result)| scan::Entry {
+                name: project.path.clone(),
+                result: result.clone(),
+                remote: batch.with_remote.map(|_| project.remote.clone()),
+            })
+            .collect::<Vec<_>>();
+        scan::write_entries(&mut io::stdout().lock(), "path", &entries, Format::Json)?;
+    } else if !batch.summary_only {
+        for (project, result) in projects.iter().zip(&results) {
+            match (result, batch.with_remote) {
+                (Ok(branch), Some(style)) => {
+                    println!("{}\t{}", project.path, style.join(&project.remote, branch))
+                }
+                (Ok(branch), None) => println!("{}\t{}", project.path, branch),
+                (Err(e), _) => eprintln!("{}: {}", project.path, e),
+            }
+        }
+    }
+    let mut mismatches = 0;
+    for (project, result) in projects.iter().zip(&results) {
+        if let (Ok(branch), Some(tracked)) =
+            (result, project.revision.as_deref().and_then(tracked_branch))
+            && tracked != branch
+        {
+            eprintln!(
+                "{}: the manifest tracks {}, but the default branch is {}",
+                project.path, tracked, branch
+            );
+            mismatches += 1;
+        }
+    }
+    batch::print_summary(results.iter().map(|r| r.as_deref().ok()), batch);
+
+    let failures = results.iter().filter(|r| r.is_err()).count();
+    if failures > 0 {
+        return Err(format!("Failed to resolve {} projects", failures).into());
+    }
+    if mismatches > 0 {
+        return Err(format!("{} projects do not track their default branch", mismatches).into());
+    }
+    Ok(())
+}
+
+fn resolve_project(
+    top: &Path,
+    project: &Project,
+    options: &resolve::Options,
+) -> Result<String, Box<dyn std::error::Error>> {
+    let dir = top.join(&project.path);
+    if dir.join(".git").exists() {
+        return resolve::run(&dir, &project.remote, &options.with_explicit_remote());
+    }
+    let fetch = project.fetch.as_deref().ok_or_else(|| {
+        format!(
+            "not checked out, and remote {} is not defined",
+            project.remote
+        )
+    })?;
+    if !fetch.contains("://") && !fetch.contains('@') {
+        return Err(format!(
+            "not checked out, and the fetch URL {} is relative to the manifest's",
+            fetch
+        )
+        .into());
+    }
+    #[cfg(feature = "subprocess")]
+    {
+        let url = format!("{}/{}", fetch.trim_end_matches('/'), project.name);
+        Ok(resolve::run_url(&url, options)?.branch)
+    }
+    #[cfg(not(feature = "subprocess"))]
+    Err("not checked out, and asking a URL needs the subprocess feature".into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load() {
+        let tmp = tempfile::tempdir().unwrap();
+        fs::write(
+            tmp.path().join("default.xml"),
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<manifest>
+  <!-- <project name="commented/out" /> -->
+  <remote name="aosp" fetch="https://android.googlesource.com" />
+  <remote name="vendor" fetch="ssh://git@git.example.com/firmware" revision="refs/heads/trunk" />
+  <default remote="aosp" revision="main" sync-j="4" />
+  <project path="build/make" name="platform/build" groups="pdk">
+    <copyfile src="core/root.mk" dest="Makefile" />
+  </project>
+  <project name="bootloader" remote="vendor" />
+  <project name="kernel &amp; drivers" revision="refs/tags/v1.0" />
+  <project name="dropped" />
+  <include name="local.xml" />
+</manifest>
+"#,
+        )
+        .unwrap();
+        fs::write(
+            tmp.path().join("local.xml"),
+            "<manifest>\n  <remove-project name='dropped'/>\n</manifest>\n",
+        )
+        .unwrap();
+
+        let projects = load(&tmp.path().join("default.xml")).unwrap();
+        assert_eq!(
+            projects,
+            [
+                Project {
+                    name: "platform/build".to_string(),
+                    path: "build/make".to_string(),
+                    remote: "aosp".to_string(),
+                    fetch: Some("https://android.googlesource.com".to_string()),
+                    revision: Some("main".to_string()),
+                },
+                Project {
+                    name: "bootloader".to_string(),
+                    path: "bootloader".to_string(),
+                    remote: "vendor".to_string(),
+                    fetch: Some("ssh://git@git.example.com/firmware".to_string()),
+                    revision: Some("refs/heads/trunk".to_string()),
+                },
+                Project {
+                    name: "kernel & drivers".to_string(),
+                    path: "kernel & drivers".to_string(),
+                    remote: "aosp".to_string(),
+                    fetch: Some("https://android.googlesource.com".to_string()),
+                    revision: Some("refs/tags/v1.0".to_string()),
+                },
+            ]
+        );
+
+        assert_eq!(tracked_branch("refs/heads/trunk"), Some("trunk"));
+        assert_eq!(tracked_branch("main"), Some("main"));
+        assert_eq!(tracked_branch("refs/tags/v1.0"), None);
+        assert_eq!(
+            tracked_branch("0123456789abcdef0123456789abcdef01234567"),
+            None
+        );
+
+        fs::write(
+            tmp.path().join("loop.xml"),
+            "<manifest><include name=\"loop.xml\"/></manifest>",
+        )
+        .unwrap();
+        assert!(load(&tmp.path().join("loop.xml")).is_err());
+    }
+}
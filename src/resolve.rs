@@ -0,0 +1,2508 @@
+use crate::config::HostProfile;
+#[cfg(feature = "libgit2")]
+use crate::libgit2;
+#[cfg(feature = "subprocess")]
+use crate::plugin;
+#[cfg(feature = "scripting")]
+use crate::script;
+#[cfg(feature = "network")]
+use crate::serve;
+use crate::{audit, cache, diagnose, fast, git, i18n, interactive, json, policy, redact};
+#[cfg(feature = "subprocess")]
+use crate::{ssh, tls};
+use clap::builder::BoolishValueParser;
+use gix::bstr::ByteSlice;
+use std::cell::RefCell;
+#[cfg(feature = "subprocess")]
+use std::ffi::OsStr;
+use std::path::Path;
+use std::time::{Duration, Instant};
+#[cfg(feature = "subprocess")]
+use std::{fs, io, process};
+
+/// Where a setting came from, from the highest precedence to the lowest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Source {
+    CommandLine,
+    Environment,
+    GitConfig,
+    ConfigFile,
+    #[default]
+    Default,
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Source::CommandLine => "command line",
+            Source::Environment => "environment",
+            Source::GitConfig => "git config",
+            Source::ConfigFile => "config file",
+            Source::Default => "default",
+        })
+    }
+}
+
+/// Settings that control how the default branch is resolved.
+#[derive(clap::Args, Clone, Default)]
+pub struct Options {
+    /// Neither use nor update cached network results
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_NO_CACHE",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub no_cache: bool,
+
+    /// Trust cached network results for this many seconds [default: 86400]
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        env = "GIT_DEFAULT_BRANCH_CACHE_TTL",
+        global = true
+    )]
+    pub cache_ttl: Option<u64>,
+
+    /// Do not ask a running `serve --unix-socket` daemon
+    #[cfg(feature = "network")]
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_NO_DAEMON",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub no_daemon: bool,
+
+    /// Only read local ref files: no subprocesses, network or configuration
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_FAST",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub fast: bool,
+
+    /// Print how long each resolution step took to stderr
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_TIMINGS",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub timings: bool,
+
+    /// Print each resolution attempt to stderr as a JSON line, with its timing and outcome
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_TRACE_JSON",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub trace_json: bool,
+
+    /// Read the repository with libgit2 instead of gitoxide; it only reads, so neither
+    /// --set-head nor --interactive is offered with it
+    #[cfg(feature = "libgit2")]
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_LIBGIT2", value_parser = BoolishValueParser::new(),
+        global = true, conflicts_with_all = ["fast", "interactive", "set_head"])]
+    pub libgit2: bool,
+
+    /// Rhai script the `script` strategy runs to pick the default branch
+    #[cfg(feature = "scripting")]
+    #[arg(
+        long,
+        value_name = "FILE",
+        env = "GIT_DEFAULT_BRANCH_SCRIPT",
+        global = true
+    )]
+    pub script: Option<std::path::PathBuf>,
+
+    /// Local branch names to fall back to, in order [default: main,master]
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        env = "GIT_DEFAULT_BRANCH_CANDIDATES",
+        global = true
+    )]
+    pub candidates: Vec<String>,
+
+    /// Globs naming release or maintenance branches that `pr-base`, and `fork-point` and
+    /// `shell-init` with --nearest, target instead of the default branch when the work is built
+    /// on one [default: release/*]
+    #[arg(
+        long = "release-pattern",
+        value_name = "GLOB",
+        value_delimiter = ',',
+        env = "GIT_DEFAULT_BRANCH_RELEASE_PATTERNS",
+        global = true
+    )]
+    pub release_patterns: Vec<String>,
+
+    /// Never contact the remote
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_OFFLINE",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub offline: bool,
+
+    /// Print this branch instead of failing when the default branch cannot be determined
+    #[arg(
+        long,
+        value_name = "BRANCH",
+        env = "GIT_DEFAULT_BRANCH_FALLBACK",
+        global = true
+    )]
+    pub fallback: Option<String>,
+
+    /// Only accept answers from the remote, never a guess from local branch names; exit with
+    /// status 6 when there is none
+    #[arg(
+        long,
+        env = "GIT_DEFAULT_BRANCH_STRICT",
+        value_parser = BoolishValueParser::new(),
+        global = true,
+        conflicts_with = "fallback"
+    )]
+    pub strict: bool,
+
+    /// Fail rather than print a name that is not a valid branch name by the rules of
+    /// `git check-ref-format --branch`, so scripts never act on a malformed ref
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_VALIDATE",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub validate: bool,
+
+    /// When only local branch names can answer and several exist, ask which one is the default
+    /// branch, if stdin and stderr are terminals
+    #[arg(short, long, env = "GIT_DEFAULT_BRANCH_INTERACTIVE",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub interactive: bool,
+
+    /// Resolve the remote the current branch pushes to (`branch.<name>.pushRemote`, then
+    /// `remote.pushDefault`) rather than the one it fetches from, for triangular workflows
+    #[arg(long, env = "GIT_DEFAULT_BRANCH_PUSH",
+        value_parser = BoolishValueParser::new(), global = true, conflicts_with = "fast")]
+    pub push: bool,
+
+    /// When the remote reports a HEAD the repository does not have, record it as
+    /// `refs/remotes/<remote>/HEAD`, after asking on a terminal; lookups change nothing otherwise
+    #[arg(long, visible_alias = "fix", env = "GIT_DEFAULT_BRANCH_SET_HEAD",
+        value_parser = BoolishValueParser::new(), global = true)]
+    pub set_head: bool,
+
+    /// Do not ask before changing the repository
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+
+    /// Report what would change instead of changing it: the remote HEAD or remembered candidate
+    /// a lookup records, and what migrate, retarget, hook and init would do
+    #[arg(short = 'n', long, global = true)]
+    pub dry_run: bool,
+
+    /// Remotes to try in order instead of the one passed in, from the configuration file.
+    #[arg(skip)]
+    pub remotes: Vec<String>,
+
+    /// Named remote priority lists that `@name` refers to.
+    #[arg(skip)]
+    pub remote_groups: Vec<(String, Vec<String>)>,
+
+    /// The strategies to try, in order.
+    #[arg(skip)]
+    pub strategies: Vec<Strategy>,
+
+    /// Where settings that did not keep their default came from, by argument name.
+    #[arg(skip)]
+    pub sources: Vec<(&'static str, Source)>,
+
+    /// Per-host settings from the configuration file.
+    #[arg(skip)]
+    pub hosts: Vec<HostProfile>,
+
+    /// Proxy for asking the remote, from the profile of the remote's host.
+    #[arg(skip)]
+    pub proxy: Option<String>,
+}
+
+impl Options {
+    pub fn source(&self, name: &str) -> Source {
+        self.sources
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map_or(Source::Default, |(_, source)| *source)
+    }
+
+    /// Records that `name` was set from `source`, unless a higher-precedence source already set
+    /// it. Returns whether the new value should be applied.
+    pub fn set_source(&mut self, name: &'static str, source: Source) -> bool {
+        if self.source(name) < source {
+            return false;
+        }
+        self.sources.retain(|(n, _)| *n != name);
+        self.sources.push((name, source));
+        true
+    }
+
+    /// The options for a request that names its remote, which overrides the configured remote
+    /// priority.
+    pub fn with_explicit_remote(&self) -> Options {
+        Options {
+            sources: vec![("remote", Source::CommandLine)],
+            remotes: Vec::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Returns how long cached results are trusted, or `None` if the cache is bypassed.
+    pub fn cache_ttl(&self) -> Option<Duration> {
+        if self.no_cache {
+            return None;
+        }
+        Some(
+            self.cache_ttl
+                .map_or(cache::DEFAULT_TTL, Duration::from_secs),
+        )
+    }
+
+    /// Turns a failed resolution into the `--fallback` branch, if one was given, and refuses an
+    /// invalid name with `--validate`.
+    pub fn or_fallback(
+        &self,
+        result: Result<Resolved, Box<dyn std::error::Error>>,
+    ) -> Result<Resolved, Box<dyn std::error::Error>> {
+        let resolved = match (result, &self.fallback) {
+            (Err(_), Some(fallback)) => Ok(Resolved::new(fallback.clone(), "fallback")),
+            (result, _) => result,
+        }?;
+        if self.validate {
+            validate_branch(&resolved.branch)?;
+        }
+        Ok(resolved)
+    }
+
+    /// Replaces each `@group` in `remotes` with the remotes of that group.
+    pub fn expand_groups(&self, remotes: &[String]) -> Result<Vec<String>, String> {
+        let mut expanded = Vec::new();
+        for remote in remotes {
+            match remote.strip_prefix('@') {
+                Some(name) => expanded.extend(
+                    self.remote_groups
+                        .iter()
+                        .find(|(group, _)| group == name)
+                        .map(|(_, members)| members.iter().cloned())
+                        .ok_or_else(|| format!("Unknown remote group @{}", name))?,
+                ),
+                None => expanded.push(remote.clone()),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Returns the remotes to try, in priority order.
+    pub fn remotes<'a>(&'a self, remote: &'a str) -> Vec<&'a str> {
+        if self.remotes.is_empty() {
+            return vec![remote];
+        }
+        self.remotes.iter().map(String::as_str).collect()
+    }
+
+    pub fn candidates(&self) -> Vec<&str> {
+        if self.candidates.is_empty() {
+            return LOCAL_NAMES.to_vec();
+        }
+        self.candidates.iter().map(String::as_str).collect()
+    }
+
+    pub fn release_patterns(&self) -> Vec<&str> {
+        if self.release_patterns.is_empty() {
+            return RELEASE_PATTERNS.to_vec();
+        }
+        self.release_patterns.iter().map(String::as_str).collect()
+    }
+
+    pub fn strategies(&self) -> Vec<Strategy> {
+        let strategies = if self.strategies.is_empty() {
+            STRATEGIES
+        } else {
+            &self.strategies
+        };
+        strategies
+            .iter()
+            .copied()
+            .filter(|strategy| {
+                !((self.offline || !policy::network_allowed()) && strategy.uses_network())
+            })
+            .filter(|strategy| !self.strict || strategy.is_authoritative())
+            .collect()
+    }
+
+    /// Returns the profile of the host the first existing remote is on.
+    pub fn host_profile(&self, repo: &gix::Repository, remote: &str) -> Option<&HostProfile> {
+        HostProfile::find(&self.hosts, &remote_host(repo, &self.remotes(remote))?)
+    }
+
+    /// Applies the profile of the host the repository's remote is on, then the repository's
+    /// `defaultBranch.*` git config keys, which take precedence over the configuration file but
+    /// not over the command line. Only the repository's own config files are read, so the
+    /// settings stay per repository.
+    pub fn for_repo(&self, repo: &gix::Repository, remote: &str) -> Options {
+        let snapshot = repo.config_snapshot();
+        let config = snapshot.plumbing();
+        let local = |meta: &gix::config::file::Metadata| {
+            matches!(
+                meta.source,
+                gix::config::Source::Local | gix::config::Source::Worktree
+            )
+        };
+        let list = |key: &str| {
+            config.strings_filter(key, local).map(|values| {
+                config_list(values.iter().map(|value| value.to_str_lossy().into_owned()))
+            })
+        };
+        self.with_repo_settings(RepoSettings {
+            host: remote_host(repo, &self.remotes(remote)),
+            remotes: list("defaultBranch.remote"),
+            candidates: list("defaultBranch.candidates"),
+            release_patterns: list("defaultBranch.releasePatterns"),
+            allow_network: config
+                .boolean_filter("defaultBranch.allowNetwork", local)
+                .and_then(Result::ok),
+            push_remote: self.follows_push().then(|| push_remote(repo)).flatten(),
+        })
+    }
+
+    /// Applies what [`Options::for_repo`] read from a repository, however it was opened.
+    pub fn with_repo_settings(&self, settings: RepoSettings) -> Options {
+        let mut options = self.clone();
+        if let Some(profile) = settings
+            .host
+            .and_then(|host| HostProfile::find(&self.hosts, &host))
+        {
+            if !profile.strategies.is_empty()
+                && options.set_source("strategies", Source::ConfigFile)
+            {
+                options.strategies = profile.strategies.clone();
+            }
+            if profile.proxy.is_some() && options.set_source("proxy", Source::ConfigFile) {
+                options.proxy = profile.proxy.clone();
+            }
+        }
+        if let Some(remotes) = settings
+            .remotes
+            .and_then(|remotes| self.expand_groups(&remotes).ok())
+            && options.set_source("remote", Source::GitConfig)
+        {
+            options.remotes = remotes;
+        }
+        if let Some(candidates) = settings.candidates
+            && options.set_source("candidates", Source::GitConfig)
+        {
+            options.candidates = candidates;
+        }
+        if let Some(patterns) = settings.release_patterns
+            && options.set_source("release_patterns", Source::GitConfig)
+        {
+            options.release_patterns = patterns;
+        }
+        if let Some(allow) = settings.allow_network
+            && options.set_source("offline", Source::GitConfig)
+        {
+            options.offline = !allow;
+        }
+        if let Some(remote) = settings.push_remote {
+            log::debug!("resolving the push remote {}", remote);
+            options.remotes = vec![remote];
+        }
+        options
+    }
+
+    /// Whether a host profile may replace the strategies, which takes the remote's URL to tell.
+    fn host_strategies_apply(&self) -> bool {
+        self.source("strategies") >= Source::ConfigFile
+            && self
+                .hosts
+                .iter()
+                .any(|profile| !profile.strategies.is_empty())
+    }
+
+    /// Whether `--push` picks the remote, which a remote given on the command line overrides.
+    pub fn follows_push(&self) -> bool {
+        self.push && self.source("remote") != Source::CommandLine
+    }
+
+    /// Whether every setting that shapes the answer keeps its default, so the daemon, which
+    /// resolves with its own settings, answers as this process would.
+    #[cfg(feature = "network")]
+    fn daemon_compatible(&self) -> bool {
+        !self.strict
+            && !self.offline
+            && !self.interactive
+            && !self.set_head
+            && self.fallback.is_none()
+            && self.candidates.is_empty()
+            && !self.follows_push()
+            && self.source("script") > Source::Environment
+    }
+}
+
+/// Asks `remote` which branch its HEAD points to, failing with git's redacted error output when
+/// the remote cannot be reached.
+#[cfg(feature = "subprocess")]
+fn query_remote_head(
+    repo: &gix::Repository,
+    remote: &str,
+    options: &Options,
+) -> Result<Option<String>, String> {
+    ls_remote_head(git::run_dir(repo), remote, options)
+}
+
+/// Runs `git ls-remote` in `dir` to ask `remote`, a remote name or URL, where its HEAD points.
+#[cfg(feature = "subprocess")]
+pub fn ls_remote_head(
+    dir: &Path,
+    remote: &str,
+    options: &Options,
+) -> Result<Option<String>, String> {
+    policy::check_network("Asking the remote")?;
+    // https://qiita.com/ymm1x/items/b22bddc9fbc192ae1a70
+    // https://stackoverflow.com/questions/28666357/how-to-get-default-git-branch/44750379#44750379
+    let mut command = std::process::Command::new("git");
+    command.args(tls::git_args());
+    if let Some(proxy) = &options.proxy {
+        command.arg("-c").arg(format!("http.proxy={}", proxy));
+    }
+    ssh::configure(&mut command, dir);
+    log::debug!("running git ls-remote --symref -- {} HEAD", remote);
+    let output = command
+        .args(["ls-remote", "--symref", "--", remote, "HEAD"])
+        .current_dir(dir)
+        .output()
+        .map_err(|e| e.to_string())?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(redact::redact(stderr.trim()).into_owned());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .find_map(|line| {
+            let target = line.strip_prefix("ref: ")?.strip_suffix("\tHEAD")?;
+            target.strip_prefix("refs/heads/").map(str::to_string)
+        }))
+}
+
+/// Asks the repository at `url` for its default branch, for repositories with no clone at hand.
+#[cfg(feature = "subprocess")]
+pub fn run_url(url: &str, options: &Options) -> Result<Resolved, Box<dyn std::error::Error>> {
+    options.or_fallback(ask_url(url, options))
+}
+
+#[cfg(feature = "subprocess")]
+fn ask_url(url: &str, options: &Options) -> Result<Resolved, Box<dyn std::error::Error>> {
+    if options.offline {
+        return Err(Failure::Undetermined(format!("--offline forbids asking {}", url)).into());
+    }
+    policy::check_url(url)?;
+    match ls_remote_head(Path::new("."), url, options) {
+        Ok(Some(branch)) => Ok(Resolved::new(branch, Strategy::SetHead.name())),
+        Ok(None) => Err(Failure::Undetermined(i18n::lookup("undetermined").to_string()).into()),
+        Err(error) => Err(Failure::Network(error).into()),
+    }
+}
+
+/// Points `refs/remotes/<remote>/HEAD` at the remote-tracking ref of `branch`, with a reflog
+/// entry saying why, created even where reflogs are off, so the change can be audited and undone.
+pub fn write_remote_head(
+    repo: &gix::Repository,
+    remote: &str,
+    branch: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let head = format!("refs/remotes/{}/HEAD", remote);
+    let target = tracking_ref(repo, remote, branch);
+    let message = format!(
+        "git-default-branch: {} is the default branch of {}",
+        branch, remote
+    );
+    let old = repo
+        .find_reference(head.as_str())
+        .ok()
+        .and_then(|reference| {
+            reference
+                .target()
+                .try_name()
+                .map(|name| name.as_bstr().to_string())
+        });
+    let change = audit::Change {
+        action: "set-remote-head",
+        name: &head,
+        old: old.as_deref(),
+        new: Some(&target),
+    };
+    audit::record(repo, &change, || {
+        git::git(
+            git::run_dir(repo),
+            &[
+                "-c",
+                "core.logAllRefUpdates=always",
+                "symbolic-ref",
+                "-m",
+                &message,
+                &head,
+                &target,
+            ],
+        )?;
+        Ok(())
+    })
+}
+
+/// The remote `git push` sends the current branch to, from `branch.<name>.pushRemote`,
+/// `remote.pushDefault` or `branch.<name>.remote`, when it is configured by name.
+fn push_remote(repo: &gix::Repository) -> Option<String> {
+    let direction = gix::remote::Direction::Push;
+    let name: gix::remote::Name = match repo.head_name().ok().flatten() {
+        Some(head) => repo.branch_remote_name(head.shorten(), direction)?,
+        None => repo
+            .config_snapshot()
+            .string("remote.pushDefault")?
+            .into_owned()
+            .into(),
+    };
+    name.as_symbol().map(str::to_string)
+}
+
+/// What a repository says about how to resolve its default branch: the host of its remote and
+/// its own `defaultBranch.*` git config keys.
+pub struct RepoSettings {
+    /// The host of the first of the remotes to try that exists.
+    pub host: Option<String>,
+    pub remotes: Option<Vec<String>>,
+    pub candidates: Option<Vec<String>>,
+    pub release_patterns: Option<Vec<String>>,
+    pub allow_network: Option<bool>,
+    /// The remote to resolve instead, when `--push` picks it.
+    pub push_remote: Option<String>,
+}
+
+/// Splits git config values listing names, separated by commas or spaces, into the names.
+pub fn config_list(values: impl IntoIterator<Item = String>) -> Vec<String> {
+    values
+        .into_iter()
+        .flat_map(|value| {
+            value
+                .split([',', ' '])
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+fn remote_host(repo: &gix::Repository, remotes: &[&str]) -> Option<String> {
+    remotes
+        .iter()
+        .find_map(|remote| repo.find_remote(*remote).ok())
+        .and_then(|remote| {
+            remote
+                .url(gix::remote::Direction::Fetch)?
+                .host()
+                .map(str::to_string)
+        })
+}
+
+/// Why nothing should be written to the repository at `common_dir`, if so: `GIT_OPTIONAL_LOCKS=0`
+/// asks for it, as for `git status`, and a read-only mount would refuse it.
+#[cfg(feature = "subprocess")]
+fn read_only(common_dir: &Path, optional_locks: Option<&OsStr>) -> Option<&'static str> {
+    if optional_locks.is_some_and(|value| value == "0") {
+        return Some("GIT_OPTIONAL_LOCKS=0");
+    }
+    let probe = common_dir.join(format!("git-default-branch-{}.probe", process::id()));
+    match fs::File::create_new(&probe) {
+        Ok(_) => {
+            let _ = fs::remove_file(&probe);
+            None
+        }
+        Err(e) if e.kind() == io::ErrorKind::ReadOnlyFilesystem => Some("read-only filesystem"),
+        Err(_) => None,
+    }
+}
+
+/// Describes a write left out as `{"event": "skip", "strategy", "reason"}`, for the trace.
+#[cfg(feature = "subprocess")]
+fn skip_json(strategy: &str, reason: &str) -> String {
+    format!(
+        "{{\"event\":\"skip\",\"strategy\":{},\"reason\":{}}}",
+        json::quote(strategy),
+        json::quote(reason)
+    )
+}
+
+/// Records how long each resolution step took and prints the breakdown to stderr when dropped.
+struct Timings {
+    enabled: bool,
+    trace: bool,
+    start: Instant,
+    steps: Vec<(&'static str, Duration)>,
+}
+
+impl Timings {
+    fn new(options: &Options) -> Self {
+        Timings {
+            enabled: options.timings,
+            trace: options.trace_json,
+            start: Instant::now(),
+            steps: Vec::new(),
+        }
+    }
+
+    fn time<T>(&mut self, step: &'static str, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        if self.enabled {
+            self.steps.push((step, start.elapsed()));
+        }
+        result
+    }
+
+    /// Like [`Timings::time`] for a step that may find the branch, also tracing its outcome.
+    fn attempt(
+        &mut self,
+        step: &'static str,
+        strategy: &str,
+        f: impl FnOnce() -> Result<Option<String>, Box<dyn std::error::Error>>,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let start = Instant::now();
+        let result = self.time(step, f);
+        if self.trace {
+            eprintln!("{}", attempt_json(strategy, step, start.elapsed(), &result));
+        }
+        result
+    }
+}
+
+/// Describes one attempt as `{"event", "strategy", "step", "elapsed_ms", "outcome", ...}`, with
+/// `branch` when one was found and `error` when the attempt failed.
+fn attempt_json(
+    strategy: &str,
+    step: &str,
+    elapsed: Duration,
+    result: &Result<Option<String>, Box<dyn std::error::Error>>,
+) -> String {
+    let (outcome, detail) = match result {
+        Ok(Some(branch)) => ("found", format!(",\"branch\":{}", json::quote(branch))),
+        Ok(None) => ("not_found", String::new()),
+        Err(e) => (
+            "error",
+            format!(
+                ",\"error\":{}",
+                json::quote(&redact::redact(&e.to_string()))
+            ),
+        ),
+    };
+    format!(
+        "{{\"event\":\"attempt\",\"strategy\":{},\"step\":{},\"elapsed_ms\":{:.3},\"outcome\":\"{}\"{}}}",
+        json::quote(strategy),
+        json::quote(step),
+        elapsed.as_secs_f64() * 1000.0,
+        outcome,
+        detail
+    )
+}
+
+impl Drop for Timings {
+    fn drop(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        for (step, elapsed) in &self.steps {
+            eprintln!("timings: {:<24} {:>10.3?}", step, elapsed);
+        }
+        eprintln!("timings: {:<24} {:>10.3?}", "total", self.start.elapsed());
+    }
+}
+
+pub fn remote_head_branch(
+    repo: &gix::Repository,
+    remote: &str,
+) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    RemoteRefs::new(repo, remote).head_branch(repo)
+}
+
+/// Lists the branches fetched from `remote`, by their names there.
+pub fn remote_branches(
+    repo: &gix::Repository,
+    remote: &str,
+) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    RemoteRefs::new(repo, remote).branches(repo)
+}
+
+/// Returns the remote-tracking ref that `branch` of `remote` is fetched into.
+pub fn tracking_ref(repo: &gix::Repository, remote: &str, branch: &str) -> String {
+    RemoteRefs::new(repo, remote).tracking_ref(branch)
+}
+
+/// How many symbolic refs git follows before giving up, as `SYMREF_MAXDEPTH` does.
+pub const MAX_SYMREF_DEPTH: usize = 5;
+
+/// The names of a remote's refs, worked out once per resolution.
+pub struct RemoteRefs<'a> {
+    pub remote: &'a str,
+    /// Where the remote HEAD may be: `refs/remotes/<remote>/HEAD`, where git keeps it, then
+    /// beside any other destination the fetch refspecs map branches to.
+    pub heads: Vec<String>,
+    /// The fetch refspecs mapping branches, followed by the default one.
+    mappings: Vec<Mapping>,
+    /// Why asking the remote failed, if it did.
+    pub network_error: RefCell<Option<String>>,
+}
+
+/// A fetch refspec from `refs/heads/<source>` to `<destination>`, both ending in `*` for a
+/// pattern.
+#[derive(Debug, PartialEq)]
+struct Mapping {
+    source: String,
+    destination: String,
+}
+
+impl Mapping {
+    fn from_refspec(spec: gix::refspec::RefSpecRef<'_>) -> Option<Self> {
+        Mapping::new(
+            spec.source()?.to_str().ok()?,
+            spec.destination()?.to_str().ok()?,
+        )
+    }
+
+    fn new(source: &str, destination: &str) -> Option<Self> {
+        let source = source.strip_prefix("refs/heads/")?;
+        (source.ends_with('*') == destination.ends_with('*')).then(|| Mapping {
+            source: source.to_string(),
+            destination: destination.to_string(),
+        })
+    }
+
+    /// The branch fetched into the remote-tracking ref `name`.
+    fn branch(&self, name: &str) -> Option<String> {
+        match self.destination.strip_suffix('*') {
+            Some(prefix) => {
+                let rest = name.strip_prefix(prefix)?;
+                Some(format!("{}{}", self.source.strip_suffix('*')?, rest))
+            }
+            None => (name == self.destination).then(|| self.source.clone()),
+        }
+    }
+
+    /// The remote-tracking ref `branch` is fetched into.
+    fn tracking_ref(&self, branch: &str) -> Option<String> {
+        match self.source.strip_suffix('*') {
+            Some(prefix) => {
+                let rest = branch.strip_prefix(prefix)?;
+                Some(format!("{}{}", self.destination.strip_suffix('*')?, rest))
+            }
+            None => (branch == self.source).then(|| self.destination.clone()),
+        }
+    }
+}
+
+impl<'a> RemoteRefs<'a> {
+    fn new(repo: &gix::Repository, remote: &'a str) -> Self {
+        let mappings = repo
+            .find_remote(remote)
+            .map(|found| {
+                found
+                    .refspecs(gix::remote::Direction::Fetch)
+                    .iter()
+                    .filter_map(|spec| Mapping::from_refspec(spec.to_ref()))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        RemoteRefs::with_mappings(remote, mappings)
+    }
+
+    /// Works out the names of `remote`'s refs from its fetch refspecs as `(source, destination)`
+    /// pairs, for a repository opened some other way.
+    #[cfg_attr(not(feature = "libgit2"), allow(dead_code))]
+    pub fn from_refspecs<'s>(
+        remote: &'a str,
+        refspecs: impl IntoIterator<Item = (&'s str, &'s str)>,
+    ) -> Self {
+        let mappings = refspecs
+            .into_iter()
+            .filter_map(|(source, destination)| Mapping::new(source, destination))
+            .collect();
+        RemoteRefs::with_mappings(remote, mappings)
+    }
+
+    fn with_mappings(remote: &'a str, mut mappings: Vec<Mapping>) -> Self {
+        let default = Mapping {
+            source: "*".to_string(),
+            destination: format!("refs/remotes/{}/*", remote),
+        };
+        if !mappings.contains(&default) {
+            mappings.push(default);
+        }
+        let mut heads = vec![format!("refs/remotes/{}/HEAD", remote)];
+        for mapping in &mappings {
+            if let Some(prefix) = mapping.destination.strip_suffix('*') {
+                let head = format!("{}HEAD", prefix);
+                if !heads.contains(&head) {
+                    heads.push(head);
+                }
+            }
+        }
+        RemoteRefs {
+            remote,
+            heads,
+            mappings,
+            network_error: RefCell::new(None),
+        }
+    }
+
+    fn head_branch(
+        &self,
+        repo: &gix::Repository,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let Some(r) = self
+            .heads
+            .iter()
+            .find_map(|head| repo.find_reference(head.as_str()).ok())
+        else {
+            log::debug!("{} does not exist", self.heads.join(", "));
+            return Ok(None);
+        };
+        let head = r.name().as_bstr().to_string();
+        let target = r.target();
+        let Some(name) = target.try_name() else {
+            let id = target.try_id().ok_or("HEAD is not symbolic")?.to_owned();
+            log::debug!("{} is detached at {}; looking for a branch there", head, id);
+            return self.branch_at(repo, &id);
+        };
+        log::debug!("{} points to {}", head, name.as_bstr());
+
+        // The target may itself be symbolic; follow the chain as far as git would.
+        let mut name = name.to_owned();
+        for _ in 0..MAX_SYMREF_DEPTH {
+            let next = match repo.find_reference(name.as_ref()) {
+                Ok(r) => r.target().try_name().map(ToOwned::to_owned),
+                Err(_) => None,
+            };
+            let Some(next) = next else {
+                return Ok(Some(self.branch_name(name.as_bstr().to_str()?)?));
+            };
+            log::debug!("{} points to {}", name.as_bstr(), next.as_bstr());
+            name = next;
+        }
+        log::warn!(
+            "{} leads through more than {} symbolic refs, which is probably a loop",
+            head,
+            MAX_SYMREF_DEPTH
+        );
+        Ok(None)
+    }
+
+    /// Finds a branch of the remote whose tip is `id`, for a remote HEAD that was written
+    /// detached. The refs are streamed so the search stops at the first match.
+    fn branch_at(
+        &self,
+        repo: &gix::Repository,
+        id: &gix::oid,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let platform = repo.references()?;
+        for prefix in self.prefixes() {
+            for r in platform.prefixed(prefix)? {
+                let Ok(r) = r else { continue };
+                let name = r.name().as_bstr().to_str()?;
+                if !self.heads.iter().any(|head| head == name) && r.target().try_id() == Some(id) {
+                    return Ok(Some(self.branch_name(name)?));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Lists the branches fetched from the remote, by their names there.
+    fn branches(&self, repo: &gix::Repository) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let platform = repo.references()?;
+        let mut branches = Vec::new();
+        for prefix in self.prefixes() {
+            for r in platform.prefixed(prefix)? {
+                let Ok(r) = r else { continue };
+                let name = r.name().as_bstr().to_str()?;
+                if self.heads.iter().any(|head| head == name) {
+                    continue;
+                }
+                let branch = self.branch_name(name)?;
+                if !branches.contains(&branch) {
+                    branches.push(branch);
+                }
+            }
+        }
+        Ok(branches)
+    }
+
+    /// The prefixes of the remote-tracking refs branches are fetched into, or the whole names
+    /// for refspecs that are not patterns.
+    pub fn prefixes(&self) -> impl Iterator<Item = &str> {
+        self.mappings.iter().map(|mapping| {
+            mapping
+                .destination
+                .strip_suffix('*')
+                .unwrap_or(&mapping.destination)
+        })
+    }
+
+    pub fn branch_name(&self, name: &str) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(self
+            .mappings
+            .iter()
+            .find_map(|mapping| mapping.branch(name))
+            .ok_or("Invalid ref format")?)
+    }
+
+    pub fn tracking_ref(&self, branch: &str) -> String {
+        self.mappings
+            .iter()
+            .find_map(|mapping| mapping.tracking_ref(branch))
+            .unwrap_or_else(|| format!("refs/remotes/{}/{}", self.remote, branch))
+    }
+}
+
+/// A way of finding the default branch. `STRATEGIES` lists them from most to least trusted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Strategy {
+    /// The remote HEAD symref as it is on disk.
+    RemoteHead,
+    /// A branch the remote reported recently.
+    Cache,
+    /// Ask the remote with `git ls-remote --symref` and record its answer as the remote HEAD.
+    #[cfg(feature = "subprocess")]
+    SetHead,
+    /// Ask each `git-default-branch-resolver-*` executable on `PATH`.
+    #[cfg(feature = "subprocess")]
+    Plugins,
+    /// Run the `--script` Rhai script, when one is given.
+    #[cfg(feature = "scripting")]
+    Script,
+    /// The first of the common default branch names that exists locally.
+    LocalNames,
+    /// A guess among the remote-tracking branches: the first candidate or common default branch
+    /// name the remote has, or its only branch.
+    RemoteBranches,
+}
+
+const STRATEGIES: &[Strategy] = &[
+    Strategy::RemoteHead,
+    Strategy::Cache,
+    #[cfg(feature = "subprocess")]
+    Strategy::SetHead,
+    #[cfg(feature = "subprocess")]
+    Strategy::Plugins,
+    #[cfg(feature = "scripting")]
+    Strategy::Script,
+    Strategy::LocalNames,
+    Strategy::RemoteBranches,
+];
+
+const LOCAL_NAMES: [&str; 2] = ["main", "master"];
+
+/// Release branches when no `--release-pattern` is configured.
+const RELEASE_PATTERNS: [&str; 1] = ["release/*"];
+
+/// Names a remote's default branch commonly has, most likely first, for [`Strategy::RemoteBranches`].
+pub const REMOTE_NAMES: [&str; 4] = ["main", "master", "trunk", "develop"];
+
+impl Strategy {
+    /// Parses the name used in configuration files.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "remote-head" => Some(Strategy::RemoteHead),
+            "cache" => Some(Strategy::Cache),
+            #[cfg(feature = "subprocess")]
+            "set-head" => Some(Strategy::SetHead),
+            #[cfg(feature = "subprocess")]
+            "plugins" => Some(Strategy::Plugins),
+            #[cfg(feature = "scripting")]
+            "script" => Some(Strategy::Script),
+            "remote-branch-guess" => Some(Strategy::RemoteBranches),
+            "local-names" => Some(Strategy::LocalNames),
+            _ => None,
+        }
+    }
+
+    /// Every strategy this binary was built with, in the default order.
+    pub fn all() -> &'static [Strategy] {
+        STRATEGIES
+    }
+
+    /// The name used in configuration files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Strategy::RemoteHead => "remote-head",
+            Strategy::Cache => "cache",
+            #[cfg(feature = "subprocess")]
+            Strategy::SetHead => "set-head",
+            #[cfg(feature = "subprocess")]
+            Strategy::Plugins => "plugins",
+            #[cfg(feature = "scripting")]
+            Strategy::Script => "script",
+            Strategy::RemoteBranches => "remote-branch-guess",
+            Strategy::LocalNames => "local-names",
+        }
+    }
+
+    /// Whether the answer comes from the remote rather than a guess.
+    fn is_authoritative(self) -> bool {
+        !matches!(self, Strategy::RemoteBranches | Strategy::LocalNames)
+    }
+
+    fn uses_network(self) -> bool {
+        match self {
+            #[cfg(feature = "subprocess")]
+            Strategy::SetHead | Strategy::Plugins => true,
+            _ => false,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Strategy::RemoteHead => "ref lookup",
+            Strategy::Cache => "cache",
+            #[cfg(feature = "subprocess")]
+            Strategy::SetHead => "network (git subprocess)",
+            #[cfg(feature = "subprocess")]
+            Strategy::Plugins => "resolver plugins",
+            #[cfg(feature = "scripting")]
+            Strategy::Script => "script",
+            Strategy::RemoteBranches => "guess (remote branches)",
+            Strategy::LocalNames => "fallback",
+        }
+    }
+
+    /// Tries the strategy for each of `remotes` in turn.
+    fn resolve(
+        self,
+        repo: &gix::Repository,
+        remotes: &[RemoteRefs],
+        options: &Options,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        if self == Strategy::LocalNames {
+            let branches = local_branches(repo)?;
+            let mut existing = options
+                .candidates()
+                .into_iter()
+                .filter_map(|name| matching_branch(&branches, name));
+            // A fresh clone that has not checked out anything may only have remote-tracking
+            // branches, which then count instead.
+            let tracked = |name: &&str| {
+                remotes.iter().any(|refs| {
+                    repo.find_reference(refs.tracking_ref(name).as_str())
+                        .is_ok()
+                })
+            };
+            if !(options.interactive && interactive::available()) {
+                return Ok(existing.next().or_else(|| {
+                    options
+                        .candidates()
+                        .into_iter()
+                        .find(tracked)
+                        .map(str::to_string)
+                }));
+            }
+            let mut found: Vec<String> = Vec::new();
+            for branch in existing {
+                if !found.contains(&branch) {
+                    found.push(branch);
+                }
+            }
+            if found.is_empty() {
+                found = options
+                    .candidates()
+                    .into_iter()
+                    .filter(tracked)
+                    .map(str::to_string)
+                    .collect();
+                found.dedup();
+            }
+            return match found
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .as_slice()
+            {
+                [] => Ok(None),
+                [only] => Ok(Some(only.to_string())),
+                several => interactive::choose(repo, several, options.dry_run).map(Some),
+            };
+        }
+        for refs in remotes {
+            if let Some(branch) = self.resolve_remote(repo, refs, options)? {
+                return Ok(Some(branch));
+            }
+        }
+        Ok(None)
+    }
+
+    fn resolve_remote(
+        self,
+        repo: &gix::Repository,
+        refs: &RemoteRefs,
+        options: &Options,
+    ) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let remote = refs.remote;
+        #[cfg(feature = "subprocess")]
+        if self.uses_network() && !policy::allows_remote(repo, remote) {
+            return Ok(None);
+        }
+        match self {
+            Strategy::RemoteHead => refs.head_branch(repo),
+            Strategy::Cache => Ok(options
+                .cache_ttl()
+                .and_then(|ttl| cache::lookup(repo, remote, ttl))),
+            #[cfg(feature = "subprocess")]
+            Strategy::SetHead => {
+                let branch = match query_remote_head(repo, remote, options) {
+                    Ok(Some(branch)) => branch,
+                    Ok(None) => return Ok(None),
+                    Err(error) => {
+                        if repo.find_remote(remote).is_ok() {
+                            *refs.network_error.borrow_mut() = Some(error);
+                        }
+                        return Ok(None);
+                    }
+                };
+                // Record the answer as `git remote set-head --auto` would, when the branch has
+                // been fetched for the remote HEAD to point at.
+                let tracking = refs.tracking_ref(&branch);
+                if refs.head_branch(repo)?.as_deref() != Some(branch.as_str())
+                    && repo.find_reference(tracking.as_str()).is_ok()
+                {
+                    let question = format!("Set refs/remotes/{}/HEAD to {}?", remote, tracking);
+                    if !options.set_head {
+                        log::info!("the remote HEAD is {}; --set-head records it", tracking);
+                    } else if options.dry_run {
+                        eprintln!("Would set refs/remotes/{}/HEAD to {}", remote, tracking);
+                    } else if let Some(reason) = read_only(
+                        repo.common_dir(),
+                        std::env::var_os("GIT_OPTIONAL_LOCKS").as_deref(),
+                    ) {
+                        log::info!("not updating the remote HEAD: {}", reason);
+                        if options.trace_json {
+                            eprintln!("{}", skip_json("set-head", reason));
+                        }
+                    } else if (options.yes
+                        || !interactive::available()
+                        || interactive::confirm(&question))
+                        && let Err(e) = write_remote_head(repo, remote, &branch)
+                    {
+                        log::warn!("could not update the remote HEAD: {}", e);
+                    }
+                }
+                if !options.no_cache {
+                    cache::record(repo, remote, &branch);
+                }
+                Ok(Some(branch))
+            }
+            #[cfg(feature = "subprocess")]
+            Strategy::Plugins => {
+                let branch = plugin::find()
+                    .iter()
+                    .find_map(|path| plugin::resolve(path, repo, remote));
+                if let Some(branch) = &branch
+                    && !options.no_cache
+                {
+                    cache::record(repo, remote, branch);
+                }
+                Ok(branch)
+            }
+            #[cfg(feature = "scripting")]
+            Strategy::Script => match &options.script {
+                Some(path) => Ok(script::run(path, &script_context(repo, refs)?)?),
+                None => Ok(None),
+            },
+            Strategy::RemoteBranches => {
+                let branches = refs.branches(repo)?;
+                Ok(options
+                    .candidates()
+                    .into_iter()
+                    .chain(REMOTE_NAMES)
+                    .find(|name| branches.iter().any(|branch| branch == name))
+                    .map(str::to_string)
+                    .or_else(|| match branches.as_slice() {
+                        [only] => Some(only.clone()),
+                        _ => None,
+                    }))
+            }
+            Strategy::LocalNames => Ok(None),
+        }
+    }
+}
+
+/// Describes `refs`'s remote and `repo` for the `script` strategy.
+#[cfg(feature = "scripting")]
+fn script_context(
+    repo: &gix::Repository,
+    refs: &RemoteRefs,
+) -> Result<script::Context, Box<dyn std::error::Error>> {
+    let mut names = Vec::new();
+    for r in repo.references()?.all()? {
+        let Ok(r) = r else { continue };
+        names.push(r.name().as_bstr().to_str()?.to_string());
+    }
+    let snapshot = repo.config_snapshot();
+    let mut config: Vec<(String, String)> = Vec::new();
+    for section in snapshot.plumbing().sections().filter(|section| {
+        matches!(
+            section.meta().source,
+            gix::config::Source::Local | gix::config::Source::Worktree
+        )
+    }) {
+        let header = section.header();
+        let name = header.name().to_str_lossy().to_lowercase();
+        let prefix = match header.subsection_name() {
+            Some(subsection) => format!("{}.{}", name, subsection),
+            None => name,
+        };
+        for name in section.body().value_names() {
+            let key = format!("{}.{}", prefix, name.to_str_lossy().to_lowercase());
+            let value = section
+                .body()
+                .value(name)
+                .map_or(String::new(), |value| value.to_string());
+            config.retain(|(k, _)| *k != key);
+            config.push((key, value));
+        }
+    }
+    Ok(script::Context {
+        remote: refs.remote.to_string(),
+        url: repo.find_remote(refs.remote).ok().and_then(|remote| {
+            remote
+                .url(gix::remote::Direction::Fetch)
+                .map(|url| url.to_bstring().to_string())
+        }),
+        remote_head: refs.head_branch(repo)?,
+        refs: names,
+        branches: local_branches(repo)?,
+        remote_branches: refs.branches(repo)?,
+        config,
+    })
+}
+
+/// Lists the local branches by their names as stored, which on a case-insensitive filesystem may
+/// differ in case from a name that can nonetheless be looked up.
+pub fn local_branches(repo: &gix::Repository) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let platform = repo.references()?;
+    let mut names = Vec::new();
+    for r in platform.local_branches()? {
+        let Ok(r) = r else { continue };
+        names.push(r.name().shorten().to_str()?.to_string());
+    }
+    Ok(names)
+}
+
+/// Finds the candidate `name` among `branches`, settling for a branch differing only in case
+/// with a warning, since tools other than git may then not find it under the candidate's name.
+pub fn matching_branch(branches: &[String], name: &str) -> Option<String> {
+    log::debug!("looking for refs/heads/{}", name);
+    if branches.iter().any(|branch| branch == name) {
+        return Some(name.to_string());
+    }
+    let variant = branches
+        .iter()
+        .find(|branch| branch.eq_ignore_ascii_case(name))?;
+    log::warn!(
+        "no branch {} exists, only {} which differs in case; using it",
+        name,
+        variant
+    );
+    Some(variant.clone())
+}
+
+/// Why the default branch could not be resolved, so scripts can branch on the exit status.
+#[derive(Debug)]
+pub enum Failure {
+    NotARepository {
+        message: String,
+        /// What is probably wrong, from looking around the path.
+        suggestion: Option<String>,
+    },
+    RemoteNotFound(String),
+    Undetermined(String),
+    Network(String),
+    /// `--strict` was given and only a guess could have answered.
+    NotAuthoritative,
+}
+
+impl Failure {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Failure::NotARepository { .. } => 2,
+            Failure::RemoteNotFound(_) => 3,
+            Failure::Undetermined(_) => 4,
+            Failure::Network(_) => 5,
+            Failure::NotAuthoritative => 6,
+        }
+    }
+
+    /// A stable identifier for machine-readable output.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Failure::NotARepository { .. } => "not_a_repository",
+            Failure::RemoteNotFound(_) => "remote_not_found",
+            Failure::Undetermined(_) => "undetermined",
+            Failure::Network(_) => "network",
+            Failure::NotAuthoritative => "not_authoritative",
+        }
+    }
+
+    /// The resolution step that failed, when a single one is to blame.
+    pub fn strategy(&self) -> Option<&'static str> {
+        match self {
+            Failure::NotARepository { .. } => Some("discovery"),
+            #[cfg(feature = "subprocess")]
+            Failure::Network(_) => Some(Strategy::SetHead.name()),
+            _ => None,
+        }
+    }
+
+    /// A targeted suggestion, when one is known for this particular failure.
+    pub fn suggestion(&self) -> Option<&str> {
+        match self {
+            Failure::NotARepository { suggestion, .. } => suggestion.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// The suggestion, or general advice for this class of failure.
+    pub fn hint(&self) -> &str {
+        if let Some(suggestion) = self.suggestion() {
+            return suggestion;
+        }
+        i18n::lookup(match self {
+            Failure::NotARepository { .. } => "hint-not-a-repository",
+            Failure::RemoteNotFound(_) => "hint-remote-not-found",
+            Failure::Undetermined(_) => "hint-undetermined",
+            Failure::Network(_) => "hint-network",
+            Failure::NotAuthoritative => "hint-not-authoritative",
+        })
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Failure::NotARepository { message, .. } => {
+                f.write_str(&i18n::text("not-a-repository", &[("message", message)]))
+            }
+            Failure::RemoteNotFound(remote) => {
+                f.write_str(&i18n::text("remote-not-found", &[("remote", remote)]))
+            }
+            Failure::Undetermined(e) => f.write_str(e),
+            Failure::Network(e) => f.write_str(&i18n::text("network", &[("error", e)])),
+            Failure::NotAuthoritative => f.write_str(i18n::lookup("not-authoritative")),
+        }
+    }
+}
+
+impl std::error::Error for Failure {}
+
+/// A resolved default branch and the strategy that found it.
+#[derive(Debug, PartialEq)]
+pub struct Resolved {
+    pub branch: String,
+    /// A strategy name, `daemon`, `api` for the gh extension's `GH_REPO`, or `fallback` for the
+    /// `--fallback` value.
+    pub provenance: &'static str,
+}
+
+impl Resolved {
+    pub fn new(branch: String, provenance: &'static str) -> Self {
+        Resolved { branch, provenance }
+    }
+}
+
+/// Refuses a name git would not accept for a remote, or that starts with `-`, before it reaches a
+/// ref path or a git command line where `--upload-pack=...` or `../..` could do harm.
+pub fn validate_remote(remote: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if remote.is_empty() || remote.starts_with('-') || gix::remote::name::validated(remote).is_err()
+    {
+        return Err(i18n::text("invalid-remote", &[("remote", &remote)]).into());
+    }
+    Ok(())
+}
+
+/// Refuses a name `git check-ref-format --branch` would, which no branch can have.
+pub fn validate_branch(branch: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let name = format!("refs/heads/{}", branch);
+    if branch.starts_with('-')
+        || gix::validate::reference::branch_name(name.as_str().into()).is_err()
+    {
+        return Err(i18n::text("invalid-branch", &[("branch", &branch)]).into());
+    }
+    Ok(())
+}
+
+pub fn run(
+    path: impl AsRef<Path>,
+    remote: &str,
+    options: &Options,
+) -> Result<String, Box<dyn std::error::Error>> {
+    run_detailed(path, remote, options).map(|resolved| resolved.branch)
+}
+
+/// Like [`run`], but also reports where the answer came from.
+pub fn run_detailed(
+    path: impl AsRef<Path>,
+    remote: &str,
+    options: &Options,
+) -> Result<Resolved, Box<dyn std::error::Error>> {
+    options.or_fallback(run_timed(path.as_ref(), remote, options))
+}
+
+fn run_timed(
+    path: &Path,
+    remote: &str,
+    options: &Options,
+) -> Result<Resolved, Box<dyn std::error::Error>> {
+    let mut timings = Timings::new(options);
+    let remotes = options.remotes(remote);
+    for remote in &remotes {
+        validate_remote(remote)?;
+    }
+    #[cfg(feature = "libgit2")]
+    if options.libgit2 {
+        return timings.time("resolution (libgit2)", || {
+            libgit2::resolve(path, remote, options)
+        });
+    }
+    // Which remote is pushed to and which host profile applies take the configuration to tell,
+    // and strategies placed before the remote HEAD must get their turn first unless only ref
+    // files are read.
+    if !options.follows_push()
+        && (options.fast
+            || (options.strategies().first() == Some(&Strategy::RemoteHead)
+                && !options.host_strategies_apply()))
+        && let Some(branch) =
+            timings.attempt("ref lookup (files)", Strategy::RemoteHead.name(), || {
+                Ok(remotes
+                    .iter()
+                    .find_map(|remote| fast::remote_head_branch(path, remote)))
+            })?
+    {
+        return Ok(Resolved::new(branch, Strategy::RemoteHead.name()));
+    }
+    if options.fast && options.strict {
+        return Err(Failure::NotAuthoritative.into());
+    }
+    if options.fast {
+        return timings
+            .attempt("fallback (files)", Strategy::LocalNames.name(), || {
+                Ok(fast::local_branch(path, &remotes, &options.candidates()))
+            })?
+            .map(|branch| Resolved::new(branch, Strategy::LocalNames.name()))
+            .ok_or_else(|| {
+                Failure::Undetermined(i18n::lookup("undetermined-local").to_string()).into()
+            });
+    }
+    // Unless the remote was given explicitly, an empty one lets the daemon apply its own remote
+    // priority. A remote group given explicitly cannot be passed on, so the daemon is skipped, as
+    // it is for settings it would not honour and when `GIT_DIR` names a repository the daemon
+    // would not see.
+    #[cfg(feature = "network")]
+    let daemon_remote = match (
+        options.source("remote") <= Source::Environment,
+        options.remotes.is_empty(),
+    ) {
+        (false, _) => Some(""),
+        (true, true) => Some(remote),
+        (true, false) => None,
+    };
+    #[cfg(feature = "network")]
+    if !options.no_daemon
+        && options.daemon_compatible()
+        && std::env::var_os("GIT_DIR").is_none()
+        && let Some(daemon_remote) = daemon_remote
+        && let Some(branch) = timings.attempt("daemon", "daemon", || {
+            Ok(serve::query(
+                &serve::default_socket_path(),
+                path,
+                daemon_remote,
+            ))
+        })?
+    {
+        return Ok(Resolved::new(branch, "daemon"));
+    }
+    let repo = timings.time("discovery", || open(path))?;
+    resolve_timed(&repo, remote, options, &mut timings)
+}
+
+/// How the local branch named after the default branch is set up.
+#[derive(Debug, PartialEq)]
+pub struct Tracking {
+    /// Whether a local branch of that name exists.
+    pub local: bool,
+    /// The branch it pulls from as git shortens it, like `origin/main`, when one is configured.
+    pub upstream: Option<String>,
+}
+
+/// Reports whether `branch` exists as a local branch in the repository at `path` and what its
+/// upstream is, so a local default branch tracking something else can be noticed.
+pub fn tracking(
+    path: impl AsRef<Path>,
+    branch: &str,
+) -> Result<Tracking, Box<dyn std::error::Error>> {
+    let repo = git::discover(path)?;
+    let name: gix::refs::FullName = format!("refs/heads/{}", branch).try_into()?;
+    let local = repo.find_reference(name.as_ref()).is_ok();
+    let upstream = repo
+        .branch_remote_tracking_ref_name(name.as_ref(), gix::remote::Direction::Fetch)
+        .and_then(Result::ok)
+        .map(|upstream| upstream.shorten().to_string());
+    Ok(Tracking { local, upstream })
+}
+
+/// Returns the remote the repository at `path` is resolved against, once `defaultBranch.remote`
+/// and `--push` are applied: the first of a group.
+pub fn primary_remote(path: impl AsRef<Path>, remote: &str, options: &Options) -> String {
+    match git::discover(path) {
+        Ok(repo) => options.for_repo(&repo, remote).remotes(remote)[0].to_string(),
+        Err(_) => options.remotes(remote)[0].to_string(),
+    }
+}
+
+/// Discovers the repository containing `path`, failing with a diagnosis of what is wrong.
+pub fn open(path: impl AsRef<Path>) -> Result<gix::Repository, Box<dyn std::error::Error>> {
+    let path = path.as_ref();
+    log::debug!("discovering the repository from {}", path.display());
+    let repo = git::discover(path).map_err(|e| Failure::NotARepository {
+        message: e.to_string(),
+        suggestion: diagnose::not_a_repository(path),
+    })?;
+    log::debug!("found the repository at {}", repo.git_dir().display());
+    Ok(repo)
+}
+
+pub fn resolve(
+    repo: &gix::Repository,
+    remote: &str,
+    options: &Options,
+) -> Result<String, Box<dyn std::error::Error>> {
+    resolve_detailed(repo, remote, options).map(|resolved| resolved.branch)
+}
+
+/// Like [`resolve`], but also reports where the answer came from.
+pub fn resolve_detailed(
+    repo: &gix::Repository,
+    remote: &str,
+    options: &Options,
+) -> Result<Resolved, Box<dyn std::error::Error>> {
+    options.or_fallback(resolve_timed(
+        repo,
+        remote,
+        options,
+        &mut Timings::new(options),
+    ))
+}
+
+fn resolve_timed(
+    repo: &gix::Repository,
+    remote: &str,
+    options: &Options,
+    timings: &mut Timings,
+) -> Result<Resolved, Box<dyn std::error::Error>> {
+    let options = &options.for_repo(repo, remote);
+    let remotes = options.remotes(remote);
+    for remote in &remotes {
+        validate_remote(remote)?;
+    }
+    let remotes = remotes
+        .into_iter()
+        .map(|remote| RemoteRefs::new(repo, remote))
+        .collect::<Vec<_>>();
+    for strategy in options.strategies() {
+        log::trace!("trying {}", strategy.name());
+        if let Some(branch) = timings.attempt(strategy.label(), strategy.name(), || {
+            strategy.resolve(repo, &remotes, options)
+        })? {
+            log::trace!("{} found {}", strategy.name(), branch);
+            return Ok(Resolved::new(branch, strategy.name()));
+        }
+    }
+    if let Some(refs) = remotes
+        .iter()
+        .find(|refs| repo.find_remote(refs.remote).is_err())
+        .filter(|_| {
+            remotes
+                .iter()
+                .all(|refs| repo.find_remote(refs.remote).is_err())
+        })
+    {
+        return Err(Failure::RemoteNotFound(refs.remote.to_string()).into());
+    }
+    if let Some(error) = remotes
+        .iter()
+        .find_map(|refs| refs.network_error.borrow_mut().take())
+    {
+        return Err(Failure::Network(error).into());
+    }
+    if options.strict {
+        return Err(Failure::NotAuthoritative.into());
+    }
+    Err(Failure::Undetermined(i18n::lookup("undetermined").to_string()).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn test_main_branch() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+
+        let result = run(tmp.path().to_str().unwrap(), "origin", &options()).unwrap();
+        assert_eq!(result, "main");
+    }
+
+    #[test]
+    fn test_master_branch() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "master");
+        commit(tmp.path(), "initial");
+
+        let result = run(tmp.path().to_str().unwrap(), "origin", &options()).unwrap();
+        assert_eq!(result, "master");
+    }
+
+    #[test]
+    fn test_origin_head() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+
+        Command::new("git")
+            .args([
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        let result = run(clone_dir.to_str().unwrap(), "origin", &options()).unwrap();
+        assert_eq!(result, "default");
+    }
+
+    #[test]
+    fn test_non_origin_remote() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+
+        Command::new("git")
+            .args([
+                "clone",
+                "--origin",
+                "upstream",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        let result = run(clone_dir.to_str().unwrap(), "upstream", &options()).unwrap();
+        assert_eq!(result, "default");
+    }
+
+    #[test]
+    fn test_fast() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "trunk");
+        commit(tmp.path(), "initial");
+        let fast = Options {
+            fast: true,
+            ..options()
+        };
+
+        assert!(run(tmp.path(), "origin", &fast).is_err());
+        Command::new("git")
+            .args(["branch", "master"])
+            .current_dir(tmp.path())
+            .output()
+            .unwrap();
+        assert_eq!(run(tmp.path(), "origin", &fast).unwrap(), "master");
+    }
+
+    #[test]
+    fn test_remote_tracking_guess() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "trunk");
+        commit(tmp.path(), "initial");
+        git::git(
+            tmp.path(),
+            &["update-ref", "refs/remotes/origin/master", "HEAD"],
+        )
+        .unwrap();
+        let fast = Options {
+            fast: true,
+            ..options()
+        };
+
+        assert_eq!(run(tmp.path(), "origin", &options()).unwrap(), "master");
+        assert_eq!(run(tmp.path(), "origin", &fast).unwrap(), "master");
+        assert!(run(tmp.path(), "upstream", &options()).is_err());
+        assert!(run(tmp.path(), "upstream", &fast).is_err());
+
+        // A local branch still comes first.
+        git::git(tmp.path(), &["branch", "main"]).unwrap();
+        assert_eq!(run(tmp.path(), "origin", &options()).unwrap(), "main");
+        assert_eq!(run(tmp.path(), "origin", &fast).unwrap(), "main");
+    }
+
+    #[test]
+    fn test_remote_branch_guess() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "feature");
+        commit(&repo_dir, "initial");
+        git::git(&repo_dir, &["branch", "develop"]).unwrap();
+        git::git(
+            tmp.path(),
+            &[
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        let git = |args: &[&str]| git::git(&clone_dir, args).unwrap();
+        git(&["symbolic-ref", "--delete", "refs/remotes/origin/HEAD"]);
+        let offline = Options {
+            offline: true,
+            ..options()
+        };
+
+        let resolved = run_detailed(&clone_dir, "origin", &offline).unwrap();
+        assert_eq!(
+            resolved,
+            Resolved::new("develop".to_string(), "remote-branch-guess")
+        );
+        let strict = Options {
+            strict: true,
+            ..offline.clone()
+        };
+        assert!(run(&clone_dir, "origin", &strict).is_err());
+
+        git(&["update-ref", "-d", "refs/remotes/origin/develop"]);
+        assert_eq!(run(&clone_dir, "origin", &offline).unwrap(), "feature");
+        git(&["update-ref", "refs/remotes/origin/other", "HEAD"]);
+        assert!(run(&clone_dir, "origin", &offline).is_err());
+    }
+
+    #[test]
+    fn test_tracking() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "main");
+        commit(&repo_dir, "initial");
+        git::git(&repo_dir, &["branch", "next"]).unwrap();
+        git::git(
+            tmp.path(),
+            &[
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+
+        assert_eq!(
+            tracking(&clone_dir, "main").unwrap(),
+            Tracking {
+                local: true,
+                upstream: Some("origin/main".to_string()),
+            }
+        );
+        assert_eq!(
+            tracking(&clone_dir, "next").unwrap(),
+            Tracking {
+                local: false,
+                upstream: None,
+            }
+        );
+        git::git(
+            &clone_dir,
+            &["branch", "--set-upstream-to", "origin/next", "main"],
+        )
+        .unwrap();
+        assert_eq!(
+            tracking(&clone_dir, "main").unwrap().upstream.as_deref(),
+            Some("origin/next")
+        );
+    }
+
+    #[test]
+    fn test_custom_refspecs() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        fs::create_dir(&repo_dir).unwrap();
+        fs::create_dir(&clone_dir).unwrap();
+        init_repo(&repo_dir, "trunk");
+        commit(&repo_dir, "initial");
+        init_repo(&clone_dir, "main");
+        commit(&clone_dir, "initial");
+
+        let git = |args: &[&str]| git::git(&clone_dir, args).unwrap();
+        git(&["remote", "add", "origin", repo_dir.to_str().unwrap()]);
+        git(&[
+            "config",
+            "remote.origin.fetch",
+            "+refs/heads/*:refs/remotes/mirror/*",
+        ]);
+        git(&["fetch", "-q", "origin"]);
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/mirror/HEAD",
+            "refs/remotes/mirror/trunk",
+        ]);
+        let remote_head = Options {
+            strategies: vec![Strategy::RemoteHead],
+            ..options()
+        };
+        assert_eq!(run(&clone_dir, "origin", &remote_head).unwrap(), "trunk");
+        let repo = git::discover(&clone_dir).unwrap();
+        assert_eq!(
+            tracking_ref(&repo, "origin", "trunk"),
+            "refs/remotes/mirror/trunk"
+        );
+
+        // Mapping a namespace of branches, or a single one.
+        let mapping = |spec: &str| {
+            let spec = gix::refspec::parse(spec.into(), gix::refspec::parse::Operation::Fetch);
+            Mapping::from_refspec(spec.unwrap()).unwrap()
+        };
+        let team = mapping("refs/heads/team/*:refs/remotes/team/*");
+        assert_eq!(
+            team.branch("refs/remotes/team/main"),
+            Some("team/main".to_string())
+        );
+        assert_eq!(team.tracking_ref("main"), None);
+        let single = mapping("+refs/heads/main:refs/remotes/origin/main");
+        assert_eq!(
+            single.branch("refs/remotes/origin/main"),
+            Some("main".to_string())
+        );
+        assert_eq!(single.branch("refs/remotes/origin/other"), None);
+    }
+
+    #[test]
+    fn test_push() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let git = |args: &[&str]| git::git(tmp.path(), args).unwrap();
+        for (remote, branch) in [("origin", "main"), ("fork", "dev")] {
+            git(&["remote", "add", remote, "/nonexistent"]);
+            let tracking = format!("refs/remotes/{}/{}", remote, branch);
+            git(&["update-ref", &tracking, "HEAD"]);
+            git(&[
+                "symbolic-ref",
+                &format!("refs/remotes/{}/HEAD", remote),
+                &tracking,
+            ]);
+        }
+        let push = Options {
+            push: true,
+            strategies: vec![Strategy::RemoteHead],
+            ..options()
+        };
+
+        assert_eq!(run(tmp.path(), "origin", &push).unwrap(), "main");
+        git(&["config", "remote.pushDefault", "fork"]);
+        assert_eq!(run(tmp.path(), "origin", &push).unwrap(), "dev");
+        git(&["config", "branch.main.pushRemote", "origin"]);
+        assert_eq!(run(tmp.path(), "origin", &push).unwrap(), "main");
+
+        // Detached, only remote.pushDefault applies.
+        git(&["checkout", "-q", "--detach"]);
+        assert_eq!(run(tmp.path(), "origin", &push).unwrap(), "dev");
+        let explicit = Options {
+            sources: vec![("remote", Source::CommandLine)],
+            ..push
+        };
+        assert_eq!(run(tmp.path(), "origin", &explicit).unwrap(), "main");
+    }
+
+    #[test]
+    fn test_chained_symrefs() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let git = |args: &[&str]| crate::git::git(tmp.path(), args).unwrap();
+        git(&["update-ref", "refs/remotes/origin/trunk", "HEAD"]);
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/origin/current",
+            "refs/remotes/origin/trunk",
+        ]);
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/origin/HEAD",
+            "refs/remotes/origin/current",
+        ]);
+        let fast = Options {
+            fast: true,
+            ..options()
+        };
+        assert_eq!(run(tmp.path(), "origin", &options()).unwrap(), "trunk");
+        assert_eq!(run(tmp.path(), "origin", &fast).unwrap(), "trunk");
+
+        // A loop is given up on, leaving the local names to answer.
+        git(&[
+            "symbolic-ref",
+            "refs/remotes/origin/trunk",
+            "refs/remotes/origin/current",
+        ]);
+        let resolved = run_detailed(tmp.path(), "origin", &options()).unwrap();
+        assert_eq!(resolved, Resolved::new("main".to_string(), "local-names"));
+        assert_eq!(run(tmp.path(), "origin", &fast).unwrap(), "main");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_symlinked_dir() {
+        // A symlink inside one repository pointing into another resolves the other, as git does.
+        let tmp = tempfile::tempdir().unwrap();
+        let (outer, inner) = (tmp.path().join("outer"), tmp.path().join("inner"));
+        fs::create_dir(&outer).unwrap();
+        fs::create_dir(&inner).unwrap();
+        init_repo(&outer, "main");
+        commit(&outer, "initial");
+        init_repo(&inner, "master");
+        commit(&inner, "initial");
+        let link = outer.join("link");
+        std::os::unix::fs::symlink(&inner, &link).unwrap();
+
+        assert_eq!(run(&link, "origin", &options()).unwrap(), "master");
+        assert_eq!(git::root(&link).unwrap(), fs::canonicalize(&inner).unwrap());
+    }
+
+    #[test]
+    fn test_case_variant() {
+        // On a case-insensitive filesystem `refs/heads/main` opens this branch too, but it must
+        // still be reported as stored.
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "Main");
+        commit(tmp.path(), "initial");
+
+        assert_eq!(run(tmp.path(), "origin", &options()).unwrap(), "Main");
+        let fast = Options {
+            fast: true,
+            ..options()
+        };
+        assert!(run(tmp.path(), "origin", &fast).is_err());
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[test]
+    fn test_deleted_origin_head() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+
+        Command::new("git")
+            .args([
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        let origin_head_file = clone_dir.join(".git/refs/remotes/origin/HEAD");
+        let _ = fs::remove_file(&origin_head_file);
+
+        // Without consent, the answer is only reported.
+        assert_eq!(run(&clone_dir, "origin", &options()).unwrap(), "default");
+        assert!(!origin_head_file.exists());
+        let set_head = Options {
+            set_head: true,
+            ..options()
+        };
+        let dry_run = Options {
+            dry_run: true,
+            ..set_head.clone()
+        };
+        assert_eq!(run(&clone_dir, "origin", &dry_run).unwrap(), "default");
+        assert!(!origin_head_file.exists());
+
+        let result = run(clone_dir.to_str().unwrap(), "origin", &set_head).unwrap();
+        assert_eq!(result, "default");
+
+        // The remote HEAD was restored, with a reflog entry saying by what.
+        let reflog = git::git(
+            &clone_dir,
+            &["reflog", "show", "--format=%gs", "refs/remotes/origin/HEAD"],
+        )
+        .unwrap();
+        assert_eq!(
+            reflog.lines().next(),
+            Some("git-default-branch: default is the default branch of origin")
+        );
+    }
+
+    #[test]
+    fn test_detached_origin_head() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+        Command::new("git")
+            .args(["branch", "other"])
+            .current_dir(&repo_dir)
+            .output()
+            .unwrap();
+        commit(&repo_dir, "second");
+
+        Command::new("git")
+            .args([
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args([
+                "update-ref",
+                "--no-deref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/default",
+            ])
+            .current_dir(&clone_dir)
+            .output()
+            .unwrap();
+        Command::new("git")
+            .args(["pack-refs", "--all"])
+            .current_dir(&clone_dir)
+            .output()
+            .unwrap();
+
+        let repo = gix::discover(&clone_dir).unwrap();
+        assert_eq!(
+            remote_head_branch(&repo, "origin").unwrap().as_deref(),
+            Some("default")
+        );
+    }
+
+    #[test]
+    fn test_configured_priorities() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+        Command::new("git")
+            .args([
+                "clone",
+                "--origin",
+                "upstream",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        let configured = Options {
+            remotes: vec!["origin".to_string(), "upstream".to_string()],
+            ..options()
+        };
+        assert_eq!(run(&clone_dir, "origin", &configured).unwrap(), "default");
+
+        let local_only = Options {
+            candidates: vec!["trunk".to_string(), "default".to_string()],
+            strategies: vec![Strategy::LocalNames],
+            ..options()
+        };
+        let repo = gix::discover(&clone_dir).unwrap();
+        assert_eq!(resolve(&repo, "upstream", &local_only).unwrap(), "default");
+    }
+
+    #[test]
+    fn test_git_config_keys() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+        Command::new("git")
+            .args([
+                "clone",
+                "--origin",
+                "upstream",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+        for (key, value) in [
+            ("defaultBranch.remote", "origin, upstream"),
+            ("defaultBranch.allowNetwork", "false"),
+        ] {
+            Command::new("git")
+                .args(["config", key, value])
+                .current_dir(&clone_dir)
+                .output()
+                .unwrap();
+        }
+
+        assert_eq!(run(&clone_dir, "origin", &options()).unwrap(), "default");
+        let explicit = Options {
+            sources: vec![("remote", Source::CommandLine)],
+            ..options()
+        };
+        fs::remove_file(clone_dir.join(".git/refs/remotes/upstream/HEAD")).unwrap();
+        // Offline, the local fallback finds neither main nor master, leaving only a guess.
+        assert_eq!(
+            run_detailed(&clone_dir, "upstream", &explicit).unwrap(),
+            Resolved::new("default".to_string(), "remote-branch-guess")
+        );
+
+        Command::new("git")
+            .args(["config", "defaultBranch.candidates", "trunk default"])
+            .current_dir(&clone_dir)
+            .output()
+            .unwrap();
+        assert_eq!(
+            run_detailed(&clone_dir, "upstream", &explicit).unwrap(),
+            Resolved::new("default".to_string(), "local-names")
+        );
+    }
+
+    #[cfg(feature = "scripting")]
+    #[test]
+    fn test_script() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "main");
+        commit(&repo_dir, "initial");
+        git::git(&repo_dir, &["branch", "stable"]).unwrap();
+        git::git(
+            tmp.path(),
+            &[
+                "clone",
+                "-q",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        git::git(&clone_dir, &["config", "team.defaultBranch", "stable"]).unwrap();
+        let script = tmp.path().join("default-branch.rhai");
+        fs::write(
+            &script,
+            r#"
+            let branch = config["team.defaultbranch"];
+            if remote == "origin" && branch in remote_branches && remote_head == "main" { branch }
+            "#,
+        )
+        .unwrap();
+        let scripted = |strategies| Options {
+            script: Some(script.clone()),
+            strategies,
+            ..options()
+        };
+
+        // Where the strategies put it decides whether it answers.
+        let first = scripted(vec![Strategy::Script, Strategy::RemoteHead]);
+        assert_eq!(run(&clone_dir, "origin", &first).unwrap(), "stable");
+        let last = scripted(vec![Strategy::RemoteHead, Strategy::Script]);
+        assert_eq!(run(&clone_dir, "origin", &last).unwrap(), "main");
+        let unset = Options {
+            strategies: vec![Strategy::Script],
+            ..options()
+        };
+        assert!(run(&clone_dir, "origin", &unset).is_err());
+        fs::write(&script, "let x = ;").unwrap();
+        let error = run(&clone_dir, "origin", &first).unwrap_err().to_string();
+        assert!(error.contains("default-branch.rhai"), "{}", error);
+    }
+
+    #[test]
+    fn test_host_profile_strategies() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        Command::new("git")
+            .args([
+                "remote",
+                "add",
+                "origin",
+                "https://git.corp.com/team/repo.git",
+            ])
+            .current_dir(tmp.path())
+            .output()
+            .unwrap();
+
+        let repo = gix::discover(tmp.path()).unwrap();
+        let offline = Options {
+            offline: true,
+            ..options()
+        };
+        assert_eq!(resolve(&repo, "origin", &offline).unwrap(), "main");
+        let restricted = Options {
+            hosts: vec![HostProfile {
+                host: "GIT.corp.com".to_string(),
+                strategies: vec![Strategy::RemoteHead],
+                ..HostProfile::default()
+            }],
+            ..options()
+        };
+        assert!(resolve(&repo, "origin", &restricted).is_err());
+
+        // The profile applies even where the remote HEAD could be read from the ref files.
+        for args in [
+            &["update-ref", "refs/remotes/origin/main", "HEAD"][..],
+            &[
+                "symbolic-ref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/main",
+            ],
+            &["branch", "trunk"],
+        ] {
+            git::git(tmp.path(), args).unwrap();
+        }
+        let local = Options {
+            hosts: vec![HostProfile {
+                host: "git.corp.com".to_string(),
+                strategies: vec![Strategy::LocalNames],
+                ..HostProfile::default()
+            }],
+            candidates: vec!["trunk".to_string()],
+            ..options()
+        };
+        assert_eq!(run(tmp.path(), "origin", &local).unwrap(), "trunk");
+        assert_eq!(run(tmp.path(), "origin", &options()).unwrap(), "main");
+    }
+
+    #[test]
+    fn test_fallback() {
+        let tmp = tempfile::tempdir().unwrap();
+        let fallback = Options {
+            fallback: Some("main".to_string()),
+            ..options()
+        };
+        assert!(run(tmp.path(), "origin", &options()).is_err());
+        assert_eq!(
+            run_detailed(tmp.path(), "origin", &fallback).unwrap(),
+            Resolved::new("main".to_string(), "fallback")
+        );
+
+        init_repo(tmp.path(), "trunk");
+        commit(tmp.path(), "initial");
+        Command::new("git")
+            .args(["branch", "master"])
+            .current_dir(tmp.path())
+            .output()
+            .unwrap();
+        assert_eq!(
+            run_detailed(tmp.path(), "origin", &fallback).unwrap(),
+            Resolved::new("master".to_string(), "local-names")
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        Command::new("git")
+            .args(["remote", "add", "origin", "/nonexistent"])
+            .current_dir(tmp.path())
+            .output()
+            .unwrap();
+        let strict = Options {
+            strict: true,
+            offline: true,
+            ..options()
+        };
+        let exit_code = |options: &Options| {
+            run(tmp.path(), "origin", options)
+                .unwrap_err()
+                .downcast_ref::<Failure>()
+                .map(Failure::exit_code)
+        };
+        assert_eq!(exit_code(&strict), Some(6));
+        assert_eq!(
+            exit_code(&Options {
+                fast: true,
+                ..strict
+            }),
+            Some(6)
+        );
+    }
+
+    #[cfg(feature = "network")]
+    #[test]
+    fn test_daemon_compatible() {
+        assert!(options().daemon_compatible());
+        for options in [
+            Options {
+                strict: true,
+                ..options()
+            },
+            Options {
+                offline: true,
+                ..options()
+            },
+            Options {
+                candidates: vec!["trunk".to_string()],
+                ..options()
+            },
+            Options {
+                fallback: Some("main".to_string()),
+                ..options()
+            },
+            Options {
+                interactive: true,
+                ..options()
+            },
+        ] {
+            assert!(!options.daemon_compatible());
+        }
+    }
+
+    #[test]
+    fn test_failure_exit_codes() {
+        let tmp = tempfile::tempdir().unwrap();
+        let exit_code = |remote: &str| {
+            run(tmp.path(), remote, &options())
+                .unwrap_err()
+                .downcast_ref::<Failure>()
+                .map(Failure::exit_code)
+        };
+        assert_eq!(exit_code("origin"), Some(2));
+
+        init_repo(tmp.path(), "trunk");
+        commit(tmp.path(), "initial");
+        assert_eq!(exit_code("origin"), Some(3));
+
+        let missing = tmp.path().join("missing");
+        Command::new("git")
+            .args(["remote", "add", "origin", missing.to_str().unwrap()])
+            .current_dir(tmp.path())
+            .output()
+            .unwrap();
+        if cfg!(feature = "subprocess") {
+            assert_eq!(exit_code("origin"), Some(5));
+        }
+        assert_eq!(
+            run(
+                tmp.path(),
+                "origin",
+                &Options {
+                    offline: true,
+                    ..options()
+                }
+            )
+            .unwrap_err()
+            .downcast_ref::<Failure>()
+            .map(Failure::exit_code),
+            Some(4)
+        );
+    }
+
+    #[test]
+    fn test_validate_remote() {
+        for remote in ["origin", "my-fork", "team/upstream", "a.b"] {
+            assert!(validate_remote(remote).is_ok(), "{}", remote);
+        }
+        for remote in [
+            "",
+            "--upload-pack=touch x",
+            "-o",
+            "../heads",
+            "a b",
+            "a..b",
+            "x.lock",
+        ] {
+            assert!(validate_remote(remote).is_err(), "{}", remote);
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let error = run(tmp.path(), "--upload-pack=touch pwned", &options()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid remote name: --upload-pack=touch pwned"
+        );
+        assert!(!tmp.path().join("pwned").exists());
+    }
+
+    #[test]
+    fn test_validate_branch() {
+        for branch in ["main", "release/1.0", "feature-x", "v1.2"] {
+            assert!(validate_branch(branch).is_ok(), "{}", branch);
+        }
+        for branch in [
+            "", "-main", "HEAD", "a..b", "a b", "x.lock", "/main", "main/", "a~1", "a:b",
+        ] {
+            assert!(validate_branch(branch).is_err(), "{}", branch);
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        let options = Options {
+            fallback: Some("bad..name".to_string()),
+            validate: true,
+            ..options()
+        };
+        let error = run(tmp.path(), "origin", &options).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The resolved name is not a valid branch name: bad..name"
+        );
+    }
+
+    #[test]
+    fn test_attempt_json() {
+        let elapsed = Duration::from_micros(1500);
+        assert_eq!(
+            attempt_json("cache", "cache", elapsed, &Ok(Some("main".to_string()))),
+            r#"{"event":"attempt","strategy":"cache","step":"cache","elapsed_ms":1.500,"outcome":"found","branch":"main"}"#
+        );
+        assert_eq!(
+            attempt_json("remote-head", "ref lookup", elapsed, &Ok(None)),
+            r#"{"event":"attempt","strategy":"remote-head","step":"ref lookup","elapsed_ms":1.500,"outcome":"not_found"}"#
+        );
+        assert_eq!(
+            attempt_json("set-head", "network", elapsed, &Err("boom".into())),
+            r#"{"event":"attempt","strategy":"set-head","step":"network","elapsed_ms":1.500,"outcome":"error","error":"boom"}"#
+        );
+    }
+
+    #[cfg(feature = "subprocess")]
+    #[test]
+    fn test_read_only() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert_eq!(read_only(tmp.path(), None), None);
+        assert_eq!(read_only(tmp.path(), Some(OsStr::new("1"))), None);
+        assert_eq!(
+            read_only(tmp.path(), Some(OsStr::new("0"))),
+            Some("GIT_OPTIONAL_LOCKS=0")
+        );
+        // The probe leaves nothing behind.
+        assert_eq!(fs::read_dir(tmp.path()).unwrap().count(), 0);
+        assert_eq!(
+            skip_json("set-head", "read-only filesystem"),
+            r#"{"event":"skip","strategy":"set-head","reason":"read-only filesystem"}"#
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use crate::json::{self, Value};
+use crate::resolve::{self, Failure};
+use crate::watch::{POLL_INTERVAL, Watcher};
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+
+/// A watched repository, reported with a `changed` notification whenever its branch changes.
+struct Watch {
+    dir: String,
+    remote: String,
+    options: resolve::Options,
+    watcher: Watcher,
+    branch: Option<String>,
+}
+
+/// Keeps repositories open between JSON-RPC 2.0 requests, one per line on stdin, answering on
+/// stdout. Methods:
+///
+/// - `resolve {dir, remote?}` returns `{branch, provenance}`
+/// - `watch {dir, remote?}` returns the same, then sends `changed {dir, remote, branch}`
+///   notifications until the session ends
+/// - `invalidate {dir?}` forgets open repositories and watches, for `dir` or all of them
+pub fn run(remote: &str, options: &resolve::Options) -> Result<(), Box<dyn std::error::Error>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines().map_while(Result::ok) {
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut session = Session {
+        repos: HashMap::new(),
+        watches: Vec::new(),
+        default_remote: remote,
+        options,
+    };
+    let mut stdout = io::stdout().lock();
+    loop {
+        match receiver.recv_timeout(POLL_INTERVAL) {
+            Ok(line) if line.trim().is_empty() => {}
+            Ok(line) => {
+                if let Some(response) = session.handle(&line) {
+                    writeln!(stdout, "{}", response)?;
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Ok(()),
+        }
+        for notification in session.poll() {
+            writeln!(stdout, "{}", notification)?;
+        }
+        stdout.flush()?;
+    }
+}
+
+struct Session<'a> {
+    repos: HashMap<String, gix::Repository>,
+    watches: Vec<Watch>,
+    default_remote: &'a str,
+    options: &'a resolve::Options,
+}
+
+impl Session<'_> {
+    /// Answers one request, or nothing for a notification.
+    fn handle(&mut self, line: &str) -> Option<String> {
+        let request = match json::parse(line) {
+            Ok(request) => request,
+            Err(e) => return Some(error(&Value::Null, -32700, &e, None)),
+        };
+        let id = request.get("id").cloned();
+        let method = request.get("method").and_then(Value::as_str);
+        let params = request.get("params").cloned().unwrap_or(Value::Null);
+        let result = match method {
+            Some("resolve") => self.resolve(&params, false),
+            Some("watch") => self.resolve(&params, true),
+            Some("invalidate") => Ok(self.invalidate(&params)),
+            Some(method) => Err((-32601, format!("Unknown method {}", method), None)),
+            None => Err((-32600, "Missing method".to_string(), None)),
+        };
+        let id = id?;
+        Some(match result {
+            Ok(result) => format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{}}}",
+                id_json(&id),
+                result
+            ),
+            Err((code, message, failure)) => error(&id, code, &message, failure),
+        })
+    }
+
+    fn resolve(&mut self, params: &Value, watch: bool) -> Result<String, RpcError> {
+        let dir = params
+            .get("dir")
+            .and_then(Value::as_str)
+            .ok_or_else(|| (-32602, "Missing dir".to_string(), None))?
+            .to_string();
+        let requested = params
+            .get("remote")
+            .and_then(Value::as_str)
+            .filter(|remote| !remote.is_empty());
+        let remote = requested.unwrap_or(self.default_remote).to_string();
+        let options = match requested {
+            Some(_) => self.options.with_explicit_remote(),
+            None => self.options.clone(),
+        };
+
+        if !self.repos.contains_key(&dir) {
+            let repo = resolve::open(&dir).map_err(failure)?;
+            self.repos.insert(dir.clone(), repo);
+        }
+        let repo = &self.repos[&dir];
+        let resolved = resolve::resolve_detailed(repo, &remote, &options).map_err(failure)?;
+        if watch
+            && !self
+                .watches
+                .iter()
+                .any(|w| w.dir == dir && w.remote == remote)
+        {
+            let mut watcher = Watcher::new(repo, &remote);
+            watcher.changed();
+            self.watches.push(Watch {
+                dir,
+                remote,
+                options,
+                watcher,
+                branch: Some(resolved.branch.clone()),
+            });
+        }
+        Ok(format!(
+            "{{\"branch\":{},\"provenance\":{}}}",
+            json::quote(&resolved.branch),
+            json::quote(resolved.provenance)
+        ))
+    }
+
+    fn invalidate(&mut self, params: &Value) -> String {
+        match params.get("dir").and_then(Value::as_str) {
+            Some(dir) => {
+                self.repos.remove(dir);
+                self.watches.retain(|w| w.dir != dir);
+            }
+            None => {
+                self.repos.clear();
+                self.watches.clear();
+            }
+        }
+        "null".to_string()
+    }
+
+    /// Re-resolves watched repositories whose remote HEAD files changed.
+    fn poll(&mut self) -> Vec<String> {
+        let mut notifications = Vec::new();
+        for watch in &mut self.watches {
+            if !watch.watcher.changed() {
+                continue;
+            }
+            let branch = self
+                .repos
+                .get(&watch.dir)
+                .and_then(|repo| resolve::resolve(repo, &watch.remote, &watch.options).ok());
+            // Resolution may have refreshed the remote HEAD itself.
+            watch.watcher.changed();
+            if branch != watch.branch {
+                notifications.push(format!(
+                    "{{\"jsonrpc\":\"2.0\",\"method\":\"changed\",\"params\":{{\"dir\":{},\"remote\":{},\"branch\":{}}}}}",
+                    json::quote(&watch.dir),
+                    json::quote(&watch.remote),
+                    branch.as_deref().map_or("null".to_string(), json::quote)
+                ));
+                watch.branch = branch;
+            }
+        }
+        notifications
+    }
+}
+
+/// A JSON-RPC error code and message, with the resolution failure behind it if any.
+type RpcError = (i64, String, Option<(&'static str, i32)>);
+
+fn failure(e: Box<dyn std::error::Error>) -> RpcError {
+    let kind = e
+        .downcast_ref::<Failure>()
+        .map(|failure| (failure.kind(), failure.exit_code()));
+    (-32000, e.to_string(), kind)
+}
+
+fn error(id: &Value, code: i64, message: &str, failure: Option<(&str, i32)>) -> String {
+    let data = failure.map_or(String::new(), |(kind, exit_code)| {
+        format!(
+            ",\"data\":{{\"kind\":{},\"exit_code\":{}}}",
+            json::quote(kind),
+            exit_code
+        )
+    });
+    format!(
+        "{{\"jsonrpc\":\"2.0\",\"id\":{},\"error\":{{\"code\":{},\"message\":{}{}}}}}",
+        id_json(id),
+        code,
+        json::quote(message),
+        data
+    )
+}
+
+fn id_json(id: &Value) -> String {
+    match id {
+        Value::String(s) => json::quote(s),
+        Value::Number(n) if n.fract() == 0.0 => format!("{}", *n as i64),
+        Value::Number(n) => n.to_string(),
+        _ => "null".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::git;
+    use crate::testutil::{commit, init_repo, options};
+
+    #[test]
+    fn test_session() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+        let dir = json::quote(tmp.path().to_str().unwrap());
+        let options = options();
+        let mut session = Session {
+            repos: HashMap::new(),
+            watches: Vec::new(),
+            default_remote: "origin",
+            options: &options,
+        };
+
+        assert_eq!(
+            session.handle(&format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"watch","params":{{"dir":{}}}}}"#,
+                dir
+            )),
+            Some(
+                r#"{"jsonrpc":"2.0","id":1,"result":{"branch":"main","provenance":"local-names"}}"#
+                    .to_string()
+            )
+        );
+        assert!(session.poll().is_empty());
+
+        // Point the remote HEAD elsewhere, as a fetch would.
+        git(tmp.path(), &["branch", "trunk"]).unwrap();
+        git(
+            tmp.path(),
+            &["update-ref", "refs/remotes/origin/trunk", "HEAD"],
+        )
+        .unwrap();
+        git(
+            tmp.path(),
+            &[
+                "symbolic-ref",
+                "refs/remotes/origin/HEAD",
+                "refs/remotes/origin/trunk",
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            session.poll(),
+            vec![format!(
+                r#"{{"jsonrpc":"2.0","method":"changed","params":{{"dir":{},"remote":"origin","branch":"trunk"}}}}"#,
+                dir
+            )]
+        );
+
+        assert_eq!(
+            session.handle(r#"{"jsonrpc":"2.0","id":"x","method":"invalidate"}"#),
+            Some(r#"{"jsonrpc":"2.0","id":"x","result":null}"#.to_string())
+        );
+        assert!(session.watches.is_empty());
+        assert_eq!(
+            session
+                .handle(
+                    r#"{"jsonrpc":"2.0","id":2,"method":"resolve","params":{"dir":"/nonexistent"}}"#
+                )
+                .unwrap(),
+            format!(
+                r#"{{"jsonrpc":"2.0","id":2,"error":{{"code":-32000,"message":{},"data":{{"kind":"not_a_repository","exit_code":2}}}}}}"#,
+                json::quote(&resolve::open("/nonexistent").unwrap_err().to_string())
+            )
+        );
+        assert!(
+            session
+                .handle(r#"{"jsonrpc":"2.0","method":"invalidate"}"#)
+                .is_none()
+        );
+    }
+}
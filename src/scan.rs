@@ -0,0 +1,399 @@
+use crate::batch::{self, BatchArgs, RemoteStyle};
+#[cfg(feature = "tui")]
+use crate::tui;
+use crate::{json, resolve};
+use clap::ValueEnum;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(clap::Args)]
+pub struct ScanArgs {
+    /// Directory to search for repositories
+    #[arg(default_value = ".")]
+    root: PathBuf,
+
+    #[arg(short, long, value_enum, default_value_t = Format::Table)]
+    format: Format,
+
+    /// Show the repositories in an interactive table, with keys to refresh them, fix their
+    /// remote HEAD or open a shell in one
+    #[cfg(feature = "tui")]
+    #[arg(long, conflicts_with = "format")]
+    tui: bool,
+
+    #[command(flatten)]
+    discovery: DiscoveryArgs,
+}
+
+#[derive(clap::Args, Default)]
+pub struct DiscoveryArgs {
+    /// Do not descend more than this many directories below the search root
+    #[arg(long)]
+    pub max_depth: Option<usize>,
+
+    /// Skip directories matching this glob (repeatable); globs without a slash match any path component
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Do not look for repositories inside other repositories, including submodules
+    #[arg(long)]
+    pub skip_nested: bool,
+}
+
+impl DiscoveryArgs {
+    fn is_excluded(&self, root: &Path, path: &Path) -> bool {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return false;
+        };
+        let relative = relative.to_string_lossy().replace('\\', "/");
+        let name = relative.rsplit('/').next().unwrap_or_default();
+        self.exclude.iter().any(|pattern| {
+            let value = if pattern.contains('/') {
+                relative.as_str()
+            } else {
+                name
+            };
+            gix::glob::wildmatch(
+                pattern.as_str().into(),
+                value.into(),
+                gix::glob::wildmatch::Mode::NO_MATCH_SLASH_LITERAL,
+            )
+        })
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Format {
+    Table,
+    Json,
+    Csv,
+}
+
+pub struct Entry {
+    pub name: String,
+    /// The default branch, or why it could not be resolved.
+    pub result: Result<String, String>,
+    /// The remote the branch is the default of, written in a column of its own when known.
+    pub remote: Option<String>,
+}
+
+pub fn run(
+    args: &ScanArgs,
+    remote: &str,
+    resolve: &resolve::Options,
+    options: &BatchArgs,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repos = find_repositories(&args.root, &args.discovery);
+    #[cfg(feature = "tui")]
+    if args.tui {
+        return tui::run(&repos, remote, resolve, options.jobs);
+    }
+    let entries = batch::map_parallel(&repos, options.jobs, |path| Entry {
+        name: path.display().to_string(),
+        result: resolve::run(path, remote, resolve).map_err(|e| e.to_string()),
+        remote: options
+            .with_remote
+            .map(|_| resolve::primary_remote(path, remote, resolve)),
+    });
+
+    if !options.summary_only {
+        // `slash` reads as one value, so it takes the place of the branch rather than a column.
+        let joined;
+        let written = match options.with_remote {
+            Some(RemoteStyle::Slash) => {
+                joined = entries
+                    .iter()
+                    .map(|e| Entry {
+                        name: e.name.clone(),
+                        result: e.result.as_ref().map_err(String::clone).map(|branch| {
+                            RemoteStyle::Slash.join(e.remote.as_deref().unwrap_or(remote), branch)
+                        }),
+                        remote: None,
+                    })
+                    .collect::<Vec<_>>();
+                &joined
+            }
+            _ => &entries,
+        };
+        write_entries(&mut io::stdout().lock(), "path", written, args.format)?;
+    }
+    batch::print_summary(entries.iter().map(|e| e.result.as_deref().ok()), options);
+
+    let failures = entries.iter().filter(|e| e.result.is_err()).count();
+    if failures > 0 {
+        return Err(format!("Failed to resolve {} repositories", failures).into());
+    }
+    Ok(())
+}
+
+/// Returns every directory under `root` that has a `.git` entry, in file name order.
+pub fn find_repositories(root: &Path, options: &DiscoveryArgs) -> Vec<PathBuf> {
+    let mut walker = WalkDir::new(root).sort_by_file_name();
+    if let Some(depth) = options.max_depth {
+        walker = walker.max_depth(depth);
+    }
+    let mut entries = walker.into_iter().filter_entry(|e| {
+        e.file_name() != ".git" && (e.depth() == 0 || !options.is_excluded(root, e.path()))
+    });
+
+    let mut repos = Vec::new();
+    while let Some(entry) = entries.next() {
+        let Ok(entry) = entry else { continue };
+        if !entry.file_type().is_dir() || !entry.path().join(".git").exists() {
+            continue;
+        }
+        repos.push(entry.into_path());
+        if options.skip_nested {
+            entries.skip_current_dir();
+        }
+    }
+    repos
+}
+
+/// Writes `entries` in `format`, using `label` as the name of the column identifying each entry,
+/// with a remote column when any entry has a remote.
+pub fn write_entries(
+    w: &mut impl Write,
+    label: &str,
+    entries: &[Entry],
+    format: Format,
+) -> io::Result<()> {
+    let with_remote = entries.iter().any(|e| e.remote.is_some());
+    let remote = |e: &Entry| e.remote.clone().unwrap_or_default();
+    match format {
+        Format::Table => {
+            let width = entries
+                .iter()
+                .map(|e| e.name.len())
+                .chain([label.len()])
+                .max()
+                .unwrap_or_default();
+            let remote_width = entries
+                .iter()
+                .map(|e| remote(e).len())
+                .chain(["REMOTE".len()])
+                .max()
+                .unwrap_or_default();
+            let name = |e: &str, remote: &str| match with_remote {
+                true => format!("{:width$}  {:remote_width$}", e, remote),
+                false => format!("{:width$}", e),
+            };
+            writeln!(w, "{}  BRANCH", name(&label.to_uppercase(), "REMOTE"))?;
+            for e in entries {
+                match &e.result {
+                    Ok(branch) => writeln!(w, "{}  {}", name(&e.name, &remote(e)), branch)?,
+                    Err(error) => writeln!(w, "{}  error: {}", name(&e.name, &remote(e)), error)?,
+                }
+            }
+        }
+        Format::Json => {
+            let items = entries
+                .iter()
+                .map(|e| {
+                    let status = match &e.result {
+                        Ok(branch) => {
+                            format!("\"status\":\"ok\",\"branch\":{}", json::quote(branch))
+                        }
+                        Err(error) => {
+                            format!("\"status\":\"error\",\"error\":{}", json::quote(error))
+                        }
+                    };
+                    let remote = e.remote.as_deref().map_or(String::new(), |remote| {
+                        format!(",\"remote\":{}", json::quote(remote))
+                    });
+                    format!(
+                        "{{{}:{}{},{}}}",
+                        json::quote(label),
+                        json::quote(&e.name),
+                        remote,
+                        status
+                    )
+                })
+                .collect::<Vec<_>>();
+            writeln!(w, "[{}]", items.join(","))?;
+        }
+        Format::Csv => {
+            let name = |e: &str, remote: &str| match with_remote {
+                true => format!("{},{}", csv_field(e), csv_field(remote)),
+                false => csv_field(e),
+            };
+            writeln!(w, "{},status,branch,error", name(label, "remote"))?;
+            for e in entries {
+                let (status, branch, error) = match &e.result {
+                    Ok(branch) => ("ok", branch.as_str(), ""),
+                    Err(error) => ("error", "", error.as_str()),
+                };
+                writeln!(
+                    w,
+                    "{},{},{},{}",
+                    name(&e.name, &remote(e)),
+                    status,
+                    csv_field(branch),
+                    csv_field(error)
+                )?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo};
+    use std::fs;
+
+    #[test]
+    fn test_find_repositories() {
+        let tmp = tempfile::tempdir().unwrap();
+        for (name, branch) in [("b", "master"), ("a/nested", "main")] {
+            let dir = tmp.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            init_repo(&dir, branch);
+            commit(&dir, "initial");
+        }
+        fs::create_dir(tmp.path().join("not-a-repo")).unwrap();
+
+        let repos = find_repositories(tmp.path(), &DiscoveryArgs::default());
+        assert_eq!(
+            repos,
+            vec![tmp.path().join("a/nested"), tmp.path().join("b")]
+        );
+    }
+
+    #[test]
+    fn test_find_repositories_with_options() {
+        let tmp = tempfile::tempdir().unwrap();
+        for name in [
+            "outer",
+            "outer/inner",
+            "deep/er/repo",
+            "vendor/lib",
+            "x/vendor",
+        ] {
+            let dir = tmp.path().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            init_repo(&dir, "main");
+        }
+
+        let find = |options: DiscoveryArgs| {
+            find_repositories(tmp.path(), &options)
+                .into_iter()
+                .map(|p| {
+                    p.strip_prefix(tmp.path())
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            find(DiscoveryArgs {
+                max_depth: Some(2),
+                ..DiscoveryArgs::default()
+            }),
+            vec!["outer", "outer/inner", "vendor/lib", "x/vendor"]
+        );
+        assert_eq!(
+            find(DiscoveryArgs {
+                exclude: vec!["vendor".to_string(), "deep/*".to_string()],
+                ..DiscoveryArgs::default()
+            }),
+            vec!["outer", "outer/inner"]
+        );
+        assert_eq!(
+            find(DiscoveryArgs {
+                skip_nested: true,
+                ..DiscoveryArgs::default()
+            }),
+            vec!["deep/er/repo", "outer", "vendor/lib", "x/vendor"]
+        );
+    }
+
+    #[test]
+    fn test_write_entries() {
+        let entries = vec![
+            Entry {
+                name: "a".to_string(),
+                result: Ok("main".to_string()),
+                remote: None,
+            },
+            Entry {
+                name: "b,c".to_string(),
+                result: Ok("master".to_string()),
+                remote: None,
+            },
+            Entry {
+                name: "d".to_string(),
+                result: Err("not a repository".to_string()),
+                remote: None,
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_entries(&mut out, "path", &entries, Format::Table).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "PATH  BRANCH\na     main\nb,c   master\nd     error: not a repository\n"
+        );
+
+        let mut out = Vec::new();
+        write_entries(&mut out, "path", &entries, Format::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            concat!(
+                "[{\"path\":\"a\",\"status\":\"ok\",\"branch\":\"main\"},",
+                "{\"path\":\"b,c\",\"status\":\"ok\",\"branch\":\"master\"},",
+                "{\"path\":\"d\",\"status\":\"error\",\"error\":\"not a repository\"}]\n"
+            )
+        );
+
+        let mut out = Vec::new();
+        write_entries(&mut out, "path", &entries, Format::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "path,status,branch,error\na,ok,main,\n\"b,c\",ok,master,\nd,error,,not a repository\n"
+        );
+
+        let entries = vec![
+            Entry {
+                name: "a".to_string(),
+                result: Ok("main".to_string()),
+                remote: Some("upstream".to_string()),
+            },
+            Entry {
+                name: "d".to_string(),
+                result: Err("not a repository".to_string()),
+                remote: Some("origin".to_string()),
+            },
+        ];
+        let mut out = Vec::new();
+        write_entries(&mut out, "path", &entries, Format::Table).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "PATH  REMOTE    BRANCH\na     upstream  main\nd     origin    error: not a repository\n"
+        );
+        let mut out = Vec::new();
+        write_entries(&mut out, "path", &entries[..1], Format::Json).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "[{\"path\":\"a\",\"remote\":\"upstream\",\"status\":\"ok\",\"branch\":\"main\"}]\n"
+        );
+        let mut out = Vec::new();
+        write_entries(&mut out, "path", &entries[..1], Format::Csv).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "path,remote,status,branch,error\na,upstream,ok,main,\n"
+        );
+    }
+}
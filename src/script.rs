@@ -0,0 +1,117 @@
+//! The `script` strategy: a Rhai script picks the default branch from what the repository knows
+//! about a remote, wherever `strategies` places it.
+//!
+//! The script sees `remote`, `url`, `remote_head`, `refs`, `branches`, `remote_branches` and
+//! `config`, and returns a branch name, or `()` when it does not know. Rhai scripts cannot touch
+//! files or run programs, and one that runs too long is stopped.
+
+use rhai::{Array, Dynamic, Engine, Map, Scope};
+use std::path::Path;
+
+/// What a script sees of the repository, for one remote.
+pub struct Context {
+    pub remote: String,
+    /// Its fetch URL, when it is configured.
+    pub url: Option<String>,
+    /// The branch the remote HEAD points to, when it is recorded.
+    pub remote_head: Option<String>,
+    /// Every ref, by its full name.
+    pub refs: Vec<String>,
+    /// The local branches.
+    pub branches: Vec<String>,
+    /// The remote's branches, by their names there.
+    pub remote_branches: Vec<String>,
+    /// The repository's own config, as `section.subsection.key` and the last value set.
+    pub config: Vec<(String, String)>,
+}
+
+/// How many operations a script may run before it is stopped, so a loop cannot hang a lookup.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// Runs the script at `path` for `context`, returning the branch it names.
+pub fn run(path: &Path, context: &Context) -> Result<Option<String>, String> {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_OPERATIONS);
+    // Standard output is for the branch.
+    engine.on_print(|text| eprintln!("{}", text));
+    engine.on_debug(|text, _, position| log::debug!("script at {}: {}", position, text));
+    let optional = |value: &Option<String>| value.clone().map_or(Dynamic::UNIT, Dynamic::from);
+    let array = |values: &[String]| values.iter().cloned().map(Dynamic::from).collect::<Array>();
+    let mut scope = Scope::new();
+    scope
+        .push_constant("remote", context.remote.clone())
+        .push_constant("url", optional(&context.url))
+        .push_constant("remote_head", optional(&context.remote_head))
+        .push_constant("refs", array(&context.refs))
+        .push_constant("branches", array(&context.branches))
+        .push_constant("remote_branches", array(&context.remote_branches))
+        .push_constant(
+            "config",
+            context
+                .config
+                .iter()
+                .map(|(key, value)| (key.into(), Dynamic::from(value.clone())))
+                .collect::<Map>(),
+        );
+    log::debug!("running script {}", path.display());
+    let result = engine
+        .eval_file_with_scope::<Dynamic>(&mut scope, path.to_path_buf())
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    if result.is_unit() {
+        return Ok(None);
+    }
+    let branch = result.into_string().map_err(|kind| {
+        format!(
+            "{}: the script returned {} rather than a branch name or ()",
+            path.display(),
+            kind
+        )
+    })?;
+    Ok((!branch.is_empty()).then_some(branch))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_run() {
+        let tmp = tempfile::tempdir().unwrap();
+        let script = tmp.path().join("default-branch.rhai");
+        let context = Context {
+            remote: "origin".to_string(),
+            url: Some("https://example.com/team/app.git".to_string()),
+            remote_head: None,
+            refs: vec!["refs/heads/develop".to_string()],
+            branches: vec!["develop".to_string()],
+            remote_branches: vec!["develop".to_string(), "stable".to_string()],
+            config: vec![("team.branch".to_string(), "stable".to_string())],
+        };
+        let run_script = |source: &str| {
+            fs::write(&script, source).unwrap();
+            run(&script, &context)
+        };
+
+        assert_eq!(
+            run_script(r#"if url.contains("team/") { config["team.branch"] }"#),
+            Ok(Some("stable".to_string()))
+        );
+        assert_eq!(
+            run_script(r#"if remote_head != () { remote_head }"#),
+            Ok(None)
+        );
+        assert_eq!(
+            run_script(r#"if "develop" in remote_branches { "develop" } else { "main" }"#),
+            Ok(Some("develop".to_string()))
+        );
+        assert_eq!(run_script(r#""""#), Ok(None));
+        assert!(
+            run_script("42")
+                .unwrap_err()
+                .contains("rather than a branch name")
+        );
+        assert!(run_script("loop {}").is_err());
+        assert!(run_script("open(").is_err());
+    }
+}
@@ -0,0 +1,606 @@
+use crate::metrics::Metrics;
+use crate::{json, resolve};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, Instant};
+
+#[derive(clap::Args)]
+pub struct ServeArgs {
+    /// Address to listen on, unless systemd passes a socket to listen on instead
+    #[arg(short, long, default_value = "127.0.0.1:7878")]
+    listen: String,
+
+    /// Listen on a Unix socket instead, which the CLI queries automatically when it is at the
+    /// default location
+    #[arg(long, value_name = "PATH", conflicts_with = "listen")]
+    #[allow(clippy::option_option)]
+    unix_socket: Option<Option<PathBuf>>,
+
+    /// Permissions of the Unix socket, in octal; only users who may write to it can connect
+    #[arg(long, value_name = "MODE", default_value = "600", value_parser = parse_mode)]
+    socket_mode: u32,
+
+    /// Instead of serving, ask the daemon at --listen or --unix-socket for `/healthz` and fail
+    /// unless it answers, for systemd watchdogs and container health checks
+    #[arg(long)]
+    health_check: bool,
+}
+
+/// How long `--health-check` waits for the daemon to answer.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the CLI waits on the daemon before resolving by itself; the daemon answers one
+/// connection at a time, so a stuck one would otherwise hang every lookup.
+#[cfg(unix)]
+const QUERY_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a client may take to send its request or read the answer, for the same reason.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most bytes read of a request line and its headers; longer requests are refused.
+const MAX_REQUEST_BYTES: u64 = 16 * 1024;
+
+/// How many discovered repositories are kept for later requests.
+const MAX_REPOSITORIES: usize = 256;
+
+/// A connection `serve` answers, which can be given read and write timeouts.
+pub trait Connection: Read + Write {
+    fn set_timeouts(&self, timeout: Duration) -> io::Result<()>;
+}
+
+impl Connection for TcpStream {
+    fn set_timeouts(&self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+#[cfg(unix)]
+impl Connection for UnixStream {
+    fn set_timeouts(&self, timeout: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(timeout))?;
+        self.set_write_timeout(Some(timeout))
+    }
+}
+
+pub fn run(
+    args: &ServeArgs,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if args.health_check {
+        return health_check(args);
+    }
+    #[cfg(unix)]
+    {
+        let fd = listen_fd(
+            std::env::var("LISTEN_PID").ok(),
+            std::env::var("LISTEN_FDS").ok(),
+        );
+        // SAFETY: this runs at startup, before any other thread exists. The variables are removed
+        // so git subprocesses do not take the socket for theirs.
+        unsafe {
+            for name in ["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+                std::env::remove_var(name);
+            }
+        }
+        if let Some(fd) = fd {
+            return serve_activated(fd, remote, options);
+        }
+    }
+    if let Some(path) = &args.unix_socket {
+        #[cfg(unix)]
+        {
+            let path = path.clone().unwrap_or_else(default_socket_path);
+            let listener = bind_unix(&path, args.socket_mode)?;
+            eprintln!("Listening on {}", path.display());
+            serve(listener.incoming(), remote, options);
+            return Ok(());
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = path;
+            return Err("Unix sockets are not supported on this platform".into());
+        }
+    }
+
+    let listener = TcpListener::bind(&args.listen)?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    serve(listener.incoming(), remote, options);
+    Ok(())
+}
+
+/// The socket systemd passed for socket activation, as `sd_listen_fds` finds it: the first
+/// descriptor after stdio, when `LISTEN_PID` names this process.
+#[cfg(unix)]
+fn listen_fd(pid: Option<String>, fds: Option<String>) -> Option<std::os::fd::RawFd> {
+    if pid?.parse() != Ok(std::process::id()) || fds?.parse::<u32>().ok()? == 0 {
+        return None;
+    }
+    Some(3)
+}
+
+/// Serves on the socket systemd passed as `fd`, a TCP or a Unix one.
+#[cfg(unix)]
+fn serve_activated(
+    fd: std::os::fd::RawFd,
+    remote: &str,
+    options: &resolve::Options,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use std::os::fd::{FromRawFd, IntoRawFd};
+    // SAFETY: systemd hands the socket to this process alone, and it is taken over only once.
+    let listener = unsafe { TcpListener::from_raw_fd(fd) };
+    // Only an internet socket has an address a TCP listener can report.
+    if let Ok(addr) = listener.local_addr() {
+        eprintln!("Listening on http://{} (socket activation)", addr);
+        serve(listener.incoming(), remote, options);
+        return Ok(());
+    }
+    // SAFETY: as above, the descriptor moves from one owner to the other.
+    let listener = unsafe { UnixListener::from_raw_fd(listener.into_raw_fd()) };
+    eprintln!("Listening on a Unix socket (socket activation)");
+    serve(listener.incoming(), remote, options);
+    Ok(())
+}
+
+/// Fails unless the daemon `args` describe answers `/healthz`.
+fn health_check(args: &ServeArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let response = match &args.unix_socket {
+        #[cfg(unix)]
+        Some(path) => {
+            let path = path.clone().unwrap_or_else(default_socket_path);
+            let stream = UnixStream::connect(&path)
+                .map_err(|e| format!("Cannot connect to {}: {}", path.display(), e))?;
+            stream.set_read_timeout(Some(HEALTH_CHECK_TIMEOUT))?;
+            get(stream, "/healthz")?
+        }
+        #[cfg(not(unix))]
+        Some(_) => return Err("Unix sockets are not supported on this platform".into()),
+        None => {
+            let stream = TcpStream::connect(&args.listen)
+                .map_err(|e| format!("Cannot connect to {}: {}", args.listen, e))?;
+            stream.set_read_timeout(Some(HEALTH_CHECK_TIMEOUT))?;
+            get(stream, "/healthz")?
+        }
+    };
+    let status = response.lines().next().unwrap_or_default();
+    if !status.starts_with("HTTP/1.1 200 ") {
+        return Err(format!("The daemon is unhealthy: {}", status).into());
+    }
+    Ok(())
+}
+
+/// Sends a `GET` for `target` over `stream` and returns the whole response.
+fn get<S: Read + Write>(mut stream: S, target: &str) -> io::Result<String> {
+    write!(stream, "GET {} HTTP/1.1\r\n\r\n", target)?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    Ok(response)
+}
+
+/// Returns `$XDG_RUNTIME_DIR/git-default-branch.sock`, falling back to a per-user name in the
+/// temporary directory.
+pub fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => PathBuf::from(dir).join("git-default-branch.sock"),
+        None => std::env::temp_dir().join(format!(
+            "git-default-branch-{}.sock",
+            std::env::var("USER").unwrap_or_default()
+        )),
+    }
+}
+
+/// Binds `path` with permissions `mode`, replacing a socket file left behind by a daemon that is
+/// no longer running. Anything else at `path` is left alone.
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: u32) -> io::Result<UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if !metadata.file_type().is_socket() => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{}: path exists and is not a socket", path.display()),
+            ));
+        }
+        Ok(_) if UnixStream::connect(path).is_err() => std::fs::remove_file(path)?,
+        _ => {}
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    Ok(listener)
+}
+
+/// Parses `--socket-mode` as octal permission bits.
+fn parse_mode(mode: &str) -> Result<u32, String> {
+    u32::from_str_radix(mode, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o777)
+        .ok_or_else(|| format!("not an octal file mode: {}", mode))
+}
+
+/// Handles connections one at a time, reusing discovered repositories across requests.
+pub fn serve<S: Connection>(
+    incoming: impl Iterator<Item = io::Result<S>>,
+    default_remote: &str,
+    options: &resolve::Options,
+) {
+    let mut repos = HashMap::new();
+    let mut metrics = Metrics::default();
+    for stream in incoming {
+        let Ok(stream) = stream else { continue };
+        if let Err(e) = stream.set_timeouts(REQUEST_TIMEOUT) {
+            eprintln!("{}", e);
+            continue;
+        }
+        if let Err(e) = handle(stream, &mut repos, &mut metrics, default_remote, options) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// Asks a daemon listening on `socket` for the default branch of `dir`. Any failure, including
+/// no daemon running, yields `None` so callers resolve locally instead.
+#[cfg(unix)]
+pub fn query(socket: &Path, dir: &Path, remote: &str) -> Option<String> {
+    let dir = crate::paths::canonicalize(dir).ok()?;
+    let stream = UnixStream::connect(socket).ok()?;
+    stream.set_read_timeout(Some(QUERY_TIMEOUT)).ok()?;
+    stream.set_write_timeout(Some(QUERY_TIMEOUT)).ok()?;
+    log::debug!("asking the daemon at {}", socket.display());
+    let target = format!(
+        "/resolve?dir={}&remote={}",
+        percent_encode(&dir.to_string_lossy()),
+        percent_encode(remote)
+    );
+    let response = get(stream, &target).ok()?;
+    let (head, body) = response.split_once("\r\n\r\n")?;
+    if !head.starts_with("HTTP/1.1 200 ") {
+        return None;
+    }
+    Some(json::parse(body).ok()?.get("branch")?.as_str()?.to_string())
+}
+
+#[cfg(not(unix))]
+pub fn query(_socket: &Path, _dir: &Path, _remote: &str) -> Option<String> {
+    None
+}
+
+fn handle<S: Read + Write>(
+    mut stream: S,
+    repos: &mut HashMap<String, gix::Repository>,
+    metrics: &mut Metrics,
+    default_remote: &str,
+    options: &resolve::Options,
+) -> io::Result<()> {
+    let mut reader = BufReader::new((&mut stream).take(MAX_REQUEST_BYTES));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Drain the headers; none of them affect the response.
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 2 {
+        line.clear();
+    }
+    let too_large = reader.get_ref().limit() == 0;
+    drop(reader);
+
+    let (status, content_type, body) = if too_large {
+        (
+            "431 Request Header Fields Too Large",
+            JSON,
+            error_body("Request too large"),
+        )
+    } else {
+        respond(&request_line, repos, metrics, default_remote, options)
+    };
+    metrics.request(
+        path_label(&request_line),
+        status[..3].parse().unwrap_or_default(),
+    );
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+const JSON: &str = "application/json";
+
+/// The path of `request_line` for the request counter, with any path `serve` does not answer
+/// lumped together so clients cannot grow the metrics without bound.
+fn path_label(request_line: &str) -> &'static str {
+    let target = request_line.split_whitespace().nth(1).unwrap_or_default();
+    match target.split_once('?').map_or(target, |(path, _)| path) {
+        "/resolve" => "/resolve",
+        "/metrics" => "/metrics",
+        "/healthz" => "/healthz",
+        "/readyz" => "/readyz",
+        _ => "other",
+    }
+}
+
+/// Answers `request_line` with a status, a content type and a body.
+fn respond(
+    request_line: &str,
+    repos: &mut HashMap<String, gix::Repository>,
+    metrics: &mut Metrics,
+    default_remote: &str,
+    options: &resolve::Options,
+) -> (&'static str, &'static str, String) {
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return ("400 Bad Request", JSON, error_body("Malformed request"));
+    };
+    if method != "GET" {
+        return (
+            "405 Method Not Allowed",
+            JSON,
+            error_body("Only GET is supported"),
+        );
+    }
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    match path {
+        "/resolve" => {}
+        "/metrics" => return ("200 OK", "text/plain; version=0.0.4", metrics.render()),
+        "/healthz" => return ("200 OK", JSON, "{\"status\":\"ok\"}".to_string()),
+        "/readyz" => {
+            return match ready() {
+                Ok(()) => ("200 OK", JSON, "{\"status\":\"ready\"}".to_string()),
+                Err(e) => ("503 Service Unavailable", JSON, error_body(&e)),
+            };
+        }
+        _ => return ("404 Not Found", JSON, error_body("Not found")),
+    }
+
+    let params = parse_query(query);
+    let dir = params.get("dir").map_or(".", String::as_str);
+    // A request naming a remote overrides the configured remote priority.
+    let requested = params.get("remote").filter(|remote| !remote.is_empty());
+    let remote = requested.map_or(default_remote, String::as_str);
+    let options = &match requested {
+        Some(_) => options.with_explicit_remote(),
+        None => options.clone(),
+    };
+
+    metrics.repository(repos.contains_key(dir));
+    if !repos.contains_key(dir) {
+        match gix::discover(dir) {
+            Ok(repo) => {
+                if repos.len() >= MAX_REPOSITORIES
+                    && let Some(evicted) = repos.keys().next().cloned()
+                {
+                    repos.remove(&evicted);
+                }
+                repos.insert(dir.to_string(), repo);
+            }
+            Err(e) => return ("404 Not Found", JSON, error_body(&e.to_string())),
+        }
+    }
+
+    let start = Instant::now();
+    let result = resolve::resolve_detailed(&repos[dir], remote, options);
+    metrics.resolution(
+        start.elapsed(),
+        match &result {
+            Ok(resolved) => Ok(resolved.provenance),
+            Err(e) => Err(e
+                .downcast_ref::<resolve::Failure>()
+                .map_or("error", resolve::Failure::kind)),
+        },
+    );
+    match result {
+        Ok(resolved) => (
+            "200 OK",
+            JSON,
+            format!(
+                "{{\"dir\":{},\"remote\":{},\"branch\":{},\"provenance\":{}}}",
+                json::quote(dir),
+                json::quote(remote),
+                json::quote(&resolved.branch),
+                json::quote(resolved.provenance)
+            ),
+        ),
+        Err(e) => {
+            repos.remove(dir);
+            ("422 Unprocessable Entity", JSON, error_body(&e.to_string()))
+        }
+    }
+}
+
+/// Whether requests can be answered: resolution runs git for anything beyond reading refs.
+fn ready() -> Result<(), String> {
+    match Command::new("git").arg("--version").output() {
+        Ok(output) if output.status.success() => Ok(()),
+        Ok(output) => Err(format!("git --version failed: {}", output.status)),
+        Err(e) => Err(format!("Cannot run git: {}", e)),
+    }
+}
+
+fn error_body(message: &str) -> String {
+    format!("{{\"error\":{}}}", json::quote(message))
+}
+
+fn parse_query(query: &str) -> HashMap<String, String> {
+    query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(key), percent_decode(value))
+        })
+        .collect()
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            b => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'+' => out.push(b' '),
+            b'%' if i + 2 < bytes.len() => {
+                let decoded = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+                match decoded {
+                    Some(b) => {
+                        out.push(b);
+                        i += 2;
+                    }
+                    None => out.push(b'%'),
+                }
+            }
+            b => out.push(b),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+    use std::net::TcpStream;
+    use std::thread;
+
+    #[test]
+    fn test_parse_query() {
+        let params = parse_query("dir=%2Ftmp%2Fa+b&remote=upstream&flag");
+        assert_eq!(params["dir"], "/tmp/a b");
+        assert_eq!(params["remote"], "upstream");
+        assert_eq!(params["flag"], "");
+        assert_eq!(percent_decode("100%"), "100%");
+        assert_eq!(parse_mode("660"), Ok(0o660));
+        assert!(parse_mode("800").is_err());
+        assert!(parse_mode("1777").is_err());
+        assert_eq!(
+            percent_decode(&percent_encode("/a b/100%&c=d")),
+            "/a b/100%&c=d"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_listen_fd() {
+        let pid = Some(std::process::id().to_string());
+        assert_eq!(listen_fd(pid.clone(), Some("1".to_string())), Some(3));
+        assert_eq!(listen_fd(pid, Some("0".to_string())), None);
+        assert_eq!(
+            listen_fd(Some("1".to_string()), Some("1".to_string())),
+            None
+        );
+        assert_eq!(listen_fd(None, None), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_socket_query() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        std::fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "main");
+        commit(&repo_dir, "initial");
+
+        let socket = tmp.path().join("daemon.sock");
+        assert_eq!(query(&socket, &repo_dir, "origin"), None);
+        std::fs::write(&socket, "not a socket").unwrap();
+        let error = bind_unix(&socket, 0o600).unwrap_err().to_string();
+        assert!(
+            error.ends_with("path exists and is not a socket"),
+            "{}",
+            error
+        );
+        assert_eq!(std::fs::read_to_string(&socket).unwrap(), "not a socket");
+        std::fs::remove_file(&socket).unwrap();
+
+        let listener = bind_unix(&socket, 0o600).unwrap();
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        thread::spawn(move || serve(listener.incoming(), "origin", &options()));
+        assert_eq!(query(&socket, &repo_dir, "origin").as_deref(), Some("main"));
+        assert_eq!(query(&socket, &tmp.path().join("missing"), "origin"), None);
+    }
+
+    #[test]
+    fn test_serve_resolve() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        commit(tmp.path(), "initial");
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve(listener.incoming(), "origin", &options()));
+
+        let get = |target: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let dir = tmp.path().to_str().unwrap();
+        for _ in 0..2 {
+            let response = get(&format!("/resolve?dir={}", dir));
+            assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+            assert!(response.ends_with(&format!(
+                "{{\"dir\":{},\"remote\":\"origin\",\"branch\":\"main\",\"provenance\":\"local-names\"}}",
+                json::quote(dir)
+            )));
+        }
+
+        assert!(get("/unknown").starts_with("HTTP/1.1 404 Not Found\r\n"));
+        // A request line that never ends is refused once the limit has been read.
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut request = b"GET /".to_vec();
+        request.resize(MAX_REQUEST_BYTES as usize, b'a');
+        stream.write_all(&request).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 431 Request Header Fields Too Large\r\n"));
+
+        assert!(get("/healthz").ends_with("{\"status\":\"ok\"}"));
+        assert!(get("/readyz").starts_with("HTTP/1.1 200 OK\r\n"));
+        let args = ServeArgs {
+            listen: addr.to_string(),
+            unix_socket: None,
+            socket_mode: 0o600,
+            health_check: true,
+        };
+        health_check(&args).unwrap();
+
+        let metrics = get("/metrics");
+        assert!(metrics.starts_with("HTTP/1.1 200 OK\r\nContent-Type: text/plain"));
+        for line in [
+            "git_default_branch_requests_total{path=\"/resolve\",status=\"200\"} 2",
+            "git_default_branch_requests_total{path=\"other\",status=\"404\"} 1",
+            "git_default_branch_requests_total{path=\"other\",status=\"431\"} 1",
+            "git_default_branch_repository_cache_hits_total 1",
+            "git_default_branch_resolutions_total{strategy=\"local-names\"} 2",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "missing {}", line);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use std::path::Path;
+
+#[derive(clap::Args)]
+pub struct ShellArgs {
+    /// Shell to print functions for, e.g. `eval "$(git-default-branch shell-init zsh)"`
+    #[arg(value_enum)]
+    shell: Shell,
+
+    /// Work against the release branch the current branch is built on, as `pr-base` picks it,
+    /// rather than always the default branch
+    #[arg(long)]
+    nearest: bool,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+/// Quoting for `--shell-quote`.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum Quote {
+    /// Single quotes for sh, bash, zsh and other POSIX shells
+    Posix,
+    /// Single quotes for PowerShell
+    Powershell,
+}
+
+/// Quotes `value` so the shell reads it back as one word, whatever it contains.
+pub fn quote(value: &str, style: Quote) -> String {
+    match style {
+        Quote::Posix => format!("'{}'", value.replace('\'', "'\\''")),
+        // PowerShell also ends single-quoted strings at typographic single quotes, and reads a
+        // doubled one as a literal.
+        Quote::Powershell => {
+            let mut quoted = String::from("'");
+            for c in value.chars() {
+                if matches!(c, '\'' | '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}') {
+                    quoted.push(c);
+                }
+                quoted.push(c);
+            }
+            quoted.push('\'');
+            quoted
+        }
+    }
+}
+
+/// A function to define. In the bodies, `branch` holds the default branch, or the nearest release
+/// branch with `--nearest`.
+struct Function {
+    name: &'static str,
+    description: &'static str,
+    sh: &'static str,
+    fish: &'static str,
+}
+
+const FUNCTIONS: &[Function] = &[
+    Function {
+        name: "gdb-switch",
+        description: "Switch to the default branch",
+        sh: r#"git switch "$branch" "$@""#,
+        fish: "git switch $branch $argv",
+    },
+    Function {
+        name: "gdb-rebase",
+        description: "Rebase the current branch onto the default branch",
+        sh: r#"git rebase "$branch" "$@""#,
+        fish: "git rebase $branch $argv",
+    },
+    Function {
+        name: "gdb-diff",
+        description: "Show changes since the current branch forked from the default branch",
+        sh: r#"git diff "$branch...HEAD" "$@""#,
+        fish: "git diff $branch...HEAD $argv",
+    },
+    Function {
+        name: "gdb-log",
+        description: "Show commits on the current branch that the default branch lacks",
+        sh: r#"git log "$branch..HEAD" "$@""#,
+        fish: "git log $branch..HEAD $argv",
+    },
+    Function {
+        name: "gdb-cleanup",
+        description: "Delete local branches already merged into the default branch",
+        sh: r#"git branch --merged "$branch" --format='%(refname:short)' | grep -vxF -e "$branch" -e "$(git branch --show-current)" | xargs -r git branch -d"#,
+        fish: "git branch --merged $branch --format='%(refname:short)' | string match -v -- $branch | string match -v -- (git branch --show-current) | xargs -r git branch -d",
+    },
+];
+
+pub fn run(args: &ShellArgs) -> Result<(), Box<dyn std::error::Error>> {
+    print!(
+        "{}",
+        functions(args.shell, &std::env::current_exe()?, args.nearest)
+    );
+    Ok(())
+}
+
+/// Returns the shell source defining [`FUNCTIONS`] in terms of the binary at `exe`, asking it
+/// for `pr-base` with `nearest`.
+pub fn functions(shell: Shell, exe: &Path, nearest: bool) -> String {
+    let exe = quote(&exe.display().to_string(), Quote::Posix);
+    let exe = match nearest {
+        true => format!("{} pr-base", exe),
+        false => exe,
+    };
+    FUNCTIONS
+        .iter()
+        .map(|f| match shell {
+            Shell::Fish => format!(
+                "function {} --description '{}'\n    set -l branch ({}); or return\n    {}\nend\n",
+                f.name, f.description, exe, f.fish
+            ),
+            Shell::Bash | Shell::Zsh => format!(
+                "# {}\n{}() {{\n    local branch\n    branch=$({}) || return\n    {}\n}}\n",
+                f.description, f.name, exe, f.sh
+            ),
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn test_quote() {
+        assert_eq!(quote("main", Quote::Posix), "'main'");
+        assert_eq!(quote("it's", Quote::Posix), "'it'\\''s'");
+        assert_eq!(quote("$(rm -rf ~)", Quote::Posix), "'$(rm -rf ~)'");
+        assert_eq!(quote("it's", Quote::Powershell), "'it''s'");
+        assert_eq!(
+            quote("a\u{2019}b", Quote::Powershell),
+            "'a\u{2019}\u{2019}b'"
+        );
+
+        let name = "x'; touch pwned; echo '$HOME`id`";
+        let output = Command::new("sh")
+            .arg("-c")
+            .arg(format!("printf %s {}", quote(name, Quote::Posix)))
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout), name);
+    }
+
+    #[test]
+    fn test_functions() {
+        let exe = Path::new("/opt/it's/git-default-branch");
+        let bash = functions(Shell::Bash, exe, false);
+        assert!(bash.contains(
+            "gdb-switch() {\n    local branch\n    branch=$('/opt/it'\\''s/git-default-branch') || return\n    git switch \"$branch\" \"$@\"\n}\n"
+        ));
+        let fish = functions(Shell::Fish, exe, false);
+        assert!(fish.contains("function gdb-rebase --description 'Rebase the current branch onto the default branch'\n    set -l branch ('/opt/it'\\''s/git-default-branch'); or return\n    git rebase $branch $argv\nend\n"));
+
+        assert!(
+            functions(Shell::Zsh, exe, true)
+                .contains("branch=$('/opt/it'\\''s/git-default-branch' pr-base) || return")
+        );
+
+        // The output must at least parse where a shell is available.
+        if let Ok(output) = Command::new("bash").args(["-n", "-c", &bash]).output() {
+            assert!(
+                output.status.success(),
+                "{}",
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+    }
+}
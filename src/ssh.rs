@@ -0,0 +1,128 @@
+#[cfg(feature = "subprocess")]
+use std::path::Path;
+#[cfg(feature = "subprocess")]
+use std::process::Command;
+#[cfg(feature = "subprocess")]
+use std::sync::OnceLock;
+
+#[derive(clap::Args)]
+pub struct SshArgs {
+    /// How to check SSH remotes' host keys against known_hosts when asking them: strict refuses
+    /// unknown hosts, accept-new records them, off skips the check. The ssh command from
+    /// GIT_SSH_COMMAND or core.sshCommand is kept, with its own setting by default
+    /// [config: ssh_host_key_checking]
+    #[arg(
+        long,
+        value_enum,
+        value_name = "MODE",
+        env = "GIT_DEFAULT_BRANCH_SSH_HOST_KEY_CHECKING",
+        global = true
+    )]
+    pub ssh_host_key_checking: Option<HostKeyChecking>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum HostKeyChecking {
+    Strict,
+    AcceptNew,
+    Off,
+}
+
+impl HostKeyChecking {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "strict" => Some(HostKeyChecking::Strict),
+            "accept-new" => Some(HostKeyChecking::AcceptNew),
+            "off" => Some(HostKeyChecking::Off),
+            _ => None,
+        }
+    }
+
+    /// The value of ssh's `StrictHostKeyChecking` option.
+    #[cfg(feature = "subprocess")]
+    fn option(self) -> &'static str {
+        match self {
+            HostKeyChecking::Strict => "yes",
+            HostKeyChecking::AcceptNew => "accept-new",
+            HostKeyChecking::Off => "no",
+        }
+    }
+}
+
+/// The host key checking asked for, set once at startup.
+#[cfg(feature = "subprocess")]
+static CHECKING: OnceLock<Option<HostKeyChecking>> = OnceLock::new();
+
+/// Checks host keys as `checking` says from now on, if it is given.
+#[cfg(feature = "subprocess")]
+pub fn init(checking: Option<HostKeyChecking>) {
+    if checking == Some(HostKeyChecking::Off) {
+        log::warn!("SSH host keys are not checked, so the answers may come from anyone");
+    }
+    let _ = CHECKING.set(checking);
+}
+
+/// Makes git, run as `command` in `dir`, check host keys as configured, by appending the option
+/// to the ssh command it would use anyway.
+#[cfg(feature = "subprocess")]
+pub fn configure(command: &mut Command, dir: &Path) {
+    let Some(checking) = CHECKING.get().copied().flatten() else {
+        return;
+    };
+    command.env(
+        "GIT_SSH_COMMAND",
+        format!(
+            "{} -o StrictHostKeyChecking={}",
+            ssh_command(dir),
+            checking.option()
+        ),
+    );
+}
+
+/// The ssh command git would run in `dir`: `GIT_SSH_COMMAND`, `core.sshCommand`, `GIT_SSH` or
+/// plain `ssh`, in git's order of precedence.
+#[cfg(feature = "subprocess")]
+fn ssh_command(dir: &Path) -> String {
+    if let Ok(command) = std::env::var("GIT_SSH_COMMAND")
+        && !command.is_empty()
+    {
+        return command;
+    }
+    let configured = Command::new("git")
+        .args(["config", "--get", "core.sshCommand"])
+        .current_dir(dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_string())
+        .filter(|command| !command.is_empty());
+    if let Some(command) = configured {
+        return command;
+    }
+    match std::env::var("GIT_SSH") {
+        // `GIT_SSH` names a program rather than a shell command.
+        Ok(program) if !program.is_empty() => format!("'{}'", program.replace('\'', "'\\''")),
+        _ => "ssh".to_string(),
+    }
+}
+
+#[cfg(all(test, feature = "subprocess"))]
+mod tests {
+    use super::*;
+    use crate::testutil::init_repo;
+
+    #[test]
+    fn test_ssh_command() {
+        if std::env::var_os("GIT_SSH_COMMAND").is_some() {
+            return;
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        Command::new("git")
+            .args(["config", "core.sshCommand", "ssh -i ~/.ssh/work"])
+            .current_dir(tmp.path())
+            .output()
+            .unwrap();
+        assert_eq!(ssh_command(tmp.path()), "ssh -i ~/.ssh/work");
+    }
+}
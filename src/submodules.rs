@@ -0,0 +1,141 @@
+use crate::batch::{self, BatchArgs};
+use crate::{git, json, resolve};
+use std::path::{Path, PathBuf};
+
+/// A submodule, found through the `.gitmodules` of the repository containing it.
+struct Submodule {
+    /// The path from the top-level repository, through any submodules containing it.
+    path: String,
+    dir: PathBuf,
+}
+
+/// Prints `path<TAB>remote<TAB>branch` for every submodule of the repository at `dir`, nested ones
+/// included, or a JSON object per line with `json`. Failures, such as a submodule that is not
+/// checked out, are reported on stderr without stopping.
+pub fn run(
+    dir: &str,
+    remote: &str,
+    options: &resolve::Options,
+    batch: &BatchArgs,
+    json: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut submodules = Vec::new();
+    find(&git::root(dir)?, "", &mut submodules);
+    let results = batch::map_parallel(&submodules, batch.jobs, |submodule| {
+        if !submodule.dir.join(".git").exists() {
+            return Err("not checked out; run git submodule update --init".to_string());
+        }
+        let remote = resolve::primary_remote(&submodule.dir, remote, options);
+        resolve::run(&submodule.dir, &remote, options)
+            .map(|branch| (remote, branch))
+            .map_err(|e| e.to_string())
+    });
+    for (submodule, result) in submodules.iter().zip(&results) {
+        match result {
+            Ok((remote, branch)) if json => println!(
+                "{{\"path\":{},\"remote\":{},\"branch\":{}}}",
+                json::quote(&submodule.path),
+                json::quote(remote),
+                json::quote(branch)
+            ),
+            Ok((remote, branch)) => println!("{}\t{}\t{}", submodule.path, remote, branch),
+            Err(e) => eprintln!("{}: {}", submodule.path, e),
+        }
+    }
+
+    let failures = results.iter().filter(|r| r.is_err()).count();
+    if failures > 0 {
+        return Err(format!("Failed to resolve {} submodules", failures).into());
+    }
+    Ok(())
+}
+
+/// Adds the submodules listed in `root`'s `.gitmodules` to `submodules`, each followed by those
+/// nested in it when it is checked out.
+fn find(root: &Path, prefix: &str, submodules: &mut Vec<Submodule>) {
+    if !root.join(".gitmodules").is_file() {
+        return;
+    }
+    let paths = git::git(
+        root,
+        &[
+            "config",
+            "--file",
+            ".gitmodules",
+            "--null",
+            "--get-regexp",
+            r"^submodule\..*\.path$",
+        ],
+    )
+    // git exits with 1 when nothing matches.
+    .unwrap_or_default();
+    for entry in paths.split('\0').filter(|entry| !entry.is_empty()) {
+        let Some((_, path)) = entry.split_once('\n') else {
+            continue;
+        };
+        let dir = root.join(path);
+        let submodule = Submodule {
+            path: format!("{}{}", prefix, path),
+            dir: dir.clone(),
+        };
+        let nested = format!("{}/", submodule.path);
+        submodules.push(submodule);
+        if dir.join(".git").exists() {
+            find(&dir, &nested, submodules);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+    use std::fs;
+    use std::process::Command;
+
+    #[test]
+    fn test_find() {
+        let tmp = tempfile::tempdir().unwrap();
+        let git = |dir: &Path, args: &[&str]| {
+            let output = Command::new("git")
+                .args(["-c", "protocol.file.allow=always"])
+                .args(args)
+                .current_dir(dir)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{:?}", output);
+        };
+        let inner = tmp.path().join("inner");
+        fs::create_dir(&inner).unwrap();
+        init_repo(&inner, "main");
+        commit(&inner, "inner");
+        let lib = tmp.path().join("lib");
+        fs::create_dir(&lib).unwrap();
+        init_repo(&lib, "trunk");
+        git(
+            &lib,
+            &["submodule", "add", inner.to_str().unwrap(), "deps/inner"],
+        );
+        git(&lib, &["commit", "-m", "add inner"]);
+        let top = tmp.path().join("top");
+        fs::create_dir(&top).unwrap();
+        init_repo(&top, "main");
+        git(
+            &top,
+            &["submodule", "add", lib.to_str().unwrap(), "vendor/lib"],
+        );
+        git(&top, &["submodule", "update", "--init", "--recursive"]);
+
+        let mut submodules = Vec::new();
+        find(&top, "", &mut submodules);
+        let paths = submodules
+            .iter()
+            .map(|s| s.path.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["vendor/lib", "vendor/lib/deps/inner"]);
+        assert_eq!(
+            resolve::run(&submodules[0].dir, "origin", &options()).unwrap(),
+            "trunk"
+        );
+    }
+}
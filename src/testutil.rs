@@ -0,0 +1,46 @@
+use crate::resolve;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Resolution options for tests, which must not touch the user's cache.
+pub fn options() -> resolve::Options {
+    resolve::Options {
+        no_cache: true,
+        #[cfg(feature = "network")]
+        no_daemon: true,
+        ..resolve::Options::default()
+    }
+}
+
+pub fn init_repo(dir: &Path, branch: &str) {
+    Command::new("git")
+        .args(["init", "--initial-branch", branch])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    Command::new("git")
+        .args(["config", "user.name", "Test"])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    Command::new("git")
+        .args(["config", "user.email", "test@example.com"])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+}
+
+pub fn commit(dir: &Path, msg: &str) {
+    fs::write(dir.join("test.txt"), msg).unwrap();
+    Command::new("git")
+        .args(["add", "."])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+    Command::new("git")
+        .args(["commit", "-m", msg])
+        .current_dir(dir)
+        .output()
+        .unwrap();
+}
@@ -0,0 +1,309 @@
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+#[derive(clap::Args)]
+pub struct TlsArgs {
+    /// Verify HTTPS servers against the CA certificates in this file, such as a TLS-intercepting
+    /// proxy's, for both the remote and forge APIs; git's http.sslCAInfo is used otherwise
+    /// [config: ca_cert]
+    #[arg(
+        long,
+        value_name = "PATH",
+        env = "GIT_DEFAULT_BRANCH_CA_CERT",
+        global = true
+    )]
+    pub ca_cert: Option<PathBuf>,
+
+    /// Do not verify HTTPS servers' certificates at all, when asking the remote or forge APIs,
+    /// like curl's and git's own; anyone on the network path can then answer instead. Only for
+    /// lab networks with self-signed certificates
+    #[arg(long, global = true)]
+    pub insecure: bool,
+}
+
+/// How servers are verified, set once at startup.
+struct Settings {
+    ca_cert: Option<PathBuf>,
+    insecure: bool,
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Verifies servers against `ca_cert` from now on, if one is given, or not at all when
+/// `insecure`, which is warned about.
+pub fn init(ca_cert: Option<PathBuf>, insecure: bool) {
+    if insecure {
+        log::warn!(
+            "--insecure: HTTPS certificates are not verified, so the answers may come from anyone \
+             on the network path"
+        );
+    }
+    let _ = SETTINGS.set(Settings { ca_cert, insecure });
+}
+
+fn ca_cert() -> Option<&'static Path> {
+    SETTINGS
+        .get()
+        .and_then(|settings| settings.ca_cert.as_deref())
+}
+
+fn insecure() -> bool {
+    SETTINGS.get().is_some_and(|settings| settings.insecure)
+}
+
+/// `-c` options making git use the configured CA bundle over its own, or skip verification.
+#[cfg(feature = "subprocess")]
+pub fn git_args() -> Vec<OsString> {
+    let mut args = vec![];
+    if let Some(ca_cert) = ca_cert() {
+        let mut setting = OsString::from("http.sslCAInfo=");
+        setting.push(ca_cert);
+        args.extend(["-c".into(), setting]);
+    }
+    if insecure() {
+        args.extend(["-c".into(), "http.sslVerify=false".into()]);
+    }
+    args
+}
+
+/// Options making curl treat `url` as git would with `http`: verifying it against the configured
+/// CA bundle, `GIT_SSL_CAINFO` or `http.sslCAInfo`, and not at all when `GIT_SSL_NO_VERIFY` is
+/// set, `http.sslVerify` is off or `--insecure` is given; and presenting the client certificate of
+/// `GIT_SSL_CERT` or `http.sslCert`, with its key, to servers that require one.
+#[cfg(feature = "forge")]
+pub fn curl_args(url: &str, http: &HttpConfig) -> Vec<OsString> {
+    let git = http.settings(url);
+    let mut args = vec![];
+    if let Some(ca_cert) = ca_cert().map(Path::to_path_buf).or(git.ca_info) {
+        args.push("--cacert".into());
+        args.push(ca_cert.into());
+    }
+    if insecure() {
+        args.push("--insecure".into());
+    } else if !git.verify {
+        log::debug!("not verifying the server's certificate since http.sslVerify is off");
+        args.push("--insecure".into());
+    }
+    if let Some(cert) = git.cert {
+        args.push("--cert".into());
+        args.push(cert.into());
+    }
+    if let Some(key) = git.key {
+        args.push("--key".into());
+        args.push(key.into());
+    }
+    args
+}
+
+/// The `http.*` TLS keys of git's configuration, read once from a repository's configuration, or
+/// the global and system files without one, and applied to each URL as `git config
+/// --get-urlmatch` would.
+#[cfg(feature = "forge")]
+#[derive(Clone, Debug, Default)]
+pub struct HttpConfig {
+    /// Each value in configuration order, with the URL of its `http "<url>"` section if any.
+    values: Vec<(Option<String>, &'static str, Option<String>)>,
+}
+
+/// The keys [`HttpConfig`] keeps.
+#[cfg(feature = "forge")]
+const HTTP_KEYS: [&str; 4] = ["sslCAInfo", "sslVerify", "sslCert", "sslKey"];
+
+#[cfg(feature = "forge")]
+impl HttpConfig {
+    /// Reads the configuration `repo` sees, or the global and system files without a repository.
+    pub fn load(repo: Option<&gix::Repository>) -> Self {
+        match repo {
+            Some(repo) => HttpConfig::from_file(repo.config_snapshot().plumbing()),
+            None => gix::config::File::from_globals()
+                .map(|file| HttpConfig::from_file(&file))
+                .unwrap_or_default(),
+        }
+    }
+
+    fn from_file(file: &gix::config::File<'_>) -> Self {
+        let mut values = Vec::new();
+        for section in file.sections_by_name("http").into_iter().flatten() {
+            let pattern = section
+                .header()
+                .subsection_name()
+                .map(|name| name.to_string());
+            for key in HTTP_KEYS {
+                if let Some(value) = section.value_implicit(key) {
+                    values.push((pattern.clone(), key, value.map(|v| v.to_string())));
+                }
+            }
+        }
+        HttpConfig { values }
+    }
+
+    /// The value of `http.<key>` for `url`: the one in the section matching it most closely, the
+    /// last one among equals. `Some(None)` is a key given without a value.
+    fn get(&self, url: &str, key: &str) -> Option<Option<&str>> {
+        let url = gix::url::parse(url.into()).ok();
+        let mut best = None;
+        for (pattern, name, value) in &self.values {
+            if !name.eq_ignore_ascii_case(key) {
+                continue;
+            }
+            let specificity = match pattern {
+                None => Some((0, false, 0, false)),
+                Some(pattern) => url.as_ref().and_then(|url| url_match(pattern, url)),
+            };
+            if let Some(specificity) = specificity
+                && best.as_ref().is_none_or(|(best, _)| specificity >= *best)
+            {
+                best = Some((specificity, value.as_deref()));
+            }
+        }
+        best.map(|(_, value)| value)
+    }
+
+    /// The settings for `url`. The `GIT_SSL_*` variables take precedence, as in git.
+    fn settings(&self, url: &str) -> Git {
+        let path = |env: &str, key: &str| {
+            std::env::var_os(env)
+                .filter(|path| !path.is_empty())
+                .map(PathBuf::from)
+                .or_else(|| {
+                    self.get(url, key)
+                        .flatten()
+                        .filter(|path| !path.is_empty())
+                        .map(crate::glob::expand_home)
+                })
+        };
+        let verify = match self.get(url, "sslVerify") {
+            Some(Some(value)) => !matches!(
+                value.to_ascii_lowercase().as_str(),
+                "false" | "no" | "off" | "0" | ""
+            ),
+            _ => true,
+        };
+        Git {
+            ca_info: path("GIT_SSL_CAINFO", "sslCAInfo"),
+            verify: std::env::var_os("GIT_SSL_NO_VERIFY").is_none() && verify,
+            cert: path("GIT_SSL_CERT", "sslCert"),
+            key: path("GIT_SSL_KEY", "sslKey"),
+        }
+    }
+}
+
+/// How closely the URL of an `http "<pattern>"` section matches `url` by git's rules, as the
+/// length of the matched host, whether it matched without wildcards, the length of the matched
+/// path and whether a user matched, in order of importance; `None` when it does not match.
+#[cfg(feature = "forge")]
+fn url_match(pattern: &str, url: &gix::Url) -> Option<(usize, bool, usize, bool)> {
+    let pattern = gix::url::parse(pattern.into()).ok()?;
+    if pattern.scheme != url.scheme || pattern.port_or_default() != url.port_or_default() {
+        return None;
+    }
+    let user = match pattern.user() {
+        Some(user) if url.user() != Some(user) => return None,
+        user => user.is_some(),
+    };
+    let (host, wanted) = (pattern.host()?, url.host()?);
+    let labels = host.split('.').collect::<Vec<_>>();
+    let wanted = wanted.split('.').collect::<Vec<_>>();
+    if labels.len() != wanted.len()
+        || !labels
+            .iter()
+            .zip(&wanted)
+            .all(|(label, wanted)| *label == "*" || label.eq_ignore_ascii_case(wanted))
+    {
+        return None;
+    }
+    let exact_host = !labels.contains(&"*");
+    let prefix = pattern.path.to_string();
+    let prefix = prefix.trim_end_matches('/');
+    let path = url.path.to_string();
+    let rest = path.strip_prefix(prefix)?;
+    if !(rest.is_empty() || rest.starts_with('/')) {
+        return None;
+    }
+    Some((host.len(), exact_host, prefix.len(), user))
+}
+
+/// git's TLS settings for a URL.
+#[cfg(feature = "forge")]
+struct Git {
+    ca_info: Option<PathBuf>,
+    verify: bool,
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+}
+
+#[cfg(all(test, feature = "forge"))]
+mod tests {
+    use super::*;
+    use crate::testutil::init_repo;
+    use std::process::Command;
+
+    #[test]
+    fn test_http_config() {
+        let tmp = tempfile::tempdir().unwrap();
+        init_repo(tmp.path(), "main");
+        let git = |args: &[&str]| {
+            Command::new("git")
+                .args(args)
+                .current_dir(tmp.path())
+                .output()
+                .unwrap();
+        };
+        git(&["config", "http.sslCAInfo", "/etc/corp/ca.pem"]);
+        git(&["config", "http.sslVerify", "no"]);
+        git(&[
+            "config",
+            "http.https://git.corp.example.sslCert",
+            "/etc/corp/client.pem",
+        ]);
+        git(&[
+            "config",
+            "http.https://git.corp.example.sslKey",
+            "/etc/corp/client.key",
+        ]);
+        git(&["config", "http.https://*.corp.example/ci.sslVerify", "true"]);
+
+        // The repository's own settings apply, wherever the process runs.
+        let repo = gix::open(tmp.path()).unwrap();
+        let http = HttpConfig::load(Some(&repo));
+        let settings = http.settings("https://git.corp.example/api/v4/projects");
+        if std::env::var_os("GIT_SSL_CAINFO").is_none() {
+            assert_eq!(settings.ca_info, Some(PathBuf::from("/etc/corp/ca.pem")));
+        }
+        assert!(!settings.verify);
+        if std::env::var_os("GIT_SSL_CERT").is_none() && std::env::var_os("GIT_SSL_KEY").is_none() {
+            assert_eq!(settings.cert, Some(PathBuf::from("/etc/corp/client.pem")));
+            assert_eq!(settings.key, Some(PathBuf::from("/etc/corp/client.key")));
+            let other = http.settings("https://api.github.com/repos/a/b");
+            assert_eq!(other.cert, None);
+            assert_eq!(other.key, None);
+        }
+        if std::env::var_os("GIT_SSL_NO_VERIFY").is_none() {
+            assert!(http.settings("https://ci.corp.example/ci/jobs").verify);
+            assert!(!http.settings("https://ci.corp.example/cix").verify);
+        }
+    }
+
+    #[test]
+    fn test_url_match() {
+        let url = gix::url::parse("https://user@git.corp.example/group/repo".into()).unwrap();
+        assert_eq!(
+            url_match("https://git.corp.example", &url),
+            Some((16, true, 0, false))
+        );
+        assert_eq!(
+            url_match("https://*.corp.example/group/", &url),
+            Some((14, false, 6, false))
+        );
+        assert!(
+            url_match("https://user@git.corp.example/group", &url)
+                .unwrap()
+                .3
+        );
+        assert_eq!(url_match("https://other@git.corp.example", &url), None);
+        assert_eq!(url_match("https://git.corp.example/gr", &url), None);
+        assert_eq!(url_match("http://git.corp.example", &url), None);
+        assert_eq!(url_match("https://*.example", &url), None);
+    }
+}
@@ -0,0 +1,401 @@
+/// A parsed TOML value. This covers the subset configuration files use: strings, integers,
+/// booleans, arrays and tables, but not floats, dates or arrays of tables.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    String(String),
+    Integer(i64),
+    Bool(bool),
+    Array(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
+
+impl Value {
+    pub fn entries(&self) -> Option<&[(String, Value)]> {
+        match self {
+            Value::Table(entries) => Some(entries),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&[Value]> {
+        match self {
+            Value::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    /// Returns the table at `path`, creating it and any missing parents.
+    fn table_mut(&mut self, path: &[String]) -> Result<&mut Vec<(String, Value)>, String> {
+        let Value::Table(entries) = self else {
+            return Err("not a table".to_string());
+        };
+        let Some((first, rest)) = path.split_first() else {
+            return Ok(entries);
+        };
+        let index = match entries.iter().position(|(k, _)| k == first) {
+            Some(index) => index,
+            None => {
+                entries.push((first.clone(), Value::Table(Vec::new())));
+                entries.len() - 1
+            }
+        };
+        entries[index]
+            .1
+            .table_mut(rest)
+            .map_err(|_| format!("{} is not a table", first))
+    }
+}
+
+/// Parses a document into its root table.
+pub fn parse(input: &str) -> Result<Value, String> {
+    let mut parser = Parser {
+        input: input.as_bytes(),
+        pos: 0,
+        line: 1,
+    };
+    let mut root = Value::Table(Vec::new());
+    let mut current = Vec::new();
+    loop {
+        parser.skip_blank_lines();
+        match parser.peek() {
+            None => return Ok(root),
+            Some(b'[') => {
+                parser.pos += 1;
+                if parser.peek() == Some(b'[') {
+                    return Err(parser.error("arrays of tables are not supported"));
+                }
+                current = parser.key_path()?;
+                parser.expect(b']')?;
+                root.table_mut(&current).map_err(|e| parser.error(&e))?;
+            }
+            Some(_) => {
+                let mut path = parser.key_path()?;
+                parser.expect(b'=')?;
+                let value = parser.value()?;
+                let key = path.pop().unwrap();
+                path.splice(0..0, current.iter().cloned());
+                let table = root.table_mut(&path).map_err(|e| parser.error(&e))?;
+                if table.iter().any(|(k, _)| *k == key) {
+                    return Err(parser.error(&format!("duplicate key {}", key)));
+                }
+                table.push((key, value));
+            }
+        }
+        parser.end_of_line()?;
+    }
+}
+
+struct Parser<'a> {
+    input: &'a [u8],
+    pos: usize,
+    line: usize,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid TOML on line {}: {}", self.line, message)
+    }
+
+    /// Skips spaces, tabs and a trailing comment, but not the end of the line.
+    fn skip_whitespace(&mut self) {
+        while let Some(&b) = self.input.get(self.pos) {
+            match b {
+                b' ' | b'\t' => self.pos += 1,
+                b'#' => {
+                    while self.input.get(self.pos).is_some_and(|&b| b != b'\n') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn skip_blank_lines(&mut self) {
+        loop {
+            self.skip_whitespace();
+            match self.input.get(self.pos) {
+                Some(b'\n') => {
+                    self.pos += 1;
+                    self.line += 1;
+                }
+                Some(b'\r') => self.pos += 1,
+                _ => break,
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<u8> {
+        self.skip_whitespace();
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), String> {
+        if self.peek() != Some(byte) {
+            return Err(self.error(&format!("expected '{}'", byte as char)));
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        match self.peek() {
+            None | Some(b'\n') | Some(b'\r') => Ok(()),
+            Some(_) => Err(self.error("expected the end of the line")),
+        }
+    }
+
+    /// Parses a dotted key such as `a."b.c".d`.
+    fn key_path(&mut self) -> Result<Vec<String>, String> {
+        let mut path = vec![self.key()?];
+        while self.peek() == Some(b'.') {
+            self.pos += 1;
+            path.push(self.key()?);
+        }
+        Ok(path)
+    }
+
+    fn key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some(b'"') => self.basic_string(),
+            Some(b'\'') => self.literal_string(),
+            _ => {
+                let start = self.pos;
+                while self
+                    .input
+                    .get(self.pos)
+                    .is_some_and(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_'))
+                {
+                    self.pos += 1;
+                }
+                if start == self.pos {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(String::from_utf8_lossy(&self.input[start..self.pos]).into_owned())
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some(b'"') => Ok(Value::String(self.basic_string()?)),
+            Some(b'\'') => Ok(Value::String(self.literal_string()?)),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_blank_lines();
+                    if self.peek() == Some(b']') {
+                        break;
+                    }
+                    items.push(self.value()?);
+                    self.skip_blank_lines();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.skip_blank_lines();
+                self.expect(b']')?;
+                Ok(Value::Array(items))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut table = Value::Table(Vec::new());
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(table);
+                }
+                loop {
+                    let mut path = self.key_path()?;
+                    self.expect(b'=')?;
+                    let value = self.value()?;
+                    let key = path.pop().unwrap();
+                    let entries = table.table_mut(&path).map_err(|e| self.error(&e))?;
+                    if entries.iter().any(|(k, _)| *k == key) {
+                        return Err(self.error(&format!("duplicate key {}", key)));
+                    }
+                    entries.push((key, value));
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        _ => break,
+                    }
+                }
+                self.expect(b'}')?;
+                Ok(table)
+            }
+            Some(b't') if self.input[self.pos..].starts_with(b"true") => {
+                self.pos += 4;
+                Ok(Value::Bool(true))
+            }
+            Some(b'f') if self.input[self.pos..].starts_with(b"false") => {
+                self.pos += 5;
+                Ok(Value::Bool(false))
+            }
+            Some(b) if b.is_ascii_digit() || b == b'-' || b == b'+' => {
+                let start = self.pos;
+                self.pos += 1;
+                while self
+                    .input
+                    .get(self.pos)
+                    .is_some_and(|b| b.is_ascii_digit() || *b == b'_')
+                {
+                    self.pos += 1;
+                }
+                let digits = String::from_utf8_lossy(&self.input[start..self.pos]).replace('_', "");
+                digits
+                    .parse()
+                    .map(Value::Integer)
+                    .map_err(|_| self.error("invalid integer"))
+            }
+            _ => Err(self.error("unexpected token")),
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect(b'"')?;
+        let mut out = Vec::new();
+        loop {
+            let b = *self
+                .input
+                .get(self.pos)
+                .filter(|&&b| b != b'\n')
+                .ok_or_else(|| self.error("unterminated string"))?;
+            self.pos += 1;
+            match b {
+                b'"' => return String::from_utf8(out).map_err(|_| self.error("invalid UTF-8")),
+                b'\\' => {
+                    let escape = self.input.get(self.pos).copied();
+                    self.pos += 1;
+                    match escape {
+                        Some(b'"') => out.push(b'"'),
+                        Some(b'\\') => out.push(b'\\'),
+                        Some(b'n') => out.push(b'\n'),
+                        Some(b't') => out.push(b'\t'),
+                        Some(b'r') => out.push(b'\r'),
+                        Some(b'u') => {
+                            let hex = self
+                                .input
+                                .get(self.pos..self.pos + 4)
+                                .ok_or_else(|| self.error("invalid escape"))?;
+                            let c = u32::from_str_radix(&String::from_utf8_lossy(hex), 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or_else(|| self.error("invalid escape"))?;
+                            self.pos += 4;
+                            out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                b => out.push(b),
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect(b'\'')?;
+        let start = self.pos;
+        while self
+            .input
+            .get(self.pos)
+            .is_some_and(|&b| b != b'\'' && b != b'\n')
+        {
+            self.pos += 1;
+        }
+        if self.input.get(self.pos) != Some(&b'\'') {
+            return Err(self.error("unterminated string"));
+        }
+        self.pos += 1;
+        String::from_utf8(self.input[start..self.pos - 1].to_vec())
+            .map_err(|_| self.error("invalid UTF-8"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get<'a>(value: &'a Value, key: &str) -> Option<&'a Value> {
+        value
+            .entries()?
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v)
+    }
+
+    #[test]
+    fn test_parse() {
+        let doc = parse(
+            r#"
+# Defaults
+remotes = ["upstream", 'origin'] # priority
+cache_ttl = 3_600
+
+[hosts]
+"git.example.com" = { forge = "github", enabled = true }
+
+[a.b]
+c = [
+  1,
+  -2,
+]
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            get(&doc, "remotes").and_then(Value::as_array),
+            Some(
+                &[
+                    Value::String("upstream".to_string()),
+                    Value::String("origin".to_string())
+                ][..]
+            )
+        );
+        assert_eq!(
+            get(&doc, "cache_ttl").and_then(Value::as_integer),
+            Some(3600)
+        );
+        let host = get(&doc, "hosts").and_then(|h| get(h, "git.example.com"));
+        assert_eq!(
+            host.and_then(|h| get(h, "forge")).and_then(Value::as_str),
+            Some("github")
+        );
+        assert_eq!(
+            host.and_then(|h| get(h, "enabled")),
+            Some(&Value::Bool(true))
+        );
+        assert_eq!(
+            get(&doc, "a")
+                .and_then(|a| get(a, "b"))
+                .and_then(|b| get(b, "c")),
+            Some(&Value::Array(vec![Value::Integer(1), Value::Integer(-2)]))
+        );
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(parse("a = 1\na = 2").is_err());
+        assert!(parse("a = \"unterminated").is_err());
+        assert!(parse("a = 1 b = 2").is_err());
+        assert!(parse("[[servers]]").is_err());
+        assert_eq!(
+            parse("x = 1\ny = ?").unwrap_err(),
+            "Invalid TOML on line 2: unexpected token"
+        );
+    }
+}
@@ -0,0 +1,384 @@
+//! `scan --tui`: the repositories a scan finds in an interactive table, with their default
+//! branch, their remote HEAD and how long ago they fetched, and keys to refresh them, fix their
+//! remote HEAD or open a shell in one.
+//!
+//! Work on repositories may ask for credentials or print warnings, so it runs with the terminal
+//! restored and the table is drawn again afterwards.
+
+use crate::resolve::{self, Options, Resolved, Source, Strategy};
+use crate::{batch, git};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Paragraph, Row, Table, TableState};
+use ratatui::{DefaultTerminal, Frame};
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+const KEYS: &str = "↑↓ select  r refresh  R refresh all  f fix remote HEAD  o open shell  q quit";
+
+/// What is known about one repository.
+struct Repo {
+    path: PathBuf,
+    /// The default branch, or why it could not be resolved.
+    result: Result<Resolved, String>,
+    /// The remote it is the default branch of.
+    remote: String,
+    /// The branch the remote HEAD points to and whether that branch has been fetched.
+    head: Option<(String, bool)>,
+    /// How long ago the repository last fetched, if it ever did.
+    fetched: Option<Duration>,
+}
+
+impl Repo {
+    fn load(path: &Path, remote: &str, options: &Options) -> Self {
+        let primary = resolve::primary_remote(path, remote, options);
+        Repo {
+            path: path.to_path_buf(),
+            result: resolve::run_detailed(path, remote, options).map_err(|e| e.to_string()),
+            head: remote_head(path, &primary),
+            fetched: last_fetch(path),
+            remote: primary,
+        }
+    }
+
+    /// What is wrong, if anything, with the color to show it in.
+    fn status(&self) -> (String, Color) {
+        let branch = match &self.result {
+            Ok(resolved) => &resolved.branch,
+            Err(error) => return (error.clone(), Color::Red),
+        };
+        match &self.head {
+            None => (format!("no {}/HEAD", self.remote), Color::Yellow),
+            Some((_, false)) => (format!("{}/HEAD is stale", self.remote), Color::Yellow),
+            Some((head, true)) if head != branch => {
+                (format!("{}/HEAD is {}", self.remote, head), Color::Yellow)
+            }
+            Some(_) => ("ok".to_string(), Color::Reset),
+        }
+    }
+
+    fn row(&self) -> Row<'_> {
+        let (status, color) = self.status();
+        Row::new([
+            self.path.display().to_string(),
+            self.result
+                .as_ref()
+                .map_or(String::new(), |resolved| resolved.branch.clone()),
+            self.head
+                .as_ref()
+                .map_or(String::new(), |(head, _)| head.clone()),
+            self.fetched.map_or("never".to_string(), age),
+            status,
+        ])
+        .style(Style::new().fg(color))
+    }
+}
+
+/// The branch the remote HEAD of the repository at `path` points to, and whether it exists.
+fn remote_head(path: &Path, remote: &str) -> Option<(String, bool)> {
+    let repo = git::discover(path).ok()?;
+    let branch = resolve::remote_head_branch(&repo, remote).ok()??;
+    let tracking = resolve::tracking_ref(&repo, remote, &branch);
+    let exists = repo.find_reference(tracking.as_str()).is_ok();
+    Some((branch, exists))
+}
+
+/// How long ago the repository at `path` last fetched, from when `FETCH_HEAD` was written.
+fn last_fetch(path: &Path) -> Option<Duration> {
+    let repo = git::discover(path).ok()?;
+    let modified = repo
+        .git_dir()
+        .join("FETCH_HEAD")
+        .metadata()
+        .ok()?
+        .modified()
+        .ok()?;
+    SystemTime::now().duration_since(modified).ok()
+}
+
+/// Writes `duration` in its largest whole unit, like `5m` or `3d`.
+fn age(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..60 => format!("{}s", seconds),
+        60..3600 => format!("{}m", seconds / 60),
+        3600..86400 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
+
+struct App<'a> {
+    paths: &'a [PathBuf],
+    remote: &'a str,
+    options: &'a Options,
+    jobs: usize,
+    repos: Vec<Repo>,
+    state: TableState,
+    /// What the last action did.
+    message: String,
+}
+
+impl App<'_> {
+    fn refresh_all(&mut self) {
+        eprintln!("Scanning {} repositories...", self.paths.len());
+        let (remote, options) = (self.remote, self.options);
+        self.repos = batch::map_parallel(self.paths, self.jobs, |path| {
+            Repo::load(path, remote, options)
+        });
+        self.message = format!("Scanned {} repositories", self.repos.len());
+    }
+
+    fn refresh(&mut self, index: usize) {
+        let path = self.repos[index].path.clone();
+        self.repos[index] = Repo::load(&path, self.remote, self.options);
+        self.message = format!("Refreshed {}", path.display());
+    }
+
+    /// Asks the remote for its HEAD and records it, as `--set-head` does.
+    fn fix(&mut self, index: usize) {
+        if self.options.dry_run {
+            self.message = "Not changing anything in a dry run".to_string();
+            return;
+        }
+        let path = self.repos[index].path.clone();
+        let mut options = Options {
+            set_head: true,
+            yes: true,
+            fallback: None,
+            strategies: vec![Strategy::SetHead],
+            ..self.options.clone()
+        };
+        options.set_source("strategies", Source::CommandLine);
+        let asked = resolve::run_detailed(&path, self.remote, &options);
+        self.refresh(index);
+        let repo = &self.repos[index];
+        self.message = match (asked, &repo.head) {
+            (Err(e), _) => format!("{}: {}", path.display(), e),
+            (Ok(resolved), Some((head, true))) if *head == resolved.branch => {
+                format!("{}: {}/HEAD is {}", path.display(), repo.remote, head)
+            }
+            (Ok(resolved), _) => format!(
+                "{}: the remote's HEAD is {}, which has not been fetched",
+                path.display(),
+                resolved.branch
+            ),
+        };
+    }
+
+    /// Runs `$SHELL` in the repository until it exits.
+    fn open(&mut self, index: usize) {
+        let path = &self.repos[index].path;
+        let shell = std::env::var_os("SHELL").unwrap_or_else(|| "sh".into());
+        eprintln!("Opening a shell in {}; exit it to return", path.display());
+        self.message = match Command::new(&shell).current_dir(path).status() {
+            Ok(_) => format!("Back from {}", path.display()),
+            Err(e) => format!("Cannot run {}: {}", shell.to_string_lossy(), e),
+        };
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [table, footer] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(2)]).areas(frame.area());
+        let header = Row::new(["PATH", "BRANCH", "REMOTE HEAD", "FETCHED", "STATUS"])
+            .style(Style::new().bold());
+        let widths = [
+            Constraint::Fill(3),
+            Constraint::Fill(1),
+            Constraint::Fill(1),
+            Constraint::Length(7),
+            Constraint::Fill(2),
+        ];
+        frame.render_stateful_widget(
+            Table::new(self.repos.iter().map(Repo::row), widths)
+                .header(header)
+                .row_highlight_style(Style::new().reversed()),
+            table,
+            &mut self.state,
+        );
+        frame.render_widget(
+            Paragraph::new(vec![
+                Line::from(self.message.as_str()),
+                Line::from(KEYS).dim(),
+            ]),
+            footer,
+        );
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<(), Box<dyn std::error::Error>> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let selected = self.state.selected().filter(|_| !self.repos.is_empty());
+            match (key.code, selected) {
+                (KeyCode::Char('q') | KeyCode::Esc, _) => return Ok(()),
+                (KeyCode::Down | KeyCode::Char('j'), _) => self.state.select_next(),
+                (KeyCode::Up | KeyCode::Char('k'), _) => self.state.select_previous(),
+                (KeyCode::Char('R'), _) => suspended(terminal, || self.refresh_all())?,
+                (KeyCode::Char('r'), Some(index)) => suspended(terminal, || self.refresh(index))?,
+                (KeyCode::Char('f'), Some(index)) => suspended(terminal, || self.fix(index))?,
+                (KeyCode::Char('o') | KeyCode::Enter, Some(index)) => {
+                    suspended(terminal, || self.open(index))?
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Runs `f` with the terminal restored, then takes it over again.
+fn suspended(
+    terminal: &mut DefaultTerminal,
+    f: impl FnOnce(),
+) -> Result<(), Box<dyn std::error::Error>> {
+    ratatui::restore();
+    f();
+    *terminal = ratatui::try_init()?;
+    terminal.clear()?;
+    Ok(())
+}
+
+/// Shows the repositories at `paths` until the user quits.
+pub fn run(
+    paths: &[PathBuf],
+    remote: &str,
+    options: &Options,
+    jobs: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if !std::io::stdout().is_terminal() {
+        return Err("--tui needs a terminal".into());
+    }
+    let mut app = App {
+        paths,
+        remote,
+        options,
+        jobs,
+        repos: Vec::new(),
+        state: TableState::default().with_selected(0),
+        message: String::new(),
+    };
+    app.refresh_all();
+    let mut terminal = ratatui::try_init()?;
+    let result = app.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo, options};
+    use ratatui::Terminal;
+    use ratatui::backend::TestBackend;
+    use std::fs;
+
+    #[test]
+    fn test_age() {
+        assert_eq!(age(Duration::from_secs(59)), "59s");
+        assert_eq!(age(Duration::from_secs(60)), "1m");
+        assert_eq!(age(Duration::from_secs(2 * 3600 + 59)), "2h");
+        assert_eq!(age(Duration::from_secs(3 * 86400)), "3d");
+    }
+
+    #[test]
+    fn test_table() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+        let local_dir = tmp.path().join("local");
+        for dir in [&repo_dir, &local_dir] {
+            fs::create_dir(dir).unwrap();
+            init_repo(dir, "main");
+            commit(dir, "initial");
+        }
+        git::git(
+            tmp.path(),
+            &[
+                "clone",
+                "-q",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+        let paths = [
+            clone_dir.clone(),
+            local_dir.clone(),
+            tmp.path().join("none"),
+        ];
+        let options = options();
+        let mut app = App {
+            paths: &paths,
+            remote: "origin",
+            options: &options,
+            jobs: 1,
+            repos: Vec::new(),
+            state: TableState::default().with_selected(0),
+            message: String::new(),
+        };
+        app.refresh_all();
+        let statuses = app
+            .repos
+            .iter()
+            .map(|repo| repo.status().0)
+            .collect::<Vec<_>>();
+        assert_eq!(statuses[0], "ok");
+        assert_eq!(statuses[1], "no origin/HEAD");
+        assert_eq!(app.repos[0].fetched, None);
+
+        git::git(
+            &clone_dir,
+            &["update-ref", "-d", "refs/remotes/origin/main"],
+        )
+        .unwrap();
+        app.refresh(0);
+        assert_eq!(app.repos[0].status().0, "origin/HEAD is stale");
+
+        // The remote's default branch moved, and fixing records it once it has been fetched.
+        git::git(&repo_dir, &["checkout", "-q", "-b", "trunk"]).unwrap();
+        app.fix(0);
+        assert!(
+            app.message.ends_with("which has not been fetched"),
+            "{}",
+            app.message
+        );
+        git::git(&clone_dir, &["fetch", "-q", "origin"]).unwrap();
+        app.fix(0);
+        assert!(
+            app.message.ends_with("origin/HEAD is trunk"),
+            "{}",
+            app.message
+        );
+        assert_eq!(app.repos[0].status().0, "ok");
+        assert!(app.repos[0].fetched.is_some());
+        git::git(
+            &clone_dir,
+            &["update-ref", "-d", "refs/remotes/origin/trunk"],
+        )
+        .unwrap();
+        app.refresh(0);
+
+        let mut terminal = Terminal::new(TestBackend::new(120, 6)).unwrap();
+        terminal.draw(|frame| app.draw(frame)).unwrap();
+        let screen = terminal
+            .backend()
+            .buffer()
+            .content()
+            .chunks(120)
+            .map(|line| line.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>();
+        assert!(screen[0].starts_with("PATH"), "{:?}", screen);
+        assert!(screen[1].contains("origin/HEAD is stale"), "{:?}", screen);
+        assert!(screen[2].contains("main"), "{:?}", screen);
+        assert!(screen[3].contains("never"), "{:?}", screen);
+        assert!(screen[4].starts_with("Refreshed"), "{:?}", screen);
+    }
+}
@@ -0,0 +1,121 @@
+use crate::{resolve, scan};
+use std::path::{Path, PathBuf};
+
+#[derive(clap::Args)]
+pub struct VerifyAllArgs {
+    /// Directories to search for repositories
+    #[arg(default_value = ".")]
+    roots: Vec<PathBuf>,
+
+    /// Branch names accepted as a default branch (repeatable); any name is accepted if omitted
+    #[arg(short, long = "allow", value_name = "BRANCH", value_delimiter = ',')]
+    allowed: Vec<String>,
+
+    #[command(flatten)]
+    discovery: scan::DiscoveryArgs,
+}
+
+pub fn run(args: &VerifyAllArgs, remote: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let github_actions = std::env::var_os("GITHUB_ACTIONS").is_some_and(|v| v == "true");
+    let mut count = 0;
+    for root in &args.roots {
+        for path in scan::find_repositories(root, &args.discovery) {
+            for problem in verify(&path, remote, &args.allowed) {
+                count += 1;
+                if github_actions {
+                    println!(
+                        "::error title=git-default-branch::{}: {}",
+                        path.display(),
+                        problem
+                    );
+                } else {
+                    println!("{}: {}", path.display(), problem);
+                }
+            }
+        }
+    }
+    if count > 0 {
+        return Err(format!("Found {} problem(s)", count).into());
+    }
+    Ok(())
+}
+
+/// Returns the problems with the remote HEAD of the repository at `path`, without touching the
+/// network or modifying the repository.
+pub fn verify(path: &Path, remote: &str, allowed: &[String]) -> Vec<String> {
+    let repo = match gix::discover(path) {
+        Ok(repo) => repo,
+        Err(e) => return vec![e.to_string()],
+    };
+    let branch = match resolve::remote_head_branch(&repo, remote) {
+        Ok(Some(branch)) => branch,
+        Ok(None) => return vec![format!("refs/remotes/{}/HEAD is missing", remote)],
+        Err(e) => return vec![e.to_string()],
+    };
+
+    let mut problems = Vec::new();
+    if repo
+        .find_reference(&resolve::tracking_ref(&repo, remote, &branch))
+        .is_err()
+    {
+        problems.push(format!(
+            "refs/remotes/{}/HEAD points to {}, which no longer exists",
+            remote, branch
+        ));
+    }
+    if !allowed.is_empty() && !allowed.contains(&branch) {
+        problems.push(format!(
+            "default branch {} is not one of {}",
+            branch,
+            allowed.join(", ")
+        ));
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::git::git;
+    use crate::testutil::{commit, init_repo};
+    use std::fs;
+
+    #[test]
+    fn test_verify() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "master");
+        commit(&repo_dir, "initial");
+        git(
+            tmp.path(),
+            &[
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ],
+        )
+        .unwrap();
+
+        assert!(verify(&clone_dir, "origin", &[]).is_empty());
+        assert_eq!(
+            verify(&clone_dir, "origin", &["main".to_string()]),
+            vec!["default branch master is not one of main"]
+        );
+
+        git(&repo_dir, &["branch", "-m", "master", "main"]).unwrap();
+        git(&clone_dir, &["fetch", "--prune"]).unwrap();
+        assert_eq!(
+            verify(&clone_dir, "origin", &[]),
+            vec!["refs/remotes/origin/HEAD points to master, which no longer exists"]
+        );
+
+        git(&clone_dir, &["remote", "set-head", "origin", "--delete"]).unwrap();
+        assert_eq!(
+            verify(&clone_dir, "origin", &[]),
+            vec!["refs/remotes/origin/HEAD is missing"]
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use crate::git;
+use crate::resolve;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+pub const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls the files backing `refs/remotes/<remote>/HEAD` for changes.
+pub struct Watcher {
+    head: PathBuf,
+    packed_refs: PathBuf,
+    last: Option<State>,
+}
+
+#[derive(PartialEq)]
+struct State {
+    head: Option<Vec<u8>>,
+    packed_refs: Option<(SystemTime, u64)>,
+}
+
+impl Watcher {
+    pub fn new(repo: &gix::Repository, remote: &str) -> Self {
+        let common_dir = repo.common_dir();
+        Watcher {
+            head: common_dir.join("refs/remotes").join(remote).join("HEAD"),
+            packed_refs: common_dir.join("packed-refs"),
+            last: None,
+        }
+    }
+
+    /// Returns true if the watched files differ from the previous call.
+    pub fn changed(&mut self) -> bool {
+        let state = State {
+            head: fs::read(&self.head).ok(),
+            packed_refs: fs::metadata(&self.packed_refs)
+                .ok()
+                .and_then(|m| Some((m.modified().ok()?, m.len()))),
+        };
+        if self.last.as_ref() == Some(&state) {
+            return false;
+        }
+        self.last = Some(state);
+        true
+    }
+}
+
+/// Prints the default branch, then again whenever it changes, running `exec` on each change after
+/// the first answer.
+pub fn run(
+    path: &Path,
+    remote: &str,
+    options: &resolve::Options,
+    exec: Option<&str>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let repo = git::discover(path)?;
+    let mut watcher = Watcher::new(&repo, remote);
+    let mut last_branch: Option<String> = None;
+
+    loop {
+        if watcher.changed() {
+            match resolve::run(path, remote, options) {
+                Ok(branch) if last_branch.as_ref() != Some(&branch) => {
+                    let mut stdout = io::stdout().lock();
+                    writeln!(stdout, "{}", branch)?;
+                    stdout.flush()?;
+                    if let (Some(command), Some(previous)) = (exec, &last_branch)
+                        && let Err(e) = run_command(command, git::run_dir(&repo), &branch, previous)
+                    {
+                        eprintln!("{}", e);
+                    }
+                    last_branch = Some(branch);
+                }
+                Ok(_) => {}
+                Err(e) => eprintln!("{}", e),
+            }
+            // Resolution may have refreshed the remote HEAD itself.
+            watcher.changed();
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Runs `command` through the shell in `dir`, with the new default branch in `DEFAULT_BRANCH` and
+/// the one it replaced in `PREVIOUS_DEFAULT_BRANCH`, and waits for it.
+fn run_command(
+    command: &str,
+    dir: &Path,
+    branch: &str,
+    previous: &str,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    log::debug!("running {}", command);
+    let status = shell
+        .arg(command)
+        .current_dir(dir)
+        .env("DEFAULT_BRANCH", branch)
+        .env("PREVIOUS_DEFAULT_BRANCH", previous)
+        .status()
+        .map_err(|e| format!("Cannot run {}: {}", command, e))?;
+    if !status.success() {
+        return Err(format!("{} failed: {}", command, status).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::{commit, init_repo};
+
+    #[test]
+    fn test_watcher_detects_head_change() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo_dir = tmp.path().join("repo");
+        let clone_dir = tmp.path().join("clone");
+
+        fs::create_dir(&repo_dir).unwrap();
+        init_repo(&repo_dir, "default");
+        commit(&repo_dir, "initial");
+        Command::new("git")
+            .args(["branch", "other"])
+            .current_dir(&repo_dir)
+            .output()
+            .unwrap();
+
+        Command::new("git")
+            .args([
+                "clone",
+                repo_dir.to_str().unwrap(),
+                clone_dir.to_str().unwrap(),
+            ])
+            .output()
+            .unwrap();
+
+        let mut watcher = Watcher::new(&gix::discover(&clone_dir).unwrap(), "origin");
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        Command::new("git")
+            .args(["remote", "set-head", "origin", "other"])
+            .current_dir(&clone_dir)
+            .output()
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_command() {
+        let tmp = tempfile::tempdir().unwrap();
+        let command = "echo \"$PREVIOUS_DEFAULT_BRANCH -> $DEFAULT_BRANCH\" > renamed";
+        run_command(command, tmp.path(), "main", "master").unwrap();
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("renamed")).unwrap(),
+            "master -> main\n"
+        );
+        assert!(run_command("exit 3", tmp.path(), "main", "master").is_err());
+    }
+}